@@ -0,0 +1,26 @@
+#!/bin/sh
+# Lints and tests every combination of the crate's features, so a feature
+# that only builds alongside the defaults can't slip through.
+set -eu
+
+cd "$(dirname "$0")"
+
+features="encrypt decrypt analysis masked shamir keys-tool kv"
+count=$(echo $features | wc -w)
+combinations=$((1 << count))
+
+i=0
+while [ $i -lt $combinations ]; do
+    set=""
+    bit=0
+    for feature in $features; do
+        if [ $(((i >> bit) & 1)) -eq 1 ]; then
+            set="$set${set:+,}$feature"
+        fi
+        bit=$((bit + 1))
+    done
+    echo "checking features: [${set}]"
+    cargo clippy -q --no-default-features --features "$set" --all-targets -- -D warnings
+    cargo test -q --no-default-features --features "$set" >/dev/null
+    i=$((i + 1))
+done
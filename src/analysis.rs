@@ -0,0 +1,183 @@
+//! Statistical analysis of the block function.
+//!
+//! Estimates how well the block function diffuses its input: the avalanche
+//! effect (how many output bits flip when a single input bit is flipped),
+//! round by round, and the balance of the ciphertext bits.
+//! Meant for researchers and for justifying the choice of the number of rounds,
+//! the results are estimates over random plaintexts and will differ between runs.
+//!
+//! Intermediate rounds are observed using the expanded key table of a given instance,
+//! i.e. round `i` is the state after the first `i` rounds of the full encryption.
+
+use rand::{thread_rng, RngCore};
+
+use crate::{Block, Word, RC5};
+
+pub mod differential;
+pub mod randomness;
+pub mod rotations;
+
+/// Diffusion of single-bit input differences after a given number of rounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundDiffusion {
+    /// Number of rounds, 0 stands for the initial key addition only.
+    pub round: usize,
+    /// Average fraction of output bits flipped by flipping
+    /// a single input bit, ideally 0.5.
+    pub avalanche: f64,
+    /// The smallest probability of an output bit flipping
+    /// when a given input bit is flipped, over all pairs of bits.
+    pub min_flip: f64,
+    /// The largest probability of an output bit flipping
+    /// when a given input bit is flipped, over all pairs of bits.
+    pub max_flip: f64,
+}
+
+impl RoundDiffusion {
+    /// Whether every output bit depends on every input bit,
+    /// i.e. every output bit flipped at least once for every input bit flip.
+    pub fn is_complete(&self) -> bool {
+        self.min_flip > 0.0
+    }
+}
+
+/// Estimates the diffusion after every round using a given number of random plaintexts.
+///
+/// The report has `r + 1` entries, starting with round 0.
+pub fn diffusion<W: Word>(rc5: &RC5<W>, samples: usize) -> Vec<RoundDiffusion> {
+    let (bits, rounds) = (2 * W::BITS, rc5.number_of_rounds + 1);
+    let mut rng = thread_rng();
+
+    // counts[(round * bits + input) * bits + output] is the number of times
+    // the output bit has flipped after the round, when the input bit was flipped.
+    let mut counts = vec![0u64; rounds * bits * bits];
+    let mut states = vec![(W::zero(), W::zero()); rounds];
+
+    for _ in 0..samples {
+        let (a, b) = (random_word::<W>(&mut rng), random_word::<W>(&mut rng));
+        Block::new(a, b).encode_traced(rc5, |round, a, b| states[round] = (a, b));
+
+        for input in 0..bits {
+            let (a, b) = flip_bit(a, b, input);
+            Block::new(a, b).encode_traced(rc5, |round, a, b| {
+                let (da, db) = (a ^ states[round].0, b ^ states[round].1);
+                let offset = (round * bits + input) * bits;
+                for output in 0..bits {
+                    if bit(da, db, output) {
+                        counts[offset + output] += 1;
+                    }
+                }
+            });
+        }
+    }
+
+    counts
+        .chunks(bits * bits)
+        .enumerate()
+        .map(|(round, counts)| {
+            let probabilities = counts.iter().map(|&c| c as f64 / samples.max(1) as f64);
+            RoundDiffusion {
+                round,
+                avalanche: probabilities.clone().sum::<f64>() / (bits * bits) as f64,
+                min_flip: probabilities.clone().fold(1.0, f64::min),
+                max_flip: probabilities.fold(0.0, f64::max),
+            }
+        })
+        .collect()
+}
+
+/// Estimates the avalanche effect of the full encryption, ideally 0.5.
+///
+/// See `RoundDiffusion::avalanche`.
+pub fn avalanche<W: Word>(rc5: &RC5<W>, samples: usize) -> f64 {
+    diffusion(rc5, samples)
+        .last()
+        .map(|report| report.avalanche)
+        .unwrap_or_default()
+}
+
+/// Estimates the probability of every ciphertext bit being set,
+/// ideally 0.5, using a given number of random plaintexts.
+///
+/// Bits `0..w` are the bits of the first word (`A`) of a block,
+/// bits `w..2w` are the bits of the second one (`B`).
+pub fn bit_balance<W: Word>(rc5: &RC5<W>, samples: usize) -> Vec<f64> {
+    let bits = 2 * W::BITS;
+    let mut rng = thread_rng();
+    let mut ones = vec![0u64; bits];
+
+    for _ in 0..samples {
+        let (a, b) = (random_word::<W>(&mut rng), random_word::<W>(&mut rng));
+        let ct = Block::new(a, b).encode(rc5);
+        let words = ct.to_words();
+        for (output, count) in ones.iter_mut().enumerate() {
+            if bit(words[0], words[1], output) {
+                *count += 1;
+            }
+        }
+    }
+
+    ones.into_iter()
+        .map(|count| count as f64 / samples.max(1) as f64)
+        .collect()
+}
+
+/// Generates a uniformly random word.
+fn random_word<W: Word>(rng: &mut impl RngCore) -> W {
+    W::from(rng.next_u64() >> (u64::BITS as usize - W::BITS)).expect("word fits in u64")
+}
+
+/// Flips the `i`-th bit of a block.
+fn flip_bit<W: Word>(a: W, b: W, i: usize) -> (W, W) {
+    if i < W::BITS {
+        (a ^ (W::one() << i), b)
+    } else {
+        (a, b ^ (W::one() << (i - W::BITS)))
+    }
+}
+
+/// Returns the `i`-th bit of a block.
+fn bit<W: Word>(a: W, b: W, i: usize) -> bool {
+    let (word, i) = if i < W::BITS {
+        (a, i)
+    } else {
+        (b, i - W::BITS)
+    };
+    (word >> i) & W::one() == W::one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffusion() {
+        let rc5 = RC5::<u32>::new_with_rounds(vec![0x42; 16], 12).unwrap();
+        let report = super::diffusion(&rc5, 64);
+        assert_eq!(report.len(), 13);
+
+        // The key addition alone only flips the input bit and carries.
+        assert!(!report[0].is_complete());
+        assert!(report[0].avalanche < 0.1);
+
+        let last = report.last().unwrap();
+        assert!(last.is_complete());
+        assert!((last.avalanche - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn bit_balance() {
+        let rc5 = RC5::<u16>::new(vec![0x42; 16]).unwrap();
+        let balance = super::bit_balance(&rc5, 4096);
+        assert_eq!(balance.len(), 32);
+        assert!(balance.iter().all(|p| (p - 0.5).abs() < 0.05));
+    }
+
+    #[test]
+    fn bits() {
+        let (a, b) = flip_bit(0u16, 0u16, 17);
+        assert_eq!((a, b), (0, 2));
+        assert!(bit(a, b, 17));
+        assert!(!bit(a, b, 1));
+    }
+}
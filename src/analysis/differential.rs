@@ -0,0 +1,166 @@
+//! Reduced-round differential cryptanalysis scaffolding.
+//!
+//! Encrypts pairs of plaintexts with a chosen XOR input difference
+//! and tabulates the XOR differences of the outputs after a given round.
+//! Reduced-round instances come either from `RC5::new_with_rounds` with
+//! a low number of rounds, or from observing an intermediate round
+//! of a full instance (which uses the full expanded key table).
+
+use std::collections::HashMap;
+
+use super::random_word;
+use crate::{Block, Word, RC5};
+
+/// Generates `n` random plaintext blocks.
+pub fn random_plaintexts<W: Word>(n: usize) -> impl Iterator<Item = (W, W)> {
+    let mut rng = rand::thread_rng();
+    (0..n).map(move |_| (random_word(&mut rng), random_word(&mut rng)))
+}
+
+/// Enumerates every plaintext block, only feasible for small words (`w <= 16`).
+pub fn all_plaintexts<W: Word>() -> impl Iterator<Item = (W, W)> {
+    assert!(W::BITS <= 16, "block space is too large to enumerate");
+    let words = 1u64 << W::BITS;
+    (0..words * words).map(move |i| {
+        let word = |v| W::from(v).expect("word fits in u64");
+        (word(i % words), word(i / words))
+    })
+}
+
+/// Pairs every plaintext with the one differing by `input_difference`.
+pub fn pairs<W: Word>(
+    input_difference: (W, W),
+    plaintexts: impl IntoIterator<Item = (W, W)>,
+) -> impl Iterator<Item = ((W, W), (W, W))> {
+    let (da, db) = input_difference;
+    plaintexts
+        .into_iter()
+        .map(move |(a, b)| ((a, b), (a ^ da, b ^ db)))
+}
+
+/// Distribution of output differences for a fixed input difference.
+#[derive(Debug, Clone)]
+pub struct DifferentialTable<W: Word> {
+    /// The XOR difference of the plaintexts of every pair.
+    pub input_difference: (W, W),
+    /// The round the output differences were observed after.
+    pub round: usize,
+    /// Number of tabulated pairs.
+    pub pairs: u64,
+    counts: HashMap<(u64, u64), u64>,
+}
+
+impl<W: Word> DifferentialTable<W> {
+    /// Tabulates output differences after a given round
+    /// (0 stands for the initial key addition only) of the encryption.
+    ///
+    /// Rounds beyond the number of rounds of the instance are never reached,
+    /// so the table stays empty.
+    pub fn tabulate(
+        rc5: &RC5<W>,
+        round: usize,
+        input_difference: (W, W),
+        plaintexts: impl IntoIterator<Item = (W, W)>,
+    ) -> Self {
+        let mut table = Self {
+            input_difference,
+            round,
+            pairs: 0,
+            counts: HashMap::new(),
+        };
+
+        for ((a1, b1), (a2, b2)) in pairs(input_difference, plaintexts) {
+            let (mut x, mut y) = (None, None);
+            Block::new(a1, b1).encode_traced(rc5, |i, a, b| {
+                if i == round {
+                    x = Some((a, b));
+                }
+            });
+            Block::new(a2, b2).encode_traced(rc5, |i, a, b| {
+                if i == round {
+                    y = Some((a, b));
+                }
+            });
+
+            if let (Some((a1, b1)), Some((a2, b2))) = (x, y) {
+                let difference = (to_u64(a1 ^ a2), to_u64(b1 ^ b2));
+                *table.counts.entry(difference).or_default() += 1;
+                table.pairs += 1;
+            }
+        }
+
+        table
+    }
+
+    /// Number of pairs with a given output difference.
+    pub fn count(&self, output_difference: (W, W)) -> u64 {
+        let (a, b) = output_difference;
+        self.counts
+            .get(&(to_u64(a), to_u64(b)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Estimated probability of a given output difference.
+    pub fn probability(&self, output_difference: (W, W)) -> f64 {
+        self.count(output_difference) as f64 / self.pairs.max(1) as f64
+    }
+
+    /// Number of distinct output differences.
+    pub fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// The `n` most frequent output differences with their counts,
+    /// most frequent first.
+    pub fn top(&self, n: usize) -> Vec<((W, W), u64)> {
+        let mut counts = self
+            .counts
+            .iter()
+            .map(|(&(a, b), &count)| ((from_u64(a), from_u64(b)), count))
+            .collect::<Vec<_>>();
+        counts.sort_by_key(|&((a, b), count)| (std::cmp::Reverse(count), to_u64(a), to_u64(b)));
+        counts.truncate(n);
+        counts
+    }
+}
+
+fn to_u64<W: Word>(w: W) -> u64 {
+    w.to_u64().expect("word fits in u64")
+}
+
+fn from_u64<W: Word>(v: u64) -> W {
+    W::from(v).expect("word fits in u64")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_difference() {
+        let rc5 = RC5::<u16>::new_with_rounds(vec![0x42; 16], 2).unwrap();
+        let table = DifferentialTable::tabulate(&rc5, 2, (0, 0), random_plaintexts(100));
+        assert_eq!(table.pairs, 100);
+        assert_eq!(table.distinct(), 1);
+        assert_eq!(table.probability((0, 0)), 1.0);
+    }
+
+    #[test]
+    fn reduced_rounds() {
+        let rc5 = RC5::<u8>::new_with_rounds(vec![0x42; 16], 8).unwrap();
+
+        // Flipping the top bit of both words survives the key addition
+        // unchanged, since no carry goes beyond the top bit.
+        let table = DifferentialTable::tabulate(&rc5, 0, (0x80, 0x80), all_plaintexts());
+        assert_eq!(table.pairs, 1 << 16);
+        assert_eq!(table.top(1), vec![((0x80, 0x80), 1 << 16)]);
+
+        // The difference spreads over many output differences in a few rounds.
+        let table = DifferentialTable::tabulate(&rc5, 4, (0x80, 0x80), all_plaintexts());
+        assert!(table.distinct() > 1000);
+
+        let table = DifferentialTable::tabulate(&rc5, 9, (0x80, 0x80), all_plaintexts());
+        assert_eq!(table.pairs, 0);
+    }
+}
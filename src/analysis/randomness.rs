@@ -0,0 +1,147 @@
+//! Keystream export for statistical test suites.
+//!
+//! Writes the counter mode keystream (see `Keystream`) of chosen parameters
+//! in the input formats of the standard randomness batteries:
+//!
+//! * [NIST STS](https://csrc.nist.gov/projects/random-bit-generation/documentation-and-software)
+//!   reads raw bytes (`Format::Raw`, "binary" input mode)
+//!   or ASCII `0`/`1` characters (`Format::Ascii`).
+//! * [Dieharder](https://webhome.phy.duke.edu/~rgb/General/dieharder.php)
+//!   reads raw bytes (`Format::Raw`, generator 201)
+//!   or its ASCII file format of 32-bit integers (`Format::Dieharder`, generator 202).
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+    io::{self, Write},
+};
+
+use crate::{Error, FromLeBytes, Keystream, ToLeBytes, Word, RC5};
+
+/// Output format of the keystream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Raw bytes.
+    Raw,
+    /// A `0` or `1` character per bit, most significant bit of a byte first.
+    Ascii,
+    /// The Dieharder file format: a header followed by an unsigned
+    /// 32-bit integer per line, read from little-endian keystream words.
+    Dieharder,
+}
+
+/// Writes `len` bytes of the keystream of a given nonce in a given format.
+///
+/// `len` is rounded down to a multiple of 4 bytes for `Format::Dieharder`.
+/// Fails without writing anything if the keystream is shorter than `len` bytes.
+pub fn export_keystream<W: Word>(
+    rc5: &RC5<W>,
+    nonce: W,
+    len: u64,
+    format: Format,
+    mut out: impl Write,
+) -> io::Result<()>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const CHUNK_SIZE: usize = 4096;
+
+    let len = match format {
+        Format::Dieharder => len / 4 * 4,
+        _ => len,
+    };
+    let mut keystream = Keystream::new(rc5, nonce);
+    // Fail before writing anything.
+    if u128::from(len) > keystream.remaining() {
+        return Err(Error::CounterExhausted.into());
+    }
+
+    if format == Format::Dieharder {
+        writeln!(out, "#{:=<66}", "")?;
+        writeln!(
+            out,
+            "# generator RC5-{}/{} CTR, nonce {}",
+            W::BITS,
+            rc5.number_of_rounds,
+            nonce.to_u64().unwrap()
+        )?;
+        writeln!(out, "#{:=<66}", "")?;
+        writeln!(out, "type: d")?;
+        writeln!(out, "count: {}", len / 4)?;
+        writeln!(out, "numbit: 32")?;
+    }
+
+    let (mut chunk, mut text) = ([0; CHUNK_SIZE], Vec::new());
+    let mut left = len;
+    while left > 0 {
+        let chunk = &mut chunk[..left.min(CHUNK_SIZE as u64) as usize];
+        chunk.fill(0);
+        keystream.apply(chunk)?;
+        left -= chunk.len() as u64;
+
+        text.clear();
+        match format {
+            Format::Raw => out.write_all(chunk)?,
+            Format::Ascii => {
+                for byte in chunk.iter() {
+                    text.extend((0..8).rev().map(|i| b'0' + (byte >> i & 1)));
+                }
+                out.write_all(&text)?;
+            }
+            Format::Dieharder => {
+                for word in chunk.chunks_exact(4) {
+                    let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                    writeln!(text, "{}", word)?;
+                }
+                out.write_all(&text)?;
+            }
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn export_keystream() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let mut keystream = vec![0; 5000];
+        Keystream::new(&rc5, 9).read_exact(&mut keystream).unwrap();
+
+        let mut raw = Vec::new();
+        super::export_keystream(&rc5, 9, 5000, Format::Raw, &mut raw).unwrap();
+        assert_eq!(raw, keystream);
+
+        let mut ascii = Vec::new();
+        super::export_keystream(&rc5, 9, 2, Format::Ascii, &mut ascii).unwrap();
+        assert_eq!(
+            ascii,
+            format!("{:08b}{:08b}", keystream[0], keystream[1]).into_bytes()
+        );
+
+        let mut dieharder = Vec::new();
+        super::export_keystream(&rc5, 9, 4099, Format::Dieharder, &mut dieharder).unwrap();
+        let dieharder = String::from_utf8(dieharder).unwrap();
+        let lines = dieharder.lines().collect::<Vec<_>>();
+        assert_eq!(&lines[3..6], ["type: d", "count: 1024", "numbit: 32"]);
+        assert_eq!(lines.len(), 6 + 1024);
+        let first = u32::from_le_bytes(keystream[..4].try_into().unwrap());
+        assert_eq!(lines[6], first.to_string());
+        let last = u32::from_le_bytes(keystream[4092..4096].try_into().unwrap());
+        assert_eq!(lines[6 + 1023], last.to_string());
+    }
+
+    #[test]
+    fn exhausted() {
+        let rc5 = RC5::<u8>::new(vec![0x42; 16]).unwrap();
+        assert!(super::export_keystream(&rc5, 0, 512, Format::Raw, io::sink()).is_ok());
+        let mut out = Vec::new();
+        assert!(super::export_keystream(&rc5, 0, 513, Format::Raw, &mut out).is_err());
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+//! Data-dependent rotation metrics.
+//!
+//! Known attacks on RC5 follow trails where the data-dependent rotation
+//! amounts are zero (or otherwise predictable) for several rounds.
+//! This module records the rotation amounts of every half-round,
+//! so that users tuning parameters can see how often such rotations occur.
+
+use crate::{Block, Word, RC5};
+
+/// Returns the rotation amounts of every half-round when encrypting a block.
+///
+/// Amounts come in pairs per round: the rotation of `A` (by `B`)
+/// followed by the rotation of `B` (by the new `A`), `2r` amounts in total.
+/// With `RotationSchedule::Constant` they are the fixed amounts of the schedule.
+pub fn rotation_amounts<W: Word>(rc5: &RC5<W>, a: W, b: W) -> Vec<u32> {
+    let schedule = rc5.rotation_schedule;
+    let mut amounts = Vec::with_capacity(2 * rc5.number_of_rounds);
+    let mut previous_b = W::zero();
+
+    Block::new(a, b).encode_traced(rc5, |round, a, b| {
+        if round > 0 {
+            amounts.push(amount(schedule.amount(2 * round, previous_b)));
+            amounts.push(amount(schedule.amount(2 * round + 1, a)));
+        }
+        previous_b = b;
+    });
+
+    amounts
+}
+
+/// Rotation statistics over a set of plaintexts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RotationStats {
+    /// Number of half-round rotations observed.
+    pub rotations: u64,
+    /// Number of rotations by zero bits.
+    pub zero: u64,
+    /// Number of rotations by the same amount as the same half
+    /// of the previous round.
+    pub repeated: u64,
+    /// Number of rotations by zero bits in every round (starting with round 1).
+    pub zero_per_round: Vec<u64>,
+    /// Number of rotations by every amount from 0 to `w - 1`.
+    pub histogram: Vec<u64>,
+}
+
+impl RotationStats {
+    /// Collects rotation statistics of encrypting given plaintexts.
+    pub fn collect<W: Word>(rc5: &RC5<W>, plaintexts: impl IntoIterator<Item = (W, W)>) -> Self {
+        let mut stats = Self {
+            zero_per_round: vec![0; rc5.number_of_rounds],
+            histogram: vec![0; W::BITS],
+            ..Self::default()
+        };
+
+        for (a, b) in plaintexts {
+            let amounts = rotation_amounts(rc5, a, b);
+            for (i, &amount) in amounts.iter().enumerate() {
+                stats.rotations += 1;
+                stats.histogram[amount as usize] += 1;
+                if amount == 0 {
+                    stats.zero += 1;
+                    stats.zero_per_round[i / 2] += 1;
+                }
+                if i >= 2 && amounts[i - 2] == amount {
+                    stats.repeated += 1;
+                }
+            }
+        }
+
+        stats
+    }
+}
+
+/// Rotation amount by a given word, i.e. its `lg(w)` low-order bits.
+fn amount<W: Word>(w: W) -> u32 {
+    (w.to_u64().expect("word fits in u64") % W::BITS as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::differential::all_plaintexts, RotationSchedule};
+
+    #[test]
+    fn rotation_amounts() {
+        let rc5 = RC5::<u16>::new_with_rounds(vec![0x42; 16], 3).unwrap();
+        let amounts = super::rotation_amounts(&rc5, 0x1234, 0x5678);
+        assert_eq!(amounts.len(), 6);
+        assert!(amounts.iter().all(|&a| a < 16));
+    }
+
+    #[test]
+    fn constant_rotation_amounts() {
+        let rc5 = RC5::<u16>::new_with_rounds(vec![0x42; 16], 3)
+            .unwrap()
+            .with_rotation_schedule(RotationSchedule::Constant);
+        // Half-rounds 2 to 7 of the constant schedule, whatever the plain text.
+        for (a, b) in [(0x1234, 0x5678), (0, 0)] {
+            assert_eq!(
+                super::rotation_amounts(&rc5, a, b),
+                vec![7, 9, 5, 15, 1, 11]
+            );
+        }
+    }
+
+    #[test]
+    fn collect() {
+        let rc5 = RC5::<u8>::new_with_rounds(vec![0x42; 16], 4).unwrap();
+        let stats = RotationStats::collect(&rc5, all_plaintexts());
+        assert_eq!(stats.rotations, 8 << 16);
+        assert_eq!(stats.histogram.iter().sum::<u64>(), stats.rotations);
+        assert_eq!(stats.zero_per_round.iter().sum::<u64>(), stats.zero);
+        // Roughly one in eight rotations of an 8-bit word is by zero bits.
+        assert!((stats.zero as f64 / stats.rotations as f64 - 0.125).abs() < 0.01);
+    }
+}
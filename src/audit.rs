@@ -0,0 +1,185 @@
+//! Key-usage audit hooks.
+//!
+//! Regulated deployments often have to keep audit trails of which keys touched
+//! which data. An audit hook attached to an RC5 instance is invoked on every
+//! encryption and decryption with the key ID, the operation and the number of
+//! bytes processed. It never sees the plaintext, the ciphertext or the key material.
+//!
+//! The modes of operation, `RecordCipher` and the `iv_prefix` helpers
+//! record a single event per call, for the length of the message without
+//! its IV, nonce or tag.
+
+use std::sync::Arc;
+
+/// An audited operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Encrypt,
+    Decrypt,
+}
+
+/// An audit trail record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditEvent<'a> {
+    /// ID of the key used for the operation.
+    pub key_id: &'a str,
+    /// Performed operation.
+    pub operation: Operation,
+    /// Number of bytes processed.
+    pub bytes: usize,
+}
+
+/// A callback interface receiving audit trail records.
+pub trait AuditHook: Send + Sync {
+    /// Records a single audit trail event.
+    fn record(&self, event: &AuditEvent);
+}
+
+impl<F> AuditHook for F
+where
+    F: Fn(&AuditEvent) + Send + Sync,
+{
+    fn record(&self, event: &AuditEvent) {
+        self(event)
+    }
+}
+
+/// An audit hook attached to an RC5 instance along with the ID of its key.
+#[derive(Clone)]
+#[cfg_attr(not(any(feature = "encrypt", feature = "decrypt")), allow(dead_code))]
+pub(crate) struct Auditor {
+    pub(crate) key_id: String,
+    pub(crate) hook: Arc<dyn AuditHook>,
+}
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+impl Auditor {
+    pub(crate) fn record(&self, operation: Operation, bytes: usize) {
+        self.hook.record(&AuditEvent {
+            key_id: &self.key_id,
+            operation,
+            bytes,
+        });
+    }
+}
+
+#[cfg(all(test, feature = "encrypt", feature = "decrypt"))]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        iv_prefix,
+        modes::{Ccm, Cfb, Cfb8, Ctr, Cts, Ocb, Pcbc, Rc5CbcPad, Xts},
+        RecordCipher, SecretKey, RC5,
+    };
+
+    #[test]
+    fn audit_hook() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let trail = Arc::clone(&events);
+        let hook = move |event: &AuditEvent| {
+            trail
+                .lock()
+                .unwrap()
+                .push((event.key_id.to_string(), event.operation, event.bytes));
+        };
+
+        let rc5 = RC5::<u32>::new(vec![1; 16])
+            .unwrap()
+            .with_audit_hook("key-1", Arc::new(hook));
+        let ct = rc5.encode([0; 16]);
+        rc5.decode(&ct[..8]);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ("key-1".to_string(), Operation::Encrypt, 16),
+                ("key-1".to_string(), Operation::Decrypt, 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn modes() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let trail = Arc::clone(&events);
+        let hook = move |event: &AuditEvent| {
+            trail.lock().unwrap().push((event.operation, event.bytes));
+        };
+
+        let rc5 = RC5::<u64>::new(vec![1; 16])
+            .unwrap()
+            .with_audit_hook("key-1", Arc::new(hook));
+        let iv = [2; 16];
+        let mut data = [0; 20];
+
+        let mut ctr = Ctr::new(rc5.clone(), 3);
+        ctr.encrypt(&mut data).unwrap();
+        ctr.decrypt(&mut data[..5]).unwrap();
+        let mut cfb = Cfb::new(rc5.clone(), &iv).unwrap();
+        cfb.encrypt(&mut data[..3]);
+        let mut cfb8 = Cfb8::new(rc5.clone(), &iv).unwrap();
+        cfb8.decrypt(&mut data[..3]);
+        let mut pcbc = Pcbc::new(rc5.clone(), &iv).unwrap();
+        pcbc.encrypt(&mut data[..16]).unwrap();
+        let xts = Xts::new(rc5.clone(), rc5.clone());
+        xts.decrypt_sector(0, &mut data).unwrap();
+        let cts = Cts::new(rc5.clone(), &iv).unwrap();
+        cts.encrypt(&mut data[..17]).unwrap();
+        cts.decrypt(&mut data[..16]).unwrap();
+        let ct = Rc5CbcPad::new(rc5.clone(), &iv).unwrap().encrypt([0; 7]);
+        Rc5CbcPad::new(rc5.clone(), &iv)
+            .unwrap()
+            .decrypt(ct)
+            .unwrap();
+        let ct = Ccm::new(rc5.clone(), 8)
+            .unwrap()
+            .encrypt(&[0; 13], [], [0; 9]);
+        Ccm::new(rc5.clone(), 8)
+            .unwrap()
+            .decrypt(&[0; 13], [], ct.unwrap())
+            .unwrap();
+        let ct = Ocb::new(rc5.clone(), 8)
+            .unwrap()
+            .encrypt(&[0; 12], [], [0; 10]);
+        Ocb::new(rc5.clone(), 8)
+            .unwrap()
+            .decrypt(&[0; 12], [], ct.unwrap())
+            .unwrap();
+        let records = RecordCipher::new(&rc5, &SecretKey::random(16), 16).unwrap();
+        records.encrypt_record(0, &mut data[..16]).unwrap();
+        records.decrypt_record(0, &mut data[..16]).unwrap();
+        let ct = iv_prefix::encrypt_cbc(&rc5, [0; 4]);
+        iv_prefix::decrypt_cbc(&rc5, ct).unwrap();
+        let ct = iv_prefix::encrypt_ctr(&rc5, [0; 6]).unwrap();
+        iv_prefix::decrypt_ctr(&rc5, ct).unwrap();
+
+        use Operation::{Decrypt, Encrypt};
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (Encrypt, 20),
+                (Decrypt, 5),
+                (Encrypt, 3),
+                (Decrypt, 3),
+                (Encrypt, 16),
+                (Decrypt, 20),
+                (Encrypt, 17),
+                (Decrypt, 16),
+                (Encrypt, 7),
+                (Decrypt, 16),
+                (Encrypt, 9),
+                (Decrypt, 9),
+                (Encrypt, 10),
+                (Decrypt, 10),
+                (Encrypt, 16),
+                (Decrypt, 16),
+                (Encrypt, 4),
+                (Decrypt, 16),
+                (Encrypt, 6),
+                (Decrypt, 6),
+            ]
+        );
+    }
+}
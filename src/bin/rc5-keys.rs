@@ -0,0 +1,309 @@
+//! Key generation and inspection tool.
+//!
+//! ```text
+//! rc5-keys generate [--length B] [--to ENC] [--out FILE]
+//! rc5-keys pack [-w W] [-r R] [--from ENC] [--to ENC] [--in FILE] [--out FILE]
+//! rc5-keys inspect [--from ENC] [--in FILE]
+//! rc5-keys convert --from ENC --to ENC [--in FILE] [--out FILE]
+//! ```
+//!
+//! * `generate` - Generates a random secret key of `B` bytes (16 by default).
+//! * `pack` - Packs a secret key into a control block with a given
+//!   word size and number of rounds (nominal RC5-32/16 by default).
+//! * `inspect` - Prints the RC5-w/r/b designation, the version and
+//!   the key check value of a control block.
+//! * `convert` - Converts between encodings.
+//!
+//! Encodings (`ENC`) are `hex`, `base64` and `raw` (the default).
+//! Input is read from the standard input and output is written to the
+//! standard output, unless `--in` or `--out` are given.
+
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    process,
+};
+
+use rc5_test::{ControlBlock, KeyLength, SecretKey};
+use zeroize::Zeroizing;
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    Hex,
+    Base64,
+    Raw,
+}
+
+impl Encoding {
+    const HEX_DIGITS: &'static [u8; 16] = b"0123456789abcdef";
+    const BASE64_ALPHABET: &'static [u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "hex" => Ok(Self::Hex),
+            "base64" => Ok(Self::Base64),
+            "raw" => Ok(Self::Raw),
+            _ => Err(format!("unknown encoding: `{}`", name)),
+        }
+    }
+
+    /// Encodes key material into a buffer that is wiped when dropped.
+    fn encode(&self, bytes: &[u8]) -> Zeroizing<Vec<u8>> {
+        match self {
+            Self::Hex => {
+                let mut hex = Zeroizing::new(Vec::with_capacity(2 * bytes.len() + 1));
+                for b in bytes {
+                    hex.push(Self::HEX_DIGITS[(b >> 4) as usize]);
+                    hex.push(Self::HEX_DIGITS[(b & 0xf) as usize]);
+                }
+                hex.push(b'\n');
+                hex
+            }
+            Self::Base64 => {
+                let mut base64 =
+                    Zeroizing::new(Vec::with_capacity(bytes.len().div_ceil(3) * 4 + 1));
+                for chunk in bytes.chunks(3) {
+                    let n = chunk
+                        .iter()
+                        .enumerate()
+                        .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+                    for i in 0..4 {
+                        if i <= chunk.len() {
+                            let sextet = (n >> (18 - 6 * i)) & 0x3f;
+                            base64.push(Self::BASE64_ALPHABET[sextet as usize]);
+                        } else {
+                            base64.push(b'=');
+                        }
+                    }
+                }
+                base64.push(b'\n');
+                base64
+            }
+            Self::Raw => Zeroizing::new(bytes.to_vec()),
+        }
+    }
+
+    /// Decodes key material into a buffer that is wiped when dropped.
+    fn decode(&self, text: &[u8]) -> Result<Zeroizing<Vec<u8>>, String> {
+        let trimmed = || {
+            Zeroizing::new(
+                text.iter()
+                    .copied()
+                    .filter(|c| !c.is_ascii_whitespace())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        match self {
+            Self::Hex => {
+                let digits = trimmed();
+                if digits.len() % 2 != 0 {
+                    return Err("odd number of hex digits".to_string());
+                }
+                // `from_str_radix` would also accept a sign.
+                if !digits.iter().all(u8::is_ascii_hexdigit) {
+                    return Err("invalid hex digit".to_string());
+                }
+                let mut bytes = Zeroizing::new(Vec::with_capacity(digits.len() / 2));
+                for pair in digits.chunks(2) {
+                    let pair = std::str::from_utf8(pair).expect("ASCII hex digits");
+                    bytes.push(u8::from_str_radix(pair, 16).expect("hex digits"));
+                }
+                Ok(bytes)
+            }
+            Self::Base64 => {
+                let chars = trimmed();
+                if chars.len() % 4 != 0 {
+                    return Err("invalid base64 length".to_string());
+                }
+                let mut bytes = Zeroizing::new(Vec::with_capacity(chars.len() / 4 * 3));
+                let quads = chars.len() / 4;
+                for (q, chunk) in chars.chunks(4).enumerate() {
+                    let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+                    // Only the last quad is padded, and it holds at least one byte.
+                    if padding > 2 || (padding > 0 && q + 1 != quads) {
+                        return Err("invalid base64 padding".to_string());
+                    }
+                    let mut n = 0u32;
+                    for (i, c) in chunk[..4 - padding].iter().enumerate() {
+                        let sextet = Self::BASE64_ALPHABET
+                            .iter()
+                            .position(|a| a == c)
+                            .ok_or_else(|| "invalid base64 character".to_string())?;
+                        n |= (sextet as u32) << (18 - 6 * i);
+                    }
+                    bytes.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+                }
+                Ok(bytes)
+            }
+            Self::Raw => Ok(Zeroizing::new(text.to_vec())),
+        }
+    }
+}
+
+/// Parsed command line options.
+struct Options {
+    length: usize,
+    w: u8,
+    r: u8,
+    from: Encoding,
+    to: Encoding,
+    input: Option<String>,
+    output: Option<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self {
+            length: 16,
+            w: 32,
+            r: 16,
+            from: Encoding::Raw,
+            to: Encoding::Raw,
+            input: None,
+            output: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value of `{}`", flag))?;
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid value of `{}`: `{}`", flag, value))
+            };
+            let byte = || {
+                value
+                    .parse::<u8>()
+                    .map_err(|_| format!("invalid value of `{}`: `{}`", flag, value))
+            };
+
+            match flag.as_str() {
+                "--length" => options.length = number()?,
+                "-w" => options.w = byte()?,
+                "-r" => options.r = byte()?,
+                "--from" => options.from = Encoding::parse(value)?,
+                "--to" => options.to = Encoding::parse(value)?,
+                "--in" => options.input = Some(value.clone()),
+                "--out" => options.output = Some(value.clone()),
+                _ => return Err(format!("unknown option: `{}`", flag)),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn read(&self) -> Result<Zeroizing<Vec<u8>>, String> {
+        let bytes = match &self.input {
+            Some(path) => Zeroizing::new(fs::read(path).map_err(|e| e.to_string())?),
+            None => {
+                let mut bytes = Zeroizing::new(Vec::new());
+                io::stdin()
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| e.to_string())?;
+                bytes
+            }
+        };
+        self.from.decode(&bytes)
+    }
+
+    fn write(&self, bytes: &[u8]) -> Result<(), String> {
+        let encoded = self.to.encode(bytes);
+        match &self.output {
+            Some(path) => fs::write(path, &*encoded),
+            None => io::stdout().write_all(&encoded),
+        }
+        .map_err(|e| e.to_string())
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let (command, options) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), Options::parse(rest)?),
+        None => return Err("missing command".to_string()),
+    };
+
+    match command {
+        "generate" => {
+            KeyLength::new(options.length).map_err(|e| e.to_string())?;
+            let key = SecretKey::random(options.length);
+            key.expose_with(|bytes| options.write(bytes))
+        }
+        "pack" => {
+            let key = options.read()?;
+            let cb = ControlBlock {
+                w: options.w,
+                r: options.r,
+                ..ControlBlock::nominal(key.to_vec()).map_err(|e| e.to_string())?
+            };
+            cb.key_check_value().map_err(|e| e.to_string())?;
+            options.write(&Zeroizing::new(cb.to_bytes()))
+        }
+        "inspect" => {
+            let cb = ControlBlock::from_bytes(&options.read()?).map_err(|e| e.to_string())?;
+            let kcv = cb.key_check_value().map_err(|e| e.to_string())?;
+            println!("designation: {}", cb);
+            println!("version: {:#04x}", cb.v);
+            println!(
+                "key check value: {}",
+                String::from_utf8_lossy(&Encoding::Hex.encode(&kcv)).trim()
+            );
+            Ok(())
+        }
+        "convert" => options.write(&options.read()?),
+        _ => Err(format!("unknown command: `{}`", command)),
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if let Err(e) = run(&args) {
+        eprintln!("rc5-keys: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        for encoding in [Encoding::Hex, Encoding::Base64, Encoding::Raw] {
+            for len in [0, 1, 2, 3, 4, 5, 16, 256] {
+                let encoded = encoding.encode(&bytes[..len]);
+                assert_eq!(*encoding.decode(&encoded).unwrap(), &bytes[..len]);
+            }
+        }
+
+        assert_eq!(*Encoding::Hex.encode(&[0x01, 0xab]), b"01ab\n");
+        assert_eq!(*Encoding::Hex.decode(b"01AB").unwrap(), [0x01, 0xab]);
+        assert_eq!(*Encoding::Base64.encode(b"a"), b"YQ==\n");
+        assert_eq!(*Encoding::Base64.encode(b"ab"), b"YWI=\n");
+        assert_eq!(*Encoding::Base64.decode(b"YW Jj\n").unwrap(), b"abc");
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(Encoding::Hex.decode(b"abc").is_err());
+        assert!(Encoding::Hex.decode(b"zz").is_err());
+        assert!(Encoding::Hex.decode(b"+f").is_err());
+        assert!(Encoding::Hex.decode(b"-1").is_err());
+
+        for text in [
+            &b"===="[..],
+            b"A===",
+            b"YQ==YWJj",
+            b"YQ=a",
+            b"Y=Q=",
+            b"YWJ",
+            b"YW!j",
+        ] {
+            assert!(Encoding::Base64.decode(text).is_err(), "{:?}", text);
+        }
+    }
+}
@@ -1,7 +1,16 @@
-use crate::{ExpandedKeyTable, Word, RC5};
+use zeroize::Zeroizing;
+
+use crate::Word;
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+use crate::{
+    compat_profile::{ByteOrder, WordOrder},
+    FromLeBytes, KeyTableStorage, RC5,
+};
 
 // Block is a pair of words.
-pub struct Block<W>(W, W);
+//
+// Blocks hold plaintext, so they are securely zeroed when dropped.
+pub struct Block<W: Word>(W, W);
 
 impl<W: Word> Block<W> {
     /// Creates a new block from words `a` and `b`.
@@ -14,17 +23,78 @@ impl<W: Word> Block<W> {
         Self::new(ws[0], ws[1])
     }
 
+    /// Reads a block from `2 * W::BYTES` bytes, packing bytes into words in a given byte order.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn read(bytes: &[u8], byte_order: ByteOrder) -> Self {
+        let (a, b) = bytes.split_at(W::BYTES);
+        Self(read_word(a, byte_order), read_word(b, byte_order))
+    }
+
+    /// Writes the block into `2 * W::BYTES` bytes, unpacking words in a given byte order.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn write(&self, bytes: &mut [u8], byte_order: ByteOrder) {
+        let (a, b) = bytes.split_at_mut(W::BYTES);
+        write_word(self.0, a, byte_order);
+        write_word(self.1, b, byte_order);
+    }
+
+    /// Reads a block like `Block::read`, swapping the words for `WordOrder::BA`.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn read_ordered(bytes: &[u8], byte_order: ByteOrder, word_order: WordOrder) -> Self {
+        let Self(a, b) = Self::read(bytes, byte_order);
+        match word_order {
+            WordOrder::AB => Self(a, b),
+            WordOrder::BA => Self(b, a),
+        }
+    }
+
+    /// Writes a block like `Block::write`, swapping the words for `WordOrder::BA`.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn write_ordered(
+        &self,
+        bytes: &mut [u8],
+        byte_order: ByteOrder,
+        word_order: WordOrder,
+    ) {
+        match word_order {
+            WordOrder::AB => self.write(bytes, byte_order),
+            WordOrder::BA => Self(self.1, self.0).write(bytes, byte_order),
+        }
+    }
+
+    /// Returns the words `A` and `B` of the block.
+    #[cfg(any(feature = "encrypt", all(feature = "masked", feature = "decrypt")))]
+    pub(crate) fn words(&self) -> (W, W) {
+        (self.0, self.1)
+    }
+
     /// Converts a block into a vector of words.
-    pub fn to_words(&self) -> Vec<W> {
-        vec![self.0, self.1]
+    pub fn to_words(&self) -> Zeroizing<Vec<W>> {
+        Zeroizing::new(vec![self.0, self.1])
     }
 
     /// Encrypts the block.
-    pub fn encode(&self, ctx: &RC5<W>) -> Self {
+    #[cfg(feature = "encrypt")]
+    pub fn encode<S: KeyTableStorage<W>>(&self, ctx: &RC5<W, S>) -> Self {
+        self.encode_traced(ctx, |_, _, _| {})
+    }
+
+    /// Encrypts the block, calling `trace` with the round number and
+    /// the registers `A` and `B` after the initial key addition (round 0)
+    /// and after every round.
+    #[cfg(feature = "encrypt")]
+    pub(crate) fn encode_traced<S: KeyTableStorage<W>>(
+        &self,
+        ctx: &RC5<W, S>,
+        mut trace: impl FnMut(usize, W, W),
+    ) -> Self {
         let RC5 {
-            expanded_key_table: ExpandedKeyTable(key_table),
+            expanded_key_table,
             number_of_rounds: r,
+            rotation_schedule: rotations,
+            ..
         } = ctx;
+        let key_table = expanded_key_table.words();
 
         // Pseudo-code:
         //
@@ -47,26 +117,32 @@ impl<W: Word> Block<W> {
 
         a = a.wrapping_add(&key_table[0]);
         b = b.wrapping_add(&key_table[1]);
+        trace(0, a, b);
         for i in 1..=*r {
             a = a
                 .bitxor(b)
-                .rotate_left_by(b)
+                .rotate_left_by(rotations.amount(2 * i, b))
                 .wrapping_add(&key_table[2 * i]);
             b = b
                 .bitxor(a)
-                .rotate_left_by(a)
+                .rotate_left_by(rotations.amount(2 * i + 1, a))
                 .wrapping_add(&key_table[2 * i + 1]);
+            trace(i, a, b);
         }
 
         Block(a, b)
     }
 
     /// Decrypts the block.
-    pub fn decode(&self, ctx: &RC5<W>) -> Self {
+    #[cfg(feature = "decrypt")]
+    pub fn decode<S: KeyTableStorage<W>>(&self, ctx: &RC5<W, S>) -> Self {
         let RC5 {
-            expanded_key_table: ExpandedKeyTable(key_table),
+            expanded_key_table,
             number_of_rounds: r,
+            rotation_schedule: rotations,
+            ..
         } = ctx;
+        let key_table = expanded_key_table.words();
 
         // Pseudo-code:
         //
@@ -81,11 +157,11 @@ impl<W: Word> Block<W> {
         for i in (1..=*r).rev() {
             b = b
                 .wrapping_sub(&key_table[2 * i + 1])
-                .rotate_right_by(a)
+                .rotate_right_by(rotations.amount(2 * i + 1, a))
                 .bitxor(a);
             a = a
                 .wrapping_sub(&key_table[2 * i])
-                .rotate_right_by(b)
+                .rotate_right_by(rotations.amount(2 * i, b))
                 .bitxor(b);
         }
         b = b.wrapping_sub(&key_table[1]);
@@ -94,3 +170,28 @@ impl<W: Word> Block<W> {
         Block(a, b)
     }
 }
+
+impl<W: Word> Drop for Block<W> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+        self.1.zeroize();
+    }
+}
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+fn read_word<W: Word>(bytes: &[u8], byte_order: ByteOrder) -> W {
+    let mut le_bytes = <W as FromLeBytes>::T::default();
+    le_bytes.as_mut().copy_from_slice(bytes);
+    if byte_order == ByteOrder::BigEndian {
+        le_bytes.as_mut().reverse();
+    }
+    W::from_le_bytes(le_bytes)
+}
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+fn write_word<W: Word>(w: W, bytes: &mut [u8], byte_order: ByteOrder) {
+    bytes.copy_from_slice(w.to_le_bytes().as_ref());
+    if byte_order == ByteOrder::BigEndian {
+        bytes.reverse();
+    }
+}
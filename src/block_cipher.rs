@@ -0,0 +1,39 @@
+//! Internal block cipher abstraction.
+//!
+//! Modes of operation are written generically over the `BlockCipher` trait,
+//! so that their code isn't duplicated for every primitive and word size.
+
+/// The largest block size of the supported words (RC5-64),
+/// for block buffers on the stack.
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub(crate) const MAX_BLOCK_SIZE: usize = 16;
+
+/// A keyed block cipher working on blocks of `BLOCK_SIZE` bytes.
+pub(crate) trait BlockCipher {
+    /// Size of a block in bytes.
+    const BLOCK_SIZE: usize;
+
+    /// Encrypts a single block in place.
+    #[cfg(feature = "encrypt")]
+    fn encrypt_block(&self, block: &mut [u8]);
+
+    /// Decrypts a single block in place.
+    #[cfg(feature = "decrypt")]
+    fn decrypt_block(&self, block: &mut [u8]);
+}
+
+/// Encrypts every block of a block-aligned buffer in place.
+#[cfg(feature = "encrypt")]
+pub(crate) fn encrypt_blocks<C: BlockCipher>(cipher: &C, data: &mut [u8]) {
+    for block in data.chunks_mut(C::BLOCK_SIZE) {
+        cipher.encrypt_block(block);
+    }
+}
+
+/// Decrypts every block of a block-aligned buffer in place.
+#[cfg(feature = "decrypt")]
+pub(crate) fn decrypt_blocks<C: BlockCipher>(cipher: &C, data: &mut [u8]) {
+    for block in data.chunks_mut(C::BLOCK_SIZE) {
+        cipher.decrypt_block(block);
+    }
+}
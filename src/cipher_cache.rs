@@ -0,0 +1,179 @@
+//! Bounded LRU cache of prepared RC5 instances.
+//!
+//! Key expansion is the most expensive part of setting up an RC5 instance.
+//! Servers decrypting under thousands of keys can keep the most recently
+//! used instances around instead of re-expanding the key for every message.
+//!
+//! Entries are keyed by a fingerprint of the secret key, so the cache never
+//! stores keys themselves. Fingerprints are 128-bit keyed SipHash digests
+//! with a random per-cache key, so they can't be precomputed or collided
+//! on purpose by someone who doesn't know it.
+
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, HashMap},
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+    hash::BuildHasher,
+    sync::Arc,
+};
+
+use crate::{Error, FromLeBytes, ToLeBytes, Word, RC5};
+
+/// A fingerprint of a secret key.
+type Fingerprint = u128;
+
+/// A cached RC5 instance.
+struct Entry<W: Word> {
+    cipher: Arc<RC5<W>>,
+    /// Value of the usage counter when the entry was used last time.
+    last_used: u64,
+}
+
+/// A bounded LRU cache mapping secret keys to prepared RC5 instances.
+///
+/// When the cache is full, the least recently used instance is evicted.
+/// Its expanded key table is securely zeroed once the last handle to it is dropped.
+pub struct CipherCache<W: Word> {
+    /// Maximum number of cached instances.
+    capacity: usize,
+    /// Number of rounds of the cached instances.
+    number_of_rounds: usize,
+    /// Two independently keyed hashers producing a 128-bit fingerprint.
+    hashers: (RandomState, RandomState),
+    entries: HashMap<Fingerprint, Entry<W>>,
+    /// Fingerprints ordered by the last usage.
+    usage: BTreeMap<u64, Fingerprint>,
+    /// Monotonic usage counter.
+    counter: u64,
+}
+
+impl<W> CipherCache<W>
+where
+    W: Word,
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Creates a cache holding at most `capacity` instances
+    /// with a default reasonable number of rounds.
+    pub fn new(capacity: usize) -> Self {
+        Self::new_with_rounds(capacity, W::ROUNDS)
+    }
+
+    /// Creates a cache holding at most `capacity` instances with a given number of rounds.
+    pub fn new_with_rounds(capacity: usize, number_of_rounds: usize) -> Self {
+        Self {
+            capacity,
+            number_of_rounds,
+            hashers: (RandomState::new(), RandomState::new()),
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+            counter: 0,
+        }
+    }
+
+    /// Returns a cached instance for a given secret key,
+    /// creating (and caching) a new one if there is none.
+    pub fn get_or_create(&mut self, secret_key: impl AsRef<[u8]>) -> Result<Arc<RC5<W>>, Error> {
+        let secret_key = secret_key.as_ref();
+        let fingerprint = self.fingerprint(secret_key);
+        self.counter += 1;
+
+        if let Some(entry) = self.entries.get_mut(&fingerprint) {
+            self.usage.remove(&entry.last_used);
+            self.usage.insert(self.counter, fingerprint);
+            entry.last_used = self.counter;
+            return Ok(Arc::clone(&entry.cipher));
+        }
+
+        let cipher = Arc::new(RC5::new_with_rounds(
+            secret_key.to_vec(),
+            self.number_of_rounds,
+        )?);
+
+        if self.capacity == 0 {
+            return Ok(cipher);
+        }
+        if self.entries.len() >= self.capacity {
+            self.evict();
+        }
+
+        self.usage.insert(self.counter, fingerprint);
+        self.entries.insert(
+            fingerprint,
+            Entry {
+                cipher: Arc::clone(&cipher),
+                last_used: self.counter,
+            },
+        );
+
+        Ok(cipher)
+    }
+
+    /// Number of cached instances.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no cached instances.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Evicts all cached instances.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.usage.clear();
+    }
+
+    /// Evicts the least recently used instance.
+    fn evict(&mut self) {
+        let least_recent = self.usage.keys().next().copied();
+        if let Some(fingerprint) = least_recent.and_then(|last_used| self.usage.remove(&last_used))
+        {
+            self.entries.remove(&fingerprint);
+        }
+    }
+
+    fn fingerprint(&self, secret_key: &[u8]) -> Fingerprint {
+        let (h1, h2) = &self.hashers;
+        (h1.hash_one(secret_key) as u128) << 64 | h2.hash_one(secret_key) as u128
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_or_create() {
+        let mut cache = CipherCache::<u32>::new(2);
+        let a = cache.get_or_create([1; 16]).unwrap();
+        let b = cache.get_or_create([1; 16]).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = CipherCache::<u32>::new(2);
+        let a = cache.get_or_create([1; 16]).unwrap();
+        let b = cache.get_or_create([2; 16]).unwrap();
+        // Touch `a`, so that `b` becomes the least recently used.
+        cache.get_or_create([1; 16]).unwrap();
+        cache.get_or_create([3; 16]).unwrap();
+        assert_eq!(cache.len(), 2);
+
+        assert!(Arc::ptr_eq(&a, &cache.get_or_create([1; 16]).unwrap()));
+        assert!(!Arc::ptr_eq(&b, &cache.get_or_create([2; 16]).unwrap()));
+    }
+
+    #[test]
+    #[cfg(all(feature = "encrypt", feature = "decrypt"))]
+    fn zero_capacity() {
+        let mut cache = CipherCache::<u16>::new(0);
+        let pt = vec![0x00, 0x11, 0x22, 0x33];
+        let rc5 = cache.get_or_create([7; 8]).unwrap();
+        assert_eq!(rc5.decode(rc5.encode(&pt)), pt);
+        assert!(cache.is_empty());
+    }
+}
@@ -11,6 +11,27 @@
 //! Used by RC5 "key-management" schemes to manage and transmit
 //! entire RC5 control blocks, containing all of the relevant parameters in
 //! addition to the usual secret cryptographic key variable.
+//!
+//! A version 2 control block (`ControlBlockV2`, version byte `0x2_`) is followed
+//! by optional fields describing how the data was encrypted, each encoded as
+//! 1 byte tag, 1 byte length and the value:
+//!
+//! * `0x01` - mode of operation, 1 byte (see `Mode`).
+//! * `0x02` - initialization vector.
+//! * `0x03` - key derivation parameters: 1 byte algorithm, 4 bytes iterations
+//!   (little-endian) and the salt.
+
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryFrom,
+    fmt,
+    hash::{BuildHasher, Hash, Hasher},
+    sync::OnceLock,
+};
+
+#[cfg(feature = "encrypt")]
+use crate::RC5;
+use crate::{Error, KeyLength, Params};
 
 pub struct ControlBlock {
     /// Version.
@@ -20,20 +41,477 @@ pub struct ControlBlock {
     /// Number of rounds.
     pub r: u8,
     /// Number of bytes in the secret key.
-    pub b: u8,
+    pub b: KeyLength,
     /// Secret key.
     pub k: Vec<u8>,
 }
 
 impl ControlBlock {
+    /// Word sizes an RC5 instance can be created with.
+    const SUPPORTED_WORD_SIZES: [u8; 3] = [16, 32, 64];
+
     /// Creates a control block with a nominal choice of parameters.
-    pub fn nominal(key: Vec<u8>) -> Self {
-        Self {
+    ///
+    /// Fails if the key is longer than 255 bytes, which doesn't fit in `b`.
+    pub fn nominal(key: Vec<u8>) -> Result<Self, Error> {
+        Ok(Self {
             v: 0x10,
             w: 32,
             r: 16,
-            b: key.len() as u8,
+            b: KeyLength::new(key.len())?,
             k: key,
+        })
+    }
+
+    /// Serializes the control block into `b + 4` bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.k.len() + 4);
+        bytes.extend_from_slice(&[self.v, self.w, self.r, self.b.get()]);
+        bytes.extend_from_slice(&self.k);
+        bytes
+    }
+
+    /// Parses a control block serialized with `ControlBlock::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        match bytes {
+            [v, w, r, b, k @ ..] if k.len() == *b as usize => Ok(Self {
+                v: *v,
+                w: *w,
+                r: *r,
+                b: KeyLength::from(*b),
+                k: k.to_vec(),
+            }),
+            _ => Err(Error::InvalidControlBlock),
+        }
+    }
+
+    /// Computes a key check value of the control block.
+    ///
+    /// The key check value is the first 4 bytes of an all-zero block encrypted
+    /// with the parameters and the key of the control block. It can be printed
+    /// and compared to make sure that both parties hold the same key
+    /// and parameters without revealing the key.
+    #[cfg(feature = "encrypt")]
+    pub fn key_check_value(&self) -> Result<[u8; 4], Error> {
+        let (k, r) = (self.k.clone(), self.r as usize);
+        let ciphertext = match self.w {
+            16 => RC5::<u16>::new_with_rounds(k, r)?.encode([0; 4]),
+            32 => RC5::<u32>::new_with_rounds(k, r)?.encode([0; 8]),
+            64 => RC5::<u64>::new_with_rounds(k, r)?.encode([0; 16]),
+            w => return Err(Error::InvalidWordSize(w as usize)),
+        };
+
+        let mut kcv = [0; 4];
+        kcv.copy_from_slice(&ciphertext[..4]);
+        Ok(kcv)
+    }
+
+    /// Negotiates parameters between two devices with different capabilities.
+    ///
+    /// Implements the key-management use case from the paper:
+    /// each device advertises the largest word size and number of rounds it supports
+    /// in its control block, and both agree on the strongest parameters supported by both.
+    /// Control blocks of different versions are incompatible.
+    pub fn negotiate(&self, other: &ControlBlock) -> Result<Params, Error> {
+        if self.v != other.v {
+            return Err(Error::IncompatibleVersions(self.v, other.v));
+        }
+
+        for w in [self.w, other.w] {
+            if !Self::SUPPORTED_WORD_SIZES.contains(&w) {
+                return Err(Error::InvalidWordSize(w as usize));
+            }
+        }
+
+        Ok(Params {
+            word_size: self.w.min(other.w) as usize,
+            rounds: self.r.min(other.r) as usize,
+        })
+    }
+}
+
+/// Mode of operation identifier of a version 2 control block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Electronic codebook, as done by `RC5::encode`.
+    Ecb = 0x00,
+    /// RC5-CBC (RFC 2040).
+    Cbc = 0x01,
+    /// RC5-CBC-Pad (RFC 2040).
+    CbcPad = 0x02,
+    /// RC5-CTS (RFC 2040).
+    Cts = 0x03,
+    /// Counter mode, as done by `RingEncryptor`.
+    Ctr = 0x04,
+    /// Full-block cipher feedback, as done by `modes::Cfb`.
+    Cfb = 0x05,
+    /// 8-bit cipher feedback, as done by `modes::Cfb8`.
+    Cfb8 = 0x06,
+    /// Propagating CBC, as done by `modes::Pcbc`.
+    Pcbc = 0x07,
+    /// XTS (IEEE 1619), as done by `modes::Xts`.
+    Xts = 0x08,
+    /// Counter with CBC-MAC (RFC 3610), as done by `modes::Ccm`.
+    Ccm = 0x09,
+    /// OCB (RFC 7253), as done by `modes::Ocb`.
+    Ocb = 0x0a,
+}
+
+impl TryFrom<u8> for Mode {
+    type Error = Error;
+
+    fn try_from(id: u8) -> Result<Self, Error> {
+        match id {
+            0x00 => Ok(Self::Ecb),
+            0x01 => Ok(Self::Cbc),
+            0x02 => Ok(Self::CbcPad),
+            0x03 => Ok(Self::Cts),
+            0x04 => Ok(Self::Ctr),
+            0x05 => Ok(Self::Cfb),
+            0x06 => Ok(Self::Cfb8),
+            0x07 => Ok(Self::Pcbc),
+            0x08 => Ok(Self::Xts),
+            0x09 => Ok(Self::Ccm),
+            0x0a => Ok(Self::Ocb),
+            _ => Err(Error::InvalidControlBlock),
+        }
+    }
+}
+
+/// Parameters of the key derivation function the secret key was derived with.
+///
+/// The crate doesn't derive keys itself, the algorithm identifier
+/// is agreed upon by the applications exchanging control blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Algorithm identifier.
+    pub algorithm: u8,
+    /// Number of iterations.
+    pub iterations: u32,
+    /// Salt.
+    pub salt: Vec<u8>,
+}
+
+/// A version 2 control block, carrying everything needed to decrypt
+/// a cipher text in addition to the parameters and the key.
+pub struct ControlBlockV2 {
+    /// Parameters and the secret key.
+    pub base: ControlBlock,
+    /// Mode of operation.
+    pub mode: Option<Mode>,
+    /// Initialization vector.
+    pub iv: Option<Vec<u8>>,
+    /// Key derivation parameters.
+    pub kdf: Option<KdfParams>,
+}
+
+impl ControlBlockV2 {
+    /// Version byte of serialized version 2 control blocks.
+    pub const VERSION: u8 = 0x20;
+
+    const TAG_MODE: u8 = 0x01;
+    const TAG_IV: u8 = 0x02;
+    const TAG_KDF: u8 = 0x03;
+
+    /// Creates a version 2 control block without optional fields.
+    pub fn new(base: ControlBlock) -> Self {
+        Self {
+            base: ControlBlock {
+                v: Self::VERSION,
+                ..base
+            },
+            mode: None,
+            iv: None,
+            kdf: None,
+        }
+    }
+
+    /// Serializes the control block, fails if a field is longer than 255 bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = self.base.to_bytes();
+        bytes[0] = Self::VERSION;
+
+        let mut push = |tag: u8, value: &[u8]| {
+            let len = u8::try_from(value.len()).map_err(|_| Error::InvalidControlBlock)?;
+            bytes.extend_from_slice(&[tag, len]);
+            bytes.extend_from_slice(value);
+            Ok::<_, Error>(())
+        };
+        if let Some(mode) = self.mode {
+            push(Self::TAG_MODE, &[mode as u8])?;
+        }
+        if let Some(iv) = &self.iv {
+            push(Self::TAG_IV, iv)?;
         }
+        if let Some(kdf) = &self.kdf {
+            let mut value = vec![kdf.algorithm];
+            value.extend_from_slice(&kdf.iterations.to_le_bytes());
+            value.extend_from_slice(&kdf.salt);
+            push(Self::TAG_KDF, &value)?;
+        }
+
+        Ok(bytes)
+    }
+
+    /// Parses a control block serialized with `ControlBlockV2::to_bytes`
+    /// or a version 1 control block serialized with `ControlBlock::to_bytes`.
+    ///
+    /// Fails on other minor versions of version 2, whose fields are unknown.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let b = match bytes {
+            [Self::VERSION, _, _, b, ..] => *b as usize,
+            [v, ..] if v >> 4 == Self::VERSION >> 4 => return Err(Error::InvalidControlBlock),
+            _ => {
+                return Ok(Self {
+                    base: ControlBlock::from_bytes(bytes)?,
+                    mode: None,
+                    iv: None,
+                    kdf: None,
+                })
+            }
+        };
+        if bytes.len() < 4 + b {
+            return Err(Error::InvalidControlBlock);
+        }
+        let (base, mut fields) = bytes.split_at(4 + b);
+
+        let mut cb = Self::new(ControlBlock::from_bytes(base)?);
+
+        while let [tag, len, rest @ ..] = fields {
+            let len = *len as usize;
+            if rest.len() < len {
+                return Err(Error::InvalidControlBlock);
+            }
+            let (value, rest) = rest.split_at(len);
+            fields = rest;
+
+            match (*tag, value) {
+                (Self::TAG_MODE, [mode]) if cb.mode.is_none() => {
+                    cb.mode = Some(Mode::try_from(*mode)?)
+                }
+                (Self::TAG_IV, iv) if cb.iv.is_none() => cb.iv = Some(iv.to_vec()),
+                (Self::TAG_KDF, [algorithm, i0, i1, i2, i3, salt @ ..]) if cb.kdf.is_none() => {
+                    cb.kdf = Some(KdfParams {
+                        algorithm: *algorithm,
+                        iterations: u32::from_le_bytes([*i0, *i1, *i2, *i3]),
+                        salt: salt.to_vec(),
+                    })
+                }
+                _ => return Err(Error::InvalidControlBlock),
+            }
+        }
+        if !fields.is_empty() {
+            return Err(Error::InvalidControlBlock);
+        }
+
+        Ok(cb)
+    }
+}
+
+/// Formats the control block as its RC5-w/r/b designation,
+/// never revealing the secret key.
+impl fmt::Display for ControlBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RC5-{}/{}/{}", self.w, self.r, self.b)
+    }
+}
+
+/// Control blocks are equal if they have the same parameters and secret key.
+///
+/// Keys are compared in constant time.
+impl PartialEq for ControlBlock {
+    fn eq(&self, other: &Self) -> bool {
+        (self.v, self.w, self.r, self.b) == (other.v, other.w, other.r, other.b)
+            && self.k.len() == other.k.len()
+            && self
+                .k
+                .iter()
+                .zip(&other.k)
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+impl Eq for ControlBlock {}
+
+/// Hashes the parameters and a fingerprint of the secret key, never the key itself,
+/// so control blocks can be used as map keys.
+///
+/// Fingerprints are SipHash digests with a random per-process key,
+/// so they don't reveal anything about the key outside of the process.
+impl Hash for ControlBlock {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        static FINGERPRINT: OnceLock<RandomState> = OnceLock::new();
+
+        (self.v, self.w, self.r, self.b).hash(state);
+        FINGERPRINT
+            .get_or_init(RandomState::new)
+            .hash_one(&self.k)
+            .hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eq_hash() {
+        use std::collections::HashSet;
+
+        let a = ControlBlock::nominal(vec![1; 16]).unwrap();
+        let b = ControlBlock::nominal(vec![1; 16]).unwrap();
+        assert!(a == b);
+        assert!(a != ControlBlock::nominal(vec![2; 16]).unwrap());
+        assert!(a != ControlBlock::nominal(vec![1; 15]).unwrap());
+        assert!(
+            a != ControlBlock {
+                r: 20,
+                ..ControlBlock::nominal(vec![1; 16]).unwrap()
+            }
+        );
+
+        let mut keyring = HashSet::new();
+        assert!(keyring.insert(a));
+        assert!(!keyring.insert(b));
+        assert!(keyring.insert(ControlBlock::nominal(vec![2; 16]).unwrap()));
+        assert_eq!(keyring.len(), 2);
+    }
+
+    #[test]
+    fn negotiate() {
+        let a = ControlBlock {
+            w: 64,
+            r: 20,
+            ..ControlBlock::nominal(vec![1; 16]).unwrap()
+        };
+        let b = ControlBlock {
+            w: 16,
+            r: 24,
+            ..ControlBlock::nominal(vec![1; 16]).unwrap()
+        };
+        let params = Params {
+            word_size: 16,
+            rounds: 20,
+        };
+        assert_eq!(a.negotiate(&b).unwrap(), params);
+        assert_eq!(b.negotiate(&a).unwrap(), params);
+    }
+
+    #[test]
+    fn negotiate_incompatible() {
+        let a = ControlBlock::nominal(vec![1; 16]).unwrap();
+        let b = ControlBlock {
+            v: 0x11,
+            ..ControlBlock::nominal(vec![1; 16]).unwrap()
+        };
+        assert!(matches!(
+            a.negotiate(&b),
+            Err(Error::IncompatibleVersions(0x10, 0x11))
+        ));
+
+        let c = ControlBlock {
+            w: 8,
+            ..ControlBlock::nominal(vec![1; 16]).unwrap()
+        };
+        assert!(matches!(a.negotiate(&c), Err(Error::InvalidWordSize(8))));
+    }
+
+    #[test]
+    fn to_from_bytes() {
+        let cb = ControlBlock::nominal(vec![0x01, 0x02, 0x03]).unwrap();
+        let bytes = cb.to_bytes();
+        assert_eq!(bytes, vec![0x10, 32, 16, 3, 0x01, 0x02, 0x03]);
+
+        let parsed = ControlBlock::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.to_bytes(), bytes);
+        assert_eq!(parsed.to_string(), "RC5-32/16/3");
+
+        assert!(ControlBlock::from_bytes(&bytes[..6]).is_err());
+        assert!(ControlBlock::from_bytes(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn v2_to_from_bytes() {
+        let mut cb = ControlBlockV2::new(ControlBlock::nominal(vec![0x01, 0x02]).unwrap());
+        assert_eq!(cb.to_bytes().unwrap(), vec![0x20, 32, 16, 2, 0x01, 0x02]);
+
+        cb.mode = Some(Mode::CbcPad);
+        cb.iv = Some(vec![7; 8]);
+        cb.kdf = Some(KdfParams {
+            algorithm: 1,
+            iterations: 1000,
+            salt: vec![9; 4],
+        });
+        let bytes = cb.to_bytes().unwrap();
+        let parsed = ControlBlockV2::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.base.to_string(), "RC5-32/16/2");
+        assert_eq!(parsed.base.k, vec![0x01, 0x02]);
+        assert_eq!(parsed.mode, cb.mode);
+        assert_eq!(parsed.iv, cb.iv);
+        assert_eq!(parsed.kdf, cb.kdf);
+
+        for len in 4..bytes.len() {
+            // Truncated in the middle of a field.
+            if len != 6 && len != 9 && len != 19 {
+                assert!(ControlBlockV2::from_bytes(&bytes[..len]).is_err());
+            }
+        }
+        let mut unknown = bytes.clone();
+        unknown.extend_from_slice(&[0x7f, 0]);
+        assert!(ControlBlockV2::from_bytes(&unknown).is_err());
+
+        let mut minor = bytes.clone();
+        minor[0] = 0x21;
+        assert!(ControlBlockV2::from_bytes(&minor).is_err());
+    }
+
+    #[test]
+    fn modes() {
+        for id in 0..=0x0a {
+            let mode = Mode::try_from(id).unwrap();
+            assert_eq!(mode as u8, id);
+
+            let mut cb = ControlBlockV2::new(ControlBlock::nominal(vec![1; 16]).unwrap());
+            cb.mode = Some(mode);
+            let parsed = ControlBlockV2::from_bytes(&cb.to_bytes().unwrap()).unwrap();
+            assert_eq!(parsed.mode, Some(mode));
+        }
+        assert!(Mode::try_from(0x0b).is_err());
+    }
+
+    #[test]
+    fn v2_accepts_v1() {
+        let bytes = ControlBlock::nominal(vec![0x01, 0x02, 0x03])
+            .unwrap()
+            .to_bytes();
+        let parsed = ControlBlockV2::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.base.v, 0x10);
+        assert_eq!(parsed.base.to_bytes(), bytes);
+        assert!(parsed.mode.is_none() && parsed.iv.is_none() && parsed.kdf.is_none());
+    }
+
+    #[test]
+    fn long_key() {
+        assert!(ControlBlock::nominal(vec![1; 255]).is_ok());
+        assert!(matches!(
+            ControlBlock::nominal(vec![1; 256]),
+            Err(Error::InvalidSecretKeyLength { actual: 256, .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "encrypt")]
+    fn key_check_value() {
+        let cb = ControlBlock {
+            r: 12,
+            ..ControlBlock::nominal(vec![
+                0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+                0x0E, 0x0F,
+            ])
+            .unwrap()
+        };
+        let rc5 = RC5::<u32>::new_with_rounds(cb.k.clone(), 12).unwrap();
+        assert_eq!(cb.key_check_value().unwrap(), rc5.encode([0; 8])[..4]);
     }
 }
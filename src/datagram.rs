@@ -0,0 +1,275 @@
+//! Authenticated datagrams for custom UDP protocols.
+//!
+//! Every packet is encrypted and authenticated with CCM (see `modes::Ccm`)
+//! under a nonce derived from the session ID, the role of the sender and
+//! a per-packet counter, so nonces never repeat within a session:
+//!
+//! ```text
+//! nonce = session ID (4 bytes) || role (1 byte) || counter (8 bytes)
+//! packet = counter (8 bytes, little-endian) || CCM(nonce, payload) || tag
+//! ```
+//!
+//! Both endpoints share the key and the session ID, and take different roles,
+//! so that their packets don't share nonces. The counter is sent in the clear
+//! and authenticated as part of the nonce.
+//!
+//! Datagrams can be lost, duplicated or reordered on the way. The receiver keeps
+//! a sliding window of the last `Datagram::WINDOW` counters: a packet is accepted
+//! once, in any order within the window, and rejected if it was already received
+//! or is older than the window. The window only moves for authentic packets.
+//!
+//! The nonce takes a whole CCM block of RC5-64, so only 64-bit words are supported
+//! and payloads are shorter than 64 KiB, which covers UDP datagrams.
+//! A session ID must never be reused with the same key.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use crate::{modes::Ccm, Error, FromLeBytes, ToLeBytes, Word, RC5};
+
+/// Length of the counter prefix of a packet in bytes.
+const COUNTER_LEN: usize = 8;
+
+/// Role of an endpoint in a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// The endpoint that opened the session.
+    Initiator = 0,
+    /// The other endpoint.
+    Responder = 1,
+}
+
+impl Role {
+    /// Role of the other endpoint.
+    pub fn peer(self) -> Self {
+        match self {
+            Role::Initiator => Role::Responder,
+            Role::Responder => Role::Initiator,
+        }
+    }
+}
+
+/// An endpoint of a datagram session.
+pub struct Datagram<W: Word> {
+    ccm: Ccm<W>,
+    session_id: u32,
+    role: Role,
+    /// Counter of the next packet sent.
+    next_counter: u64,
+    /// Highest counter received so far.
+    highest: Option<u64>,
+    /// Bit `i` is set once the packet with the counter `highest - i` is received.
+    window: u64,
+}
+
+impl<W: Word> Datagram<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Number of counters the receiver keeps track of.
+    pub const WINDOW: u64 = 64;
+
+    /// Creates an endpoint of a session with tags of `tag_len` bytes
+    /// (an even number from 4 up to 16).
+    ///
+    /// Fails for word sizes other than 64 bits.
+    pub fn new(cipher: RC5<W>, tag_len: usize, session_id: u32, role: Role) -> Result<Self, Error> {
+        if W::BITS != 64 {
+            return Err(Error::InvalidWordSize(W::BITS));
+        }
+        Ok(Self {
+            ccm: Ccm::new(cipher, tag_len)?,
+            session_id,
+            role,
+            next_counter: 0,
+            highest: None,
+            window: 0,
+        })
+    }
+
+    /// Encrypts a payload into the next packet of the session.
+    ///
+    /// Fails with `Error::NonceExhausted` once every counter has been used.
+    pub fn seal(&mut self, payload: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+        let counter = self.next_counter;
+        let next_counter = counter.checked_add(1).ok_or(Error::NonceExhausted)?;
+
+        let nonce = self.nonce(self.role, counter);
+        let ciphertext = self.ccm.encrypt(&nonce, [], payload)?;
+        self.next_counter = next_counter;
+
+        let mut packet = Vec::with_capacity(COUNTER_LEN + ciphertext.len());
+        packet.extend_from_slice(&counter.to_le_bytes());
+        packet.extend_from_slice(&ciphertext);
+        Ok(packet)
+    }
+
+    /// Verifies and decrypts a packet sealed by the other endpoint.
+    ///
+    /// Fails with `Error::ReplayedPacket` if the packet was already received
+    /// or is too old, and with `Error::AuthenticationFailed` if it was tampered with.
+    pub fn open(&mut self, packet: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+        let packet = packet.as_ref();
+        if packet.len() < COUNTER_LEN {
+            return Err(Error::CiphertextTooShort {
+                actual: packet.len(),
+                min: COUNTER_LEN + self.ccm.tag_len(),
+            });
+        }
+        let (counter, ciphertext) = packet.split_at(COUNTER_LEN);
+        let counter = u64::from_le_bytes(counter.try_into().expect("8 bytes"));
+        self.check_counter(counter)?;
+
+        let peer = match self.role {
+            Role::Initiator => Role::Responder,
+            Role::Responder => Role::Initiator,
+        };
+        let payload = self
+            .ccm
+            .decrypt(&self.nonce(peer, counter), [], ciphertext)?;
+        self.accept_counter(counter);
+        Ok(payload)
+    }
+
+    /// Fails if a counter was already received or is older than the window.
+    fn check_counter(&self, counter: u64) -> Result<(), Error> {
+        match self.highest {
+            Some(highest) if counter <= highest => {
+                let age = highest - counter;
+                if age >= Self::WINDOW || self.window >> age & 1 == 1 {
+                    return Err(Error::ReplayedPacket(counter));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Marks an authentic counter as received, moving the window if it's the highest.
+    fn accept_counter(&mut self, counter: u64) {
+        match self.highest {
+            Some(highest) if counter <= highest => self.window |= 1 << (highest - counter),
+            Some(highest) => {
+                let shift = counter - highest;
+                self.window = if shift < Self::WINDOW {
+                    self.window << shift | 1
+                } else {
+                    1
+                };
+                self.highest = Some(counter);
+            }
+            None => {
+                self.window = 1;
+                self.highest = Some(counter);
+            }
+        }
+    }
+
+    fn nonce(&self, sender: Role, counter: u64) -> [u8; 13] {
+        let mut nonce = [0; 13];
+        nonce[..4].copy_from_slice(&self.session_id.to_le_bytes());
+        nonce[4] = sender as u8;
+        nonce[5..].copy_from_slice(&counter.to_le_bytes());
+        nonce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> (Datagram<u64>, Datagram<u64>) {
+        let rc5 = RC5::<u64>::new(vec![0x42; 16]).unwrap();
+        (
+            Datagram::new(rc5.clone(), 16, 7, Role::Initiator).unwrap(),
+            Datagram::new(rc5, 16, 7, Role::Responder).unwrap(),
+        )
+    }
+
+    #[test]
+    fn both_directions() {
+        let (mut client, mut server) = session();
+        for i in 0..3u8 {
+            let packet = client.seal([i; 10]).unwrap();
+            assert_eq!(packet.len(), 8 + 10 + 16);
+            assert_eq!(packet[..8], (i as u64).to_le_bytes());
+            assert_eq!(server.open(&packet).unwrap(), [i; 10]);
+
+            let packet = server.seal([i; 3]).unwrap();
+            assert_eq!(client.open(&packet).unwrap(), [i; 3]);
+        }
+
+        // The nonces of the two directions differ, so do the packets.
+        let (mut client, mut server) = session();
+        let (a, b) = (client.seal([0; 4]).unwrap(), server.seal([0; 4]).unwrap());
+        assert_ne!(a, b);
+        // A packet reflected back to its sender isn't accepted.
+        assert_eq!(client.open(&a), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn replay_window() {
+        let (mut client, mut server) = session();
+        let packets = (0..100u8)
+            .map(|i| client.seal([i]).unwrap())
+            .collect::<Vec<_>>();
+
+        // Reordered within the window.
+        assert_eq!(server.open(&packets[10]).unwrap(), [10]);
+        assert_eq!(server.open(&packets[3]).unwrap(), [3]);
+        assert_eq!(server.open(&packets[11]).unwrap(), [11]);
+        for i in [10, 3, 11] {
+            assert_eq!(
+                server.open(&packets[i]),
+                Err(Error::ReplayedPacket(i as u64))
+            );
+        }
+
+        // Older than the window once packet 80 is received.
+        assert_eq!(server.open(&packets[80]).unwrap(), [80]);
+        assert_eq!(server.open(&packets[16]), Err(Error::ReplayedPacket(16)));
+        assert_eq!(server.open(&packets[17]).unwrap(), [17]);
+        assert_eq!(server.open(&packets[79]).unwrap(), [79]);
+    }
+
+    #[test]
+    fn forgeries_keep_window() {
+        let (mut client, mut server) = session();
+        let packet = client.seal([1; 8]).unwrap();
+
+        // A forged counter neither decrypts nor moves the window.
+        let mut forged = packet.clone();
+        forged[..8].copy_from_slice(&1000u64.to_le_bytes());
+        assert_eq!(server.open(&forged), Err(Error::AuthenticationFailed));
+        let mut forged = packet.clone();
+        forged[9] ^= 1;
+        assert_eq!(server.open(&forged), Err(Error::AuthenticationFailed));
+        assert_eq!(server.open(&packet).unwrap(), [1; 8]);
+
+        assert!(matches!(
+            server.open([0; 5]),
+            Err(Error::CiphertextTooShort { actual: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        assert!(matches!(
+            Datagram::new(rc5, 8, 7, Role::Initiator),
+            Err(Error::InvalidWordSize(32))
+        ));
+        let rc5 = RC5::<u64>::new(vec![0x42; 16]).unwrap();
+        assert!(matches!(
+            Datagram::new(rc5, 5, 7, Role::Initiator),
+            Err(Error::InvalidTagLength(5))
+        ));
+
+        let (mut client, _) = session();
+        client.next_counter = u64::MAX;
+        assert_eq!(client.seal([0; 4]), Err(Error::NonceExhausted));
+    }
+}
@@ -0,0 +1,219 @@
+//! Configuration from environment variables.
+//!
+//! Twelve-factor deployments configure services through the environment.
+//! The conventional variables are:
+//!
+//! * `RC5_KEY` - the secret key, hex-encoded.
+//! * `RC5_KEY_FILE` - path to a file holding the raw bytes of the secret key
+//!   (as written by `rc5-keys generate`), used if `RC5_KEY` is not set.
+//! * `RC5_WORD_SIZE` - word size in bits.
+//! * `RC5_ROUNDS` - number of rounds, the default reasonable number
+//!   of rounds of the word size if not set.
+//!
+//! Copies of the key made while parsing are securely zeroed.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    ffi::OsString,
+    fmt::Debug,
+    fs,
+};
+
+use zeroize::Zeroizing;
+
+use crate::{params::WordSize, Error, FromLeBytes, Params, ToLeBytes, Word, RC5};
+
+const KEY: &str = "RC5_KEY";
+const KEY_FILE: &str = "RC5_KEY_FILE";
+const WORD_SIZE: &str = "RC5_WORD_SIZE";
+const ROUNDS: &str = "RC5_ROUNDS";
+
+impl Params {
+    /// Reads parameters from the `RC5_WORD_SIZE` and `RC5_ROUNDS` variables.
+    ///
+    /// The word size is 32 bits if not set.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::from_vars(32, |name| std::env::var_os(name))
+    }
+
+    /// Reads parameters from variables looked up by `var`.
+    fn from_vars(
+        default_word_size: usize,
+        var: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<Self, Error> {
+        let word_size = match parse(WORD_SIZE, &var)? {
+            Some(bits) => WordSize::new(bits)?,
+            None => WordSize::new(default_word_size)?,
+        };
+        let rounds = match parse(ROUNDS, &var)? {
+            Some(rounds) => rounds,
+            None => match word_size {
+                WordSize::W8 => u8::ROUNDS,
+                WordSize::W16 => u16::ROUNDS,
+                WordSize::W32 => u32::ROUNDS,
+                WordSize::W64 => u64::ROUNDS,
+            },
+        };
+
+        Ok(Self {
+            word_size: word_size.bits(),
+            rounds,
+        })
+    }
+}
+
+impl<W> RC5<W>
+where
+    W: Word,
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Creates a new RC5 instance configured by the `RC5_KEY` (or `RC5_KEY_FILE`),
+    /// `RC5_WORD_SIZE` and `RC5_ROUNDS` variables.
+    ///
+    /// The word size defaults to the size of `W` and must match it if set.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::from_vars(|name| std::env::var_os(name))
+    }
+
+    /// Creates a new RC5 instance configured by variables looked up by `var`.
+    fn from_vars(var: impl Fn(&str) -> Option<OsString>) -> Result<Self, Error> {
+        let params = Params::from_vars(W::BITS, &var)?;
+        Self::new_with_params(key(&var)?, params)
+    }
+}
+
+/// Reads the secret key from `RC5_KEY` or `RC5_KEY_FILE`.
+fn key(var: impl Fn(&str) -> Option<OsString>) -> Result<Vec<u8>, Error> {
+    if let Some(value) = var(KEY) {
+        let hex = Zeroizing::new(value.into_string().map_err(|_| Error::InvalidEnvVar {
+            name: KEY,
+            reason: "not valid unicode",
+        })?);
+        return decode_hex(hex.trim()).ok_or(Error::InvalidEnvVar {
+            name: KEY,
+            reason: "not a hex-encoded key",
+        });
+    }
+
+    match var(KEY_FILE) {
+        Some(path) => Ok(fs::read(path)?),
+        None => Err(Error::InvalidEnvVar {
+            name: KEY,
+            reason: "neither `RC5_KEY` nor `RC5_KEY_FILE` is set",
+        }),
+    }
+}
+
+/// Parses an optional numeric variable.
+fn parse(
+    name: &'static str,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Result<Option<usize>, Error> {
+    var(name)
+        .map(|value| {
+            value
+                .to_str()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or(Error::InvalidEnvVar {
+                    name,
+                    reason: "not a number",
+                })
+        })
+        .transpose()
+}
+
+/// Decodes a hex string, the partially decoded key is wiped on failure.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let mut bytes = Zeroizing::new(Vec::with_capacity(hex.len() / 2));
+    for pair in hex.as_bytes().chunks(2) {
+        let digit = |c: u8| (c as char).to_digit(16);
+        bytes.push((digit(pair[0])? * 16 + digit(pair[1])?) as u8);
+    }
+    Some(std::mem::take(&mut *bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), OsString::from(value)))
+            .collect::<HashMap<_, _>>();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn params_from_vars() {
+        assert_eq!(
+            Params::from_vars(32, vars(&[])).unwrap(),
+            Params::nominal::<u32>()
+        );
+        assert_eq!(
+            Params::from_vars(32, vars(&[(WORD_SIZE, "64")])).unwrap(),
+            Params::nominal::<u64>()
+        );
+        assert_eq!(
+            Params::from_vars(32, vars(&[(WORD_SIZE, "16"), (ROUNDS, " 20 ")])).unwrap(),
+            Params {
+                word_size: 16,
+                rounds: 20
+            }
+        );
+        assert!(matches!(
+            Params::from_vars(32, vars(&[(WORD_SIZE, "24")])),
+            Err(Error::InvalidWordSize(24))
+        ));
+        assert!(matches!(
+            Params::from_vars(32, vars(&[(ROUNDS, "many")])),
+            Err(Error::InvalidEnvVar { name: ROUNDS, .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "encrypt")]
+    fn rc5_from_vars() {
+        let key = vec![
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+            0x0E, 0x0F,
+        ];
+        let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let ct = RC5::<u32>::new_with_rounds(key.clone(), 12)
+            .unwrap()
+            .encode(pt);
+
+        let hex = "000102030405060708090a0B0c0d0e0f";
+        let rc5 = RC5::<u32>::from_vars(vars(&[(KEY, hex), (ROUNDS, "12")])).unwrap();
+        assert_eq!(rc5.encode(pt), ct);
+
+        let path = std::env::temp_dir().join(format!("rc5-env-key-{}", std::process::id()));
+        fs::write(&path, &key).unwrap();
+        let rc5 =
+            RC5::<u32>::from_vars(vars(&[(KEY_FILE, path.to_str().unwrap()), (ROUNDS, "12")]))
+                .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(rc5.encode(pt), ct);
+
+        assert!(matches!(
+            RC5::<u32>::from_vars(vars(&[])),
+            Err(Error::InvalidEnvVar { name: KEY, .. })
+        ));
+        assert!(matches!(
+            RC5::<u32>::from_vars(vars(&[(KEY, "0g")])),
+            Err(Error::InvalidEnvVar { name: KEY, .. })
+        ));
+        assert!(matches!(
+            RC5::<u64>::from_vars(vars(&[(KEY, hex), (WORD_SIZE, "32")])),
+            Err(Error::InvalidWordSize(32))
+        ));
+        assert!(RC5::<u64>::from_vars(vars(&[(KEY, hex)])).is_ok());
+    }
+}
@@ -1,13 +1,401 @@
+use std::{fmt, io, ops::Deref, sync::Arc};
+
 use thiserror::Error;
 
+use crate::control_block::Mode;
+
 /// RC5 errors.
-#[derive(Error, Debug)]
+///
+/// Errors are cheap to clone, the sources of I/O and key provider errors
+/// are shared between the clones.
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum Error {
-    /// Length of the secret key is greater than 256.
-    #[error("Invalid secret key length: `{0}`")]
-    InvalidSecretKeyLength(usize),
+    /// Length of the secret key is outside of the expected range:
+    /// greater than 255 or not matching the parameters.
+    #[error("Invalid secret key length: `{actual}` bytes, expected `{min}` to `{max}` bytes")]
+    InvalidSecretKeyLength {
+        actual: usize,
+        min: usize,
+        max: usize,
+    },
+
+    /// Secret key looks weak, e.g. it's a short password or a repeated pattern.
+    #[error("Weak secret key: estimated `{estimated}` bits of entropy, expected at least `{min}`")]
+    WeakSecretKey { estimated: usize, min: usize },
+
+    /// Number of rounds is greater than the maximum.
+    #[error("Invalid number of rounds: `{actual}`, expected at most `{max}`")]
+    InvalidNumberOfRounds { actual: usize, max: usize },
+
+    /// Length of the whitening key is not twice the block size.
+    #[error("Invalid whitening key length: `{actual}` bytes, expected `{expected}` bytes")]
+    InvalidWhiteningKeyLength { actual: usize, expected: usize },
+
+    /// Length of the storage of an expanded key table doesn't match the number of rounds.
+    #[error("Invalid key table length: `{actual}` words, expected `{expected}` words")]
+    InvalidKeyTableLength { actual: usize, expected: usize },
+
+    /// Word size doesn't match the word type of an RC5 instance.
+    #[error("Invalid word size: `{0}`")]
+    InvalidWordSize(usize),
+
+    /// Control block is malformed.
+    #[error("Invalid control block")]
+    InvalidControlBlock,
+
+    /// Control blocks have different versions.
+    #[error("Incompatible control block versions: `{0}` and `{1}`")]
+    IncompatibleVersions(u8, u8),
+
+    /// Offset is past the end of the buffer.
+    #[error("Invalid offset: `{offset}`, expected at most `{len}`")]
+    InvalidOffset { offset: usize, len: usize },
+
+    /// Length of the input is not a (non-zero) multiple of the block size.
+    #[error("Invalid input length: `{actual}` bytes, expected a multiple of `{block_size}` bytes")]
+    InvalidInputLength { actual: usize, block_size: usize },
+
+    /// Length of the plain text doesn't match the length of the zero-padded cipher text.
+    #[error(
+        "Invalid plain text length: `{actual}` bytes for `{ciphertext_len}` bytes of cipher text"
+    )]
+    InvalidPlaintextLength {
+        actual: usize,
+        ciphertext_len: usize,
+    },
+
+    /// Length of an initialization vector is not the block size.
+    #[error("Invalid IV length: `{actual}` bytes, expected `{expected}` bytes")]
+    InvalidIvLength { actual: usize, expected: usize },
+
+    /// Cipher text is too short to hold its IV prefix and at least one block.
+    #[error("Cipher text is too short: `{actual}` bytes, expected at least `{min}` bytes")]
+    CiphertextTooShort { actual: usize, min: usize },
+
+    /// Encoded algorithm parameters are malformed or unsupported.
+    #[error("Invalid algorithm parameters")]
+    InvalidParameters,
+
+    /// Mode of operation isn't supported by the operation.
+    #[error("Unsupported mode of operation: `{0:?}`")]
+    UnsupportedMode(Mode),
+
+    /// Authentication tag length isn't supported by the mode.
+    #[error("Invalid tag length: `{0}` bytes")]
+    InvalidTagLength(usize),
+
+    /// Nonce length isn't supported by the mode.
+    #[error("Invalid nonce length: `{actual}` bytes, expected `{min}` to `{max}` bytes")]
+    InvalidNonceLength {
+        actual: usize,
+        min: usize,
+        max: usize,
+    },
+
+    /// Authentication tag doesn't match the cipher text and associated data.
+    #[error("Authentication failed")]
+    AuthenticationFailed,
+
+    /// Padding of decrypted plain text is malformed.
+    #[error("Invalid padding")]
+    InvalidPadding,
+
+    /// Length of a record doesn't match the record size of a `RecordCipher`.
+    #[error("Invalid record length: `{actual}` bytes, expected `{expected}` bytes")]
+    InvalidRecordLength { actual: usize, expected: usize },
+
+    /// Record number doesn't fit into a block.
+    #[error("Record number is too large: `{0}`")]
+    RecordNumberTooLarge(u64),
+
+    /// Sector index doesn't fit into a block.
+    #[error("Sector index is too large: `{0}`")]
+    SectorIndexTooLarge(u64),
+
+    /// Domain of the integer permutation is too small.
+    #[error("Invalid permutation domain: `{0}`")]
+    InvalidDomain(u64),
+
+    /// Integer is outside of the permutation domain.
+    #[error("Value is out of the permutation domain: `{0}`")]
+    OutOfDomain(u64),
+
+    /// Internal buffer of an operation would exceed the memory budget.
+    #[error(
+        "Memory budget exceeded: `{requested}` bytes requested, the budget is `{budget}` bytes"
+    )]
+    MemoryBudgetExceeded { requested: usize, budget: usize },
+
+    /// Input is too large to be processed on this platform.
+    #[error("Input is too large")]
+    InputTooLarge,
+
+    /// All counter blocks of a keystream have been used.
+    #[error("Keystream counter is exhausted")]
+    CounterExhausted,
+
+    /// A write to an encrypted file would reuse the keystream of the bytes written before.
+    #[error("Invalid write position: `{pos}`, expected at least `{end}`")]
+    InvalidWritePosition { pos: u64, end: u64 },
+
+    /// Serialized state of an encrypted stream is malformed.
+    #[error("Invalid stream state")]
+    InvalidStreamState,
+
+    /// All nonces of a nonce sequence have been used.
+    #[error("Nonce sequence is exhausted")]
+    NonceExhausted,
+
+    /// Packet counter has been received before, or is older than the replay window.
+    #[error("Replayed packet: `{0}`")]
+    ReplayedPacket(u64),
+
+    /// Stored state of a nonce sequence is malformed.
+    #[error("Invalid nonce sequence state")]
+    InvalidNonceState,
+
+    /// Stored entries of a key-value store backend are malformed.
+    #[cfg(feature = "kv")]
+    #[error("Invalid key-value store state")]
+    InvalidStoreState,
+
+    /// Environment variable is missing or malformed.
+    #[error("Invalid environment variable `{name}`: {reason}")]
+    InvalidEnvVar {
+        name: &'static str,
+        reason: &'static str,
+    },
+
+    /// I/O error.
+    #[error("I/O error: {0}")]
+    Io(#[source] Shared<io::Error>),
+
+    /// Key provider doesn't know a key with a given ID.
+    #[error("Key not found: `{0}`")]
+    KeyNotFound(String),
+
+    /// Key provider failed to resolve a key.
+    #[error("Key provider error: {0}")]
+    KeyProvider(#[source] Shared<dyn std::error::Error + Send + Sync>),
+
+    /// Threshold of the secret sharing scheme is zero or
+    /// greater than the number of shares.
+    #[cfg(feature = "shamir")]
+    #[error("Invalid secret sharing threshold: `{k}` out of `{n}`")]
+    InvalidShareThreshold { n: u8, k: u8 },
+
+    /// Share is malformed or doesn't match the other shares.
+    #[cfg(feature = "shamir")]
+    #[error("Invalid secret share")]
+    InvalidShare,
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io(Shared::new(error))
+    }
+}
+
+/// The source of an `Error`, shared between its clones.
+///
+/// Sources are compared by their messages (and kinds for I/O errors),
+/// as neither `io::Error` nor boxed errors implement `PartialEq`.
+#[derive(Debug)]
+pub struct Shared<E: ?Sized>(Arc<E>);
+
+impl<E> Shared<E> {
+    /// Wraps an error source.
+    pub fn new(source: E) -> Self {
+        Self(Arc::new(source))
+    }
+
+    /// Unwraps the error source, if it isn't shared with any other clone.
+    pub fn try_unwrap(self) -> Result<E, Self> {
+        Arc::try_unwrap(self.0).map_err(Self)
+    }
+}
+
+impl<E: ?Sized> From<Box<E>> for Shared<E> {
+    fn from(source: Box<E>) -> Self {
+        Self(Arc::from(source))
+    }
+}
+
+impl<E: ?Sized> Clone for Shared<E> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<E: ?Sized> Deref for Shared<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.0
+    }
+}
+
+impl<E: fmt::Display + ?Sized> fmt::Display for Shared<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<E: std::error::Error + ?Sized> std::error::Error for Shared<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+impl PartialEq for Shared<io::Error> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind() && self.to_string() == other.to_string()
+    }
+}
+
+impl PartialEq for Shared<dyn std::error::Error + Send + Sync> {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+/// Maps RC5 errors into I/O errors, so that they compose with `std::io` plumbing
+/// (e.g. `Read` and `Write` implementations, `io::copy` or `BufReader`).
+///
+/// I/O errors are unwrapped (unless shared with a clone), every other error
+/// is kept as the source of an I/O error of the closest `ErrorKind`.
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        let error = match error {
+            Error::Io(e) => match e.try_unwrap() {
+                Ok(e) => return e,
+                Err(e) => Error::Io(e),
+            },
+            error => error,
+        };
+        let kind = match &error {
+            Error::Io(e) => e.kind(),
+            Error::InvalidSecretKeyLength { .. }
+            | Error::WeakSecretKey { .. }
+            | Error::InvalidNumberOfRounds { .. }
+            | Error::InvalidWhiteningKeyLength { .. }
+            | Error::InvalidKeyTableLength { .. }
+            | Error::InvalidWordSize(_)
+            | Error::InvalidOffset { .. }
+            | Error::InvalidInputLength { .. }
+            | Error::InvalidPlaintextLength { .. }
+            | Error::InvalidRecordLength { .. }
+            | Error::InvalidIvLength { .. }
+            | Error::InvalidTagLength(_)
+            | Error::InvalidNonceLength { .. }
+            | Error::RecordNumberTooLarge(_)
+            | Error::SectorIndexTooLarge(_)
+            | Error::InvalidEnvVar { .. }
+            | Error::InvalidDomain(_)
+            | Error::OutOfDomain(_)
+            | Error::InvalidWritePosition { .. }
+            | Error::InputTooLarge => io::ErrorKind::InvalidInput,
+            Error::InvalidControlBlock
+            | Error::IncompatibleVersions(_, _)
+            | Error::InvalidStreamState
+            | Error::CiphertextTooShort { .. }
+            | Error::InvalidPadding
+            | Error::InvalidParameters
+            | Error::AuthenticationFailed
+            | Error::ReplayedPacket(_)
+            | Error::InvalidNonceState => io::ErrorKind::InvalidData,
+            Error::UnsupportedMode(_) => io::ErrorKind::Unsupported,
+            Error::KeyNotFound(_) => io::ErrorKind::NotFound,
+            Error::MemoryBudgetExceeded { .. } => io::ErrorKind::OutOfMemory,
+            Error::CounterExhausted | Error::NonceExhausted | Error::KeyProvider(_) => {
+                io::ErrorKind::Other
+            }
+            #[cfg(feature = "shamir")]
+            Error::InvalidShareThreshold { .. } => io::ErrorKind::InvalidInput,
+            #[cfg(feature = "shamir")]
+            Error::InvalidShare => io::ErrorKind::InvalidData,
+            #[cfg(feature = "kv")]
+            Error::InvalidStoreState => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn into_io_error() {
+        let e = io::Error::from(Error::InvalidInputLength {
+            actual: 3,
+            block_size: 8,
+        });
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(matches!(
+            e.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+            Some(Error::InvalidInputLength { actual: 3, .. })
+        ));
+
+        let e = io::Error::from(Error::from(io::Error::from(io::ErrorKind::UnexpectedEof)));
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(e.get_ref().is_none());
+
+        let shared = Error::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+        let e = io::Error::from(shared.clone());
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            e.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+            Some(&shared)
+        );
+    }
+
+    #[test]
+    fn clone_eq() {
+        let e = Error::InvalidInputLength {
+            actual: 7,
+            block_size: 8,
+        };
+        assert_eq!(e.clone(), e);
+        assert_ne!(
+            e,
+            Error::InvalidInputLength {
+                actual: 7,
+                block_size: 16
+            }
+        );
+
+        let io = |kind, message| Error::from(io::Error::new(kind, message));
+        let e = io(io::ErrorKind::NotFound, "no key file");
+        assert_eq!(e.clone(), e);
+        assert_eq!(e, io(io::ErrorKind::NotFound, "no key file"));
+        assert_ne!(e, io(io::ErrorKind::PermissionDenied, "no key file"));
+
+        let provider = |message: &str| {
+            Error::KeyProvider(Box::<dyn std::error::Error + Send + Sync>::from(message).into())
+        };
+        assert_eq!(provider("timeout"), provider("timeout"));
+        assert_ne!(provider("timeout"), provider("denied"));
+    }
 
-    /// Number of rounds is greater than 256.
-    #[error("Invalid number of rounds: `{0}`")]
-    InvalidNumberOfRounds(usize),
+    #[test]
+    fn display() {
+        let e = Error::InvalidSecretKeyLength {
+            actual: 300,
+            min: 0,
+            max: 255,
+        };
+        assert_eq!(
+            e.to_string(),
+            "Invalid secret key length: `300` bytes, expected `0` to `255` bytes"
+        );
+        let e = Error::InvalidInputLength {
+            actual: 7,
+            block_size: 8,
+        };
+        assert_eq!(
+            e.to_string(),
+            "Invalid input length: `7` bytes, expected a multiple of `8` bytes"
+        );
+    }
 }
@@ -1,18 +1,69 @@
-use crate::{Error, SecretKey, Word};
+use std::marker::PhantomData;
+
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{Error, SecretKeyRef, Word};
+
+/// Maximum number of rounds, `r` is a single byte (RFC 2040).
+pub(crate) const MAX_NUMBER_OF_ROUNDS: usize = 255;
+
+/// Maximum number of words of a table, the one with `MAX_NUMBER_OF_ROUNDS` rounds.
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "encrypt", feature = "decrypt")
+))]
+pub(crate) const MAX_LEN: usize = 2 * (MAX_NUMBER_OF_ROUNDS + 1);
+
+/// Storage of the words of an expanded key table.
+///
+/// Implementations must securely zero the words when dropped:
+/// owned buffers (`Vec<W>`, `Box<[W]>` or `[W; N]`) are wrapped into `Zeroizing`,
+/// buffers borrowed e.g. from an arena or a static into `BorrowedStorage`.
+pub trait KeyTableStorage<W: Word>: AsRef<[W]> + AsMut<[W]> {}
+
+impl<W: Word, Z: Zeroize + AsRef<[W]> + AsMut<[W]>> KeyTableStorage<W> for Zeroizing<Z> {}
+
+/// A borrowed key table buffer, securely zeroed when dropped.
+#[derive(Debug)]
+pub struct BorrowedStorage<'a, W: Word>(pub &'a mut [W]);
+
+impl<W: Word> AsRef<[W]> for BorrowedStorage<'_, W> {
+    fn as_ref(&self) -> &[W] {
+        self.0
+    }
+}
+
+impl<W: Word> AsMut<[W]> for BorrowedStorage<'_, W> {
+    fn as_mut(&mut self) -> &mut [W] {
+        self.0
+    }
+}
+
+impl<W: Word> KeyTableStorage<W> for BorrowedStorage<'_, W> {}
+
+impl<W: Word> Drop for BorrowedStorage<'_, W> {
+    fn drop(&mut self) {
+        self.0.iter_mut().zeroize();
+    }
+}
 
 /// Expanded key table.
 ///
 /// The key-expansion algorithm has a certain amount of "one-wayness":
 /// it is not so easy to determine the secret key from expanded key table.
 /// Hence we're not using [secrecy](https://docs.rs/secrecy/latest/secrecy/) here.
-#[derive(Debug, PartialEq, Eq)]
-pub struct ExpandedKeyTable<W>(pub(crate) Vec<W>);
+/// Still, the table is all one needs to encrypt and decrypt,
+/// so it is securely zeroed when dropped (see `KeyTableStorage`).
+///
+/// The words are kept in a heap-allocated vector by default,
+/// `ExpandedKeyTable::new_in` expands the key into any other storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandedKeyTable<W: Word, S: KeyTableStorage<W> = Zeroizing<Vec<W>>>(
+    pub(crate) S,
+    PhantomData<W>,
+);
 
 impl<W: Word> ExpandedKeyTable<W> {
-    /// Maximum number of rounds
-    /// according to the RC5 original paper.
-    const MAX_NUMBER_OF_ROUNDS: usize = 256;
-
     /// Creates an expanded key table.
     ///
     /// The key-expansion routine expands the users secret key to fill the
@@ -21,27 +72,155 @@ impl<W: Word> ExpandedKeyTable<W> {
     /// The algorithm uses two "magic constants" `P` and `Q`
     /// (see the `HasPQ`'s trait implementations for `u8`, `u16`, `u32` and `u64`)
     /// and consists of three simple algorithmic parts:
-    /// 1. Converting the secret key from bytes to words (see `SecretKey::to_words`).
+    /// 1. Converting the secret key from bytes to words (see `SecretKeyRef::to_words`).
     /// 2. Initializing the vector S (expanded key table).
     /// 3. Mixing in the secret key.
-    pub fn new(key: &SecretKey, number_of_rounds: usize) -> Result<Self, Error> {
-        if number_of_rounds > Self::MAX_NUMBER_OF_ROUNDS {
-            return Err(Error::InvalidNumberOfRounds(number_of_rounds));
+    pub fn new<'k>(
+        key: impl Into<SecretKeyRef<'k>>,
+        number_of_rounds: usize,
+    ) -> Result<Self, Error> {
+        let len = Self::len(number_of_rounds)?;
+        Self::new_in(key, number_of_rounds, Zeroizing::new(vec![W::zero(); len]))
+    }
+}
+
+impl<W: Word, S: KeyTableStorage<W>> ExpandedKeyTable<W, S> {
+    /// Number of words of a table with a given number of rounds: `t = 2 * (r + 1)`.
+    pub fn len(number_of_rounds: usize) -> Result<usize, Error> {
+        if number_of_rounds > MAX_NUMBER_OF_ROUNDS {
+            return Err(Error::InvalidNumberOfRounds {
+                actual: number_of_rounds,
+                max: MAX_NUMBER_OF_ROUNDS,
+            });
         }
+        Ok(2 * (number_of_rounds + 1))
+    }
+
+    /// Creates an expanded key table (see `ExpandedKeyTable::new`) in a given storage,
+    /// e.g. a static buffer on embedded targets or a buffer borrowed from an arena.
+    ///
+    /// The storage must be exactly `ExpandedKeyTable::len` words long,
+    /// its previous contents are overwritten.
+    /// `RC5::from_key_table` creates an instance encrypting with the table.
+    pub fn new_in<'k>(
+        key: impl Into<SecretKeyRef<'k>>,
+        number_of_rounds: usize,
+        mut storage: S,
+    ) -> Result<Self, Error> {
+        check_len::<W, S>(&mut storage, number_of_rounds)?;
+        Ok(KeyExpansion::start(key, storage).finish())
+    }
 
-        // Create and initialize a key table.
-        // Expanded key table resembles a vector of t = 2 * (r + 1) random binary words.
-        let mut key_table = Self::setup(2 * (number_of_rounds + 1));
-        // Mixin the user's secret key.
-        Self::mixin(&mut key_table, key.to_words());
+    /// Words of the table.
+    pub fn words(&self) -> &[W] {
+        self.0.as_ref()
+    }
+}
 
-        Ok(Self(key_table))
+/// Checks that a storage is `ExpandedKeyTable::len` words long.
+fn check_len<W: Word, S: KeyTableStorage<W>>(
+    storage: &mut S,
+    number_of_rounds: usize,
+) -> Result<(), Error> {
+    let expected = ExpandedKeyTable::<W, S>::len(number_of_rounds)?;
+    let actual = storage.as_mut().len();
+    if actual != expected {
+        return Err(Error::InvalidKeyTableLength { actual, expected });
     }
+    Ok(())
+}
 
-    /// Creates a key table initialized to a particular fixed (key-independent)
+/// Incremental, resumable key expansion.
+///
+/// `ExpandedKeyTable::new` mixes the secret key into the table
+/// in `3 * max(t, c)` iterations at once. Firmware with tight interrupt
+/// or watchdog budgets can instead spread them over idle time slices,
+/// calling `KeyExpansion::step` until it is done.
+///
+/// The intermediate state (including the key words) is as sensitive
+/// as the secret key itself, so it is securely zeroed when dropped.
+pub struct KeyExpansion<W: Word, S: KeyTableStorage<W> = Zeroizing<Vec<W>>> {
+    key_table: S,
+    /// The secret key words `L`.
+    key_words: Zeroizing<Vec<W>>,
+    /// The running words `A` and `B`.
+    ab: Zeroizing<[W; 2]>,
+    /// Indices into the key table and the key words.
+    i: usize,
+    j: usize,
+    /// Number of mixing iterations left.
+    remaining: usize,
+}
+
+impl<W: Word> KeyExpansion<W> {
+    /// Starts expanding a key into a table with a given number of rounds.
+    pub fn new<'k>(
+        key: impl Into<SecretKeyRef<'k>>,
+        number_of_rounds: usize,
+    ) -> Result<Self, Error> {
+        let len = ExpandedKeyTable::<W>::len(number_of_rounds)?;
+        Self::new_in(key, number_of_rounds, Zeroizing::new(vec![W::zero(); len]))
+    }
+}
+
+impl<W: Word, S: KeyTableStorage<W>> KeyExpansion<W, S> {
+    /// Starts expanding a key into a given storage (see `ExpandedKeyTable::new_in`).
+    pub fn new_in<'k>(
+        key: impl Into<SecretKeyRef<'k>>,
+        number_of_rounds: usize,
+        mut storage: S,
+    ) -> Result<Self, Error> {
+        check_len::<W, S>(&mut storage, number_of_rounds)?;
+        Ok(Self::start(key, storage))
+    }
+
+    /// Converts the key into words and initializes the table,
+    /// which takes `t` additions. The storage must be `t` words long.
+    fn start<'k>(key: impl Into<SecretKeyRef<'k>>, mut key_table: S) -> Self {
+        let key_words = Zeroizing::new(key.into().to_words());
+        Self::setup(key_table.as_mut());
+
+        let remaining = 3 * key_table.as_ref().len().max(key_words.len()); // 3 * max(t, c)
+        Self {
+            key_table,
+            key_words,
+            ab: Zeroizing::new([W::zero(); 2]),
+            i: 0,
+            j: 0,
+            remaining,
+        }
+    }
+
+    /// Number of mixing iterations left.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Returns `true` once every iteration is done.
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Runs at most `n` mixing iterations and
+    /// returns the number of iterations left.
+    pub fn step(&mut self, n: usize) -> usize {
+        for _ in 0..n.min(self.remaining) {
+            self.mix();
+        }
+        self.remaining -= n.min(self.remaining);
+        self.remaining
+    }
+
+    /// Runs the iterations left and returns the expanded key table.
+    pub fn finish(mut self) -> ExpandedKeyTable<W, S> {
+        self.step(self.remaining);
+        ExpandedKeyTable(self.key_table, PhantomData)
+    }
+
+    /// Initializes a key table to a particular fixed (key-independent)
     /// pseudo-random bit pattern, using an arithmetic progression
     /// modulo `2^w` determined by magic constants `P` and `Q`.
-    fn setup(len: usize) -> Vec<W> {
+    fn setup(key_table: &mut [W]) {
         // Pseudo-code:
         //
         // S[0] = P
@@ -52,19 +231,15 @@ impl<W: Word> ExpandedKeyTable<W> {
         //
         // S - Key-expansion table
 
-        let mut key_table = vec![W::zero(); len];
-
         key_table[0] = W::p();
         for i in 1..key_table.len() {
             key_table[i] = key_table[i - 1].wrapping_add(&W::q());
         }
-
-        key_table
     }
 
-    /// Mixins the user's secret key in 3 passes over a
-    /// key table vector and a vector of key words.
-    fn mixin(key_table: &mut Vec<W>, key_words: Vec<W>) {
+    /// Runs a single iteration of mixing the user's secret key into the table,
+    /// there are 3 passes over the key table and the key words in total.
+    fn mix(&mut self) {
         // Pseudo-code:
         //
         // i = j = 0
@@ -85,42 +260,36 @@ impl<W: Word> ExpandedKeyTable<W> {
         // t = table.len() - Length of the key-expansion table
         // c = key_words.len() - Length of the key words vector
 
-        let mut key_words = key_words;
+        let key_table = self.key_table.as_mut();
+        let key_words = &mut self.key_words;
+        let [a, b] = &mut *self.ab;
+        let (i, j) = (self.i, self.j);
 
-        let (mut a, mut b) = (W::zero(), W::zero());
-        let (mut i, mut j) = (0, 0);
+        // Note that we use the `rotate_left` function here instead of
+        // our custom `rotate_left_by` (<<<). It is safe because we known that 3 is
+        // less than the size of the smallest word (u8): 3 < 8.
 
-        let mix_steps = key_table.len().max(key_words.len()); // max(t, c)
+        key_table[i] = key_table[i].wrapping_add(a).wrapping_add(b).rotate_left(3);
+        *a = key_table[i];
 
-        for _ in 0..(3 * mix_steps) {
-            // Note that we use the `rotate_left` function here instead of
-            // our custom `rotate_left_by` (<<<). It is safe because we known that 3 is
-            // less than the size of the smallest word (u16): 3 < 16.
+        // And here we use the `rotate_left_by`, because the sum of
+        // `a + b` can be greater than 64 (the size of `u64`).
 
-            key_table[i] = key_table[i]
-                .wrapping_add(&a)
-                .wrapping_add(&b)
-                .rotate_left(3);
-            a = key_table[i];
+        key_words[j] = key_words[j]
+            .wrapping_add(a)
+            .wrapping_add(b)
+            .rotate_left_by(a.wrapping_add(b));
+        *b = key_words[j];
 
-            // And here we use the `rotate_left_by`, because the sum of
-            // `a + b` can be greater than 64 (the size of `u64`).
-
-            key_words[j] = key_words[j]
-                .wrapping_add(&a)
-                .wrapping_add(&b)
-                .rotate_left_by(a.wrapping_add(&b));
-            b = key_words[j];
-
-            i = (i + 1) % key_table.len();
-            j = (j + 1) % key_words.len();
-        }
+        self.i = (i + 1) % key_table.len();
+        self.j = (j + 1) % key_words.len();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::SecretKey;
 
     #[test]
     fn new() {
@@ -132,11 +301,65 @@ mod tests {
         let key1_table = ExpandedKeyTable::<u16>::new(&key1, 12).unwrap();
         assert_eq!(
             key1_table,
-            ExpandedKeyTable(vec![
-                35335, 28312, 22618, 34867, 45234, 46162, 22833, 59388, 47522, 35862, 3067, 9299,
-                32031, 62182, 903, 8243, 57179, 45493, 29169, 52645, 27594, 36810, 63883, 25203,
-                40548, 8227
-            ])
+            ExpandedKeyTable(
+                Zeroizing::new(vec![
+                    35335, 28312, 22618, 34867, 45234, 46162, 22833, 59388, 47522, 35862, 3067,
+                    9299, 32031, 62182, 903, 8243, 57179, 45493, 29169, 52645, 27594, 36810, 63883,
+                    25203, 40548, 8227
+                ]),
+                PhantomData
+            )
         );
     }
+
+    #[test]
+    fn new_in() {
+        let key = SecretKey::new(vec![0x42; 16]).unwrap();
+        let table = ExpandedKeyTable::<u32>::new(&key, 12).unwrap();
+
+        let array = ExpandedKeyTable::new_in(&key, 12, Zeroizing::new([0u32; 26])).unwrap();
+        assert_eq!(array.words(), table.words());
+
+        let mut arena = [7u32; 64];
+        {
+            let borrowed =
+                ExpandedKeyTable::new_in(&key, 12, BorrowedStorage(&mut arena[..26])).unwrap();
+            assert_eq!(borrowed.words(), table.words());
+        }
+        assert!(arena.iter().take(26).all(|w| *w == 0));
+
+        assert!(matches!(
+            ExpandedKeyTable::new_in(&key, 12, Zeroizing::new(vec![0u32; 25])),
+            Err(Error::InvalidKeyTableLength {
+                actual: 25,
+                expected: 26
+            })
+        ));
+    }
+
+    #[test]
+    fn key_expansion() {
+        let key = SecretKey::new(vec![0x42; 20]).unwrap();
+        let table = ExpandedKeyTable::<u32>::new(&key, 12).unwrap();
+
+        // 3 * max(t, c) = 3 * max(26, 5).
+        let mut expansion = KeyExpansion::<u32>::new(&key, 12).unwrap();
+        assert_eq!(expansion.remaining(), 78);
+        assert_eq!(expansion.step(10), 68);
+        while expansion.step(7) > 0 {}
+        assert!(expansion.is_done());
+        assert_eq!(expansion.step(1), 0);
+        assert_eq!(expansion.finish(), table);
+
+        let mut expansion = KeyExpansion::<u32>::new(&key, 12).unwrap();
+        expansion.step(30);
+        assert_eq!(expansion.finish(), table);
+
+        let mut arena = [0u32; 26];
+        let expansion = KeyExpansion::new_in(&key, 12, BorrowedStorage(&mut arena)).unwrap();
+        assert_eq!(expansion.finish().words(), table.words());
+
+        assert!(KeyExpansion::<u32>::new(&key, 255).is_ok());
+        assert!(KeyExpansion::<u32>::new(&key, 256).is_err());
+    }
 }
@@ -0,0 +1,338 @@
+//! Encryption of whole files.
+//!
+//! `encrypt_file` and `decrypt_file` stream a file through a mode of operation
+//! a chunk at a time, so memory use doesn't depend on the size of the file.
+//! The output is written to a temporary file next to the destination, synced
+//! to disk and atomically renamed over it: after a crash or a power loss the
+//! destination holds either its previous contents or the complete output,
+//! never a part of it. A failed decryption (e.g. of a truncated file)
+//! leaves the destination untouched as well.
+//!
+//! An encrypted file is a random IV of a block followed by the cipher text.
+//! The mode isn't recorded, a file has to be decrypted with the mode
+//! it was encrypted with:
+//!
+//! * `Mode::CbcPad` - RC5-CBC-Pad (RFC 2040), 1 to block size bytes of padding.
+//! * `Mode::Cfb` and `Mode::Cfb8` - cipher text as long as the plain text.
+//!
+//! None of them is authenticated: tampering with an encrypted file goes undetected.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    ffi::OsString,
+    fmt::Debug,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use rand::{thread_rng, RngCore};
+use zeroize::Zeroizing;
+
+use crate::{
+    audit::Operation,
+    block_cipher::{BlockCipher, MAX_BLOCK_SIZE},
+    control_block::Mode,
+    modes::{self, Cfb, Cfb8},
+    Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// Number of bytes read at a time, a multiple of every block size.
+const CHUNK_SIZE: usize = 4096;
+
+/// Encrypts the file at `src` into the file at `dst`, replacing it atomically.
+///
+/// Fails with `Error::UnsupportedMode` for modes other than `CbcPad`, `Cfb` and `Cfb8`.
+pub fn encrypt_file<W: Word>(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    cipher: &RC5<W>,
+    mode: Mode,
+) -> Result<(), Error>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    let block_size = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+    let mut iv = [0; MAX_BLOCK_SIZE];
+    thread_rng().fill_bytes(&mut iv[..block_size]);
+    let mut chain = Chain::new(cipher, mode, &iv[..block_size])?;
+    let mut input = File::open(src)?;
+
+    replace(dst.as_ref(), |output| {
+        output.write_all(&iv[..block_size])?;
+        let mut buf = Zeroizing::new(vec![0; CHUNK_SIZE + block_size]);
+        loop {
+            let n = read_full(&mut input, &mut buf[..CHUNK_SIZE])?;
+            let last = n < CHUNK_SIZE;
+            let len = match &mut chain {
+                Chain::CbcPad(iv) => {
+                    let mut len = n;
+                    if last {
+                        let padding = block_size - n % block_size;
+                        len += padding;
+                        buf[n..len].fill(padding as u8);
+                    }
+                    modes::cbc_encrypt(cipher, &iv[..block_size], &mut buf[..len])?;
+                    iv[..block_size].copy_from_slice(&buf[len - block_size..len]);
+                    cipher.audit(Operation::Encrypt, n);
+                    len
+                }
+                Chain::Cfb(cfb) => {
+                    cfb.encrypt(&mut buf[..n]);
+                    n
+                }
+                Chain::Cfb8(cfb8) => {
+                    cfb8.encrypt(&mut buf[..n]);
+                    n
+                }
+            };
+            output.write_all(&buf[..len])?;
+            if last {
+                return Ok(());
+            }
+        }
+    })
+}
+
+/// Decrypts a file produced by `encrypt_file` with the same mode,
+/// replacing the file at `dst` atomically.
+///
+/// Fails with `Error::UnsupportedMode` for modes other than `CbcPad`, `Cfb` and `Cfb8`.
+pub fn decrypt_file<W: Word>(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    cipher: &RC5<W>,
+    mode: Mode,
+) -> Result<(), Error>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    let block_size = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+    let mut input = File::open(src)?;
+    let mut iv = [0; MAX_BLOCK_SIZE];
+    let n = read_full(&mut input, &mut iv[..block_size])?;
+    if n < block_size {
+        return Err(Error::CiphertextTooShort {
+            actual: n,
+            min: block_size,
+        });
+    }
+    let mut chain = Chain::new(cipher, mode, &iv[..block_size])?;
+
+    replace(dst.as_ref(), |output| {
+        let mut buf = Zeroizing::new(vec![0; CHUNK_SIZE]);
+        // Number of bytes held in `buf`.
+        let mut len = 0;
+        loop {
+            len += read_full(&mut input, &mut buf[len..])?;
+            let last = len < CHUNK_SIZE;
+            match &mut chain {
+                Chain::CbcPad(iv) => {
+                    // The last block is held back until the end of the file,
+                    // which is where the padding is.
+                    let n = if last { len } else { len - block_size };
+                    let mut next_iv = [0; MAX_BLOCK_SIZE];
+                    if n >= block_size {
+                        next_iv[..block_size].copy_from_slice(&buf[n - block_size..n]);
+                    }
+                    modes::cbc_decrypt(cipher, &iv[..block_size], &mut buf[..n])?;
+                    *iv = next_iv;
+                    cipher.audit(Operation::Decrypt, n);
+
+                    if last {
+                        let mut plaintext = Zeroizing::new(buf[..n].to_vec());
+                        modes::unpad(&mut plaintext, block_size)?;
+                        output.write_all(&plaintext)?;
+                        return Ok(());
+                    }
+                    output.write_all(&buf[..n])?;
+                    buf.copy_within(n..len, 0);
+                    len -= n;
+                }
+                Chain::Cfb(cfb) => {
+                    cfb.decrypt(&mut buf[..len]);
+                    output.write_all(&buf[..len])?;
+                    len = 0;
+                }
+                Chain::Cfb8(cfb8) => {
+                    cfb8.decrypt(&mut buf[..len]);
+                    output.write_all(&buf[..len])?;
+                    len = 0;
+                }
+            }
+            if last {
+                return Ok(());
+            }
+        }
+    })
+}
+
+/// State of a mode of operation between chunks.
+enum Chain<W: Word> {
+    /// The IV of the next chunk, the last cipher text block of the previous one.
+    CbcPad([u8; MAX_BLOCK_SIZE]),
+    Cfb(Cfb<W>),
+    Cfb8(Cfb8<W>),
+}
+
+impl<W: Word> Chain<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    fn new(cipher: &RC5<W>, mode: Mode, iv: &[u8]) -> Result<Self, Error> {
+        match mode {
+            Mode::CbcPad => {
+                let mut block = [0; MAX_BLOCK_SIZE];
+                block[..iv.len()].copy_from_slice(iv);
+                Ok(Self::CbcPad(block))
+            }
+            Mode::Cfb => Ok(Self::Cfb(Cfb::new(cipher.clone(), iv)?)),
+            Mode::Cfb8 => Ok(Self::Cfb8(Cfb8::new(cipher.clone(), iv)?)),
+            mode => Err(Error::UnsupportedMode(mode)),
+        }
+    }
+}
+
+/// Reads until `buf` is full or the end of file, returns the number of bytes read.
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match input.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Writes a temporary file next to `path` with `write`, syncs it to disk
+/// and atomically renames it over `path`.
+///
+/// The temporary file is removed if anything fails.
+fn replace(path: &Path, write: impl FnOnce(&mut File) -> Result<(), Error>) -> Result<(), Error> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let (tmp_path, mut tmp) = loop {
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(name);
+        tmp_name.push(format!(".{:016x}.tmp", thread_rng().next_u64()));
+        let tmp_path = path.with_file_name(tmp_name);
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+        {
+            Ok(file) => break (tmp_path, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let result = write(&mut tmp)
+        .and_then(|()| Ok(tmp.sync_all()?))
+        .and_then(|()| Ok(fs::rename(&tmp_path, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
+    }
+
+    // Make the rename itself durable, where directories can be synced.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A fresh directory in the temporary directory of the system.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rc5-fs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn round_trips() {
+        let dir = temp_dir("round-trips");
+        let (src, enc, dec) = (dir.join("src"), dir.join("enc"), dir.join("dec"));
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+
+        for mode in [Mode::CbcPad, Mode::Cfb, Mode::Cfb8] {
+            for len in [0, 1, 8, 4095, 4096, 4097, 10000] {
+                let pt = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+                fs::write(&src, &pt).unwrap();
+                encrypt_file(&src, &enc, &rc5, mode).unwrap();
+                let ct = fs::read(&enc).unwrap();
+                match mode {
+                    Mode::CbcPad => assert_eq!(ct.len(), 8 + (len / 8 + 1) * 8),
+                    _ => assert_eq!(ct.len(), 8 + len),
+                }
+
+                decrypt_file(&enc, &dec, &rc5, mode).unwrap();
+                assert_eq!(fs::read(&dec).unwrap(), pt, "{:?} {}", mode, len);
+            }
+        }
+
+        // The output matches the modes, with the IV in front.
+        fs::write(&src, [7; 20]).unwrap();
+        encrypt_file(&src, &enc, &rc5, Mode::Cfb).unwrap();
+        let ct = fs::read(&enc).unwrap();
+        let mut data = [7; 20];
+        Cfb::new(rc5.clone(), &ct[..8]).unwrap().encrypt(&mut data);
+        assert_eq!(ct[8..], data);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failures_keep_destination() {
+        let dir = temp_dir("failures");
+        let (src, enc, dec) = (dir.join("src"), dir.join("enc"), dir.join("dec"));
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        fs::write(&src, [7; 5000]).unwrap();
+        fs::write(&dec, b"previous").unwrap();
+
+        assert_eq!(
+            encrypt_file(&src, &enc, &rc5, Mode::Ecb),
+            Err(Error::UnsupportedMode(Mode::Ecb))
+        );
+        assert!(!enc.exists());
+
+        encrypt_file(&src, &enc, &rc5, Mode::CbcPad).unwrap();
+        let ct = fs::read(&enc).unwrap();
+        for truncated in [&ct[..4], &ct[..ct.len() - 3], &ct[..8]] {
+            fs::write(&enc, truncated).unwrap();
+            assert!(decrypt_file(&enc, &dec, &rc5, Mode::CbcPad).is_err());
+        }
+        // Changes the last padding byte from 8 to 24.
+        let mut tampered = ct.clone();
+        let len = tampered.len();
+        tampered[len - 9] ^= 0x10;
+        fs::write(&enc, &tampered).unwrap();
+        assert_eq!(
+            decrypt_file(&enc, &dec, &rc5, Mode::CbcPad),
+            Err(Error::InvalidPadding)
+        );
+
+        // Neither the destination nor a temporary file has been written.
+        assert_eq!(fs::read(&dec).unwrap(), b"previous");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+//! IV-prefixed cipher text.
+//!
+//! Many existing systems transmit a random initialization vector by simply
+//! prepending it to the cipher text, without any other header (unlike
+//! `ControlBlockV2`). These helpers generate the IV, prepend it on encryption
+//! and split it off on decryption, so callers don't handle IVs themselves:
+//!
+//! * `encrypt_cbc` - `IV || RC5-CBC(PKCS #7 padded plain text)`, with a random IV of a block.
+//! * `encrypt_ctr` - `nonce || RC5-CTR(plain text)`, with a random nonce word
+//!   (little-endian) and the keystream of `Keystream`.
+//!
+//! Random nonces of a single word collide after about `2^(w/2)` messages
+//! under the same key (e.g. 65536 messages for RC5-32), so CTR is only
+//! available for RC5-64, whose nonces collide after about `2^32` messages.
+//!
+//! None of them is authenticated: tampering with the cipher text goes undetected,
+//! and services answering with padding errors can leak the CBC plain text.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+#[cfg(feature = "encrypt")]
+use rand::{thread_rng, RngCore};
+use zeroize::Zeroizing;
+
+#[cfg(feature = "encrypt")]
+use crate::Keystream;
+use crate::{
+    audit::Operation, block_cipher::BlockCipher, modes, Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// Encrypts plain text of any length in CBC mode with a random IV
+/// and PKCS #7 padding, prepending the IV to the cipher text.
+#[cfg(feature = "encrypt")]
+pub fn encrypt_cbc<W: Word>(rc5: &RC5<W>, plaintext: impl AsRef<[u8]>) -> Vec<u8>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    let block_size = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+    let plaintext = plaintext.as_ref();
+    let padding = block_size - plaintext.len() % block_size;
+
+    let mut data = Zeroizing::new(vec![0; block_size]);
+    thread_rng().fill_bytes(&mut data);
+    data.extend_from_slice(plaintext);
+    let len = data.len() + padding;
+    data.resize(len, padding as u8);
+
+    let (iv, blocks) = data.split_at_mut(block_size);
+    modes::cbc_encrypt(rc5, iv, blocks).expect("padded to the block size");
+    rc5.audit(Operation::Encrypt, plaintext.len());
+    std::mem::take(&mut *data)
+}
+
+/// Decrypts cipher text produced by `encrypt_cbc`, removing the padding.
+#[cfg(feature = "decrypt")]
+pub fn decrypt_cbc<W: Word>(rc5: &RC5<W>, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, Error>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    let block_size = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+    let ciphertext = ciphertext.as_ref();
+    // The IV and at least one block of padding.
+    if ciphertext.len() < 2 * block_size {
+        return Err(Error::CiphertextTooShort {
+            actual: ciphertext.len(),
+            min: 2 * block_size,
+        });
+    }
+    if !ciphertext.len().is_multiple_of(block_size) {
+        return Err(Error::InvalidInputLength {
+            actual: ciphertext.len(),
+            block_size,
+        });
+    }
+
+    let (iv, ciphertext) = ciphertext.split_at(block_size);
+    let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+    modes::cbc_decrypt(rc5, iv, &mut plaintext)?;
+    rc5.audit(Operation::Decrypt, ciphertext.len());
+    modes::unpad(&mut plaintext, block_size)?;
+    Ok(std::mem::take(&mut *plaintext))
+}
+
+/// Encrypts plain text of any length in counter mode with a random nonce,
+/// prepending the nonce to the cipher text.
+///
+/// Fails for word sizes other than 64 bits,
+/// or if the plain text is longer than the keystream of a nonce.
+#[cfg(feature = "encrypt")]
+pub fn encrypt_ctr<W: Word>(rc5: &RC5<W>, plaintext: impl AsRef<[u8]>) -> Result<Vec<u8>, Error>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    check_ctr_word_size::<W>()?;
+    let mut nonce = <W as FromLeBytes>::T::default();
+    thread_rng().fill_bytes(nonce.as_mut());
+
+    let mut data = Zeroizing::new(nonce.as_mut().to_vec());
+    data.extend_from_slice(plaintext.as_ref());
+    Keystream::new(rc5, W::from_le_bytes(nonce)).apply(&mut data[W::BYTES..])?;
+    rc5.audit(Operation::Encrypt, data.len() - W::BYTES);
+    Ok(std::mem::take(&mut *data))
+}
+
+/// Decrypts cipher text produced by `encrypt_ctr`.
+///
+/// Fails for word sizes other than 64 bits.
+#[cfg(feature = "encrypt")]
+pub fn decrypt_ctr<W: Word>(rc5: &RC5<W>, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, Error>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    check_ctr_word_size::<W>()?;
+    let ciphertext = ciphertext.as_ref();
+    if ciphertext.len() < W::BYTES {
+        return Err(Error::CiphertextTooShort {
+            actual: ciphertext.len(),
+            min: W::BYTES,
+        });
+    }
+
+    let (prefix, ciphertext) = ciphertext.split_at(W::BYTES);
+    let mut nonce = <W as FromLeBytes>::T::default();
+    nonce.as_mut().copy_from_slice(prefix);
+
+    let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+    Keystream::new(rc5, W::from_le_bytes(nonce)).apply(&mut plaintext)?;
+    rc5.audit(Operation::Decrypt, plaintext.len());
+    Ok(std::mem::take(&mut *plaintext))
+}
+
+/// Random nonces of smaller words collide too soon.
+#[cfg(feature = "encrypt")]
+fn check_ctr_word_size<W: Word>() -> Result<(), Error> {
+    match W::BITS {
+        64 => Ok(()),
+        bits => Err(Error::InvalidWordSize(bits)),
+    }
+}
+
+#[cfg(all(test, feature = "encrypt"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn cbc() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        for len in [0, 1, 7, 8, 9, 100] {
+            let pt = vec![7; len];
+            let ct = encrypt_cbc(&rc5, &pt);
+            assert_eq!(ct.len(), 8 + (len / 8 + 1) * 8);
+            assert_eq!(decrypt_cbc(&rc5, &ct).unwrap(), pt);
+        }
+
+        // Random IVs.
+        assert_ne!(encrypt_cbc(&rc5, [7; 8]), encrypt_cbc(&rc5, [7; 8]));
+
+        // The first block is `E(IV ^ P)`.
+        let ct = encrypt_cbc(&rc5, [1; 8]);
+        let mut block = [1; 8];
+        modes::xor(&mut block, &ct[..8]);
+        assert_eq!(rc5.encode(block), ct[8..16]);
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn cbc_invalid() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        assert_eq!(
+            decrypt_cbc(&rc5, [0; 8]),
+            Err(Error::CiphertextTooShort { actual: 8, min: 16 })
+        );
+        assert!(matches!(
+            decrypt_cbc(&rc5, [0; 17]),
+            Err(Error::InvalidInputLength { actual: 17, .. })
+        ));
+
+        let mut ct = encrypt_cbc(&rc5, [7; 5]);
+        // Flips the last padding byte.
+        ct[7] ^= 0x01;
+        assert_eq!(decrypt_cbc(&rc5, &ct), Err(Error::InvalidPadding));
+    }
+
+    #[test]
+    fn ctr() {
+        let rc5 = RC5::<u64>::new(vec![0x42; 16]).unwrap();
+        for len in [0, 1, 7, 100] {
+            let pt = vec![7; len];
+            let ct = encrypt_ctr(&rc5, &pt).unwrap();
+            assert_eq!(ct.len(), 8 + len);
+            assert_eq!(decrypt_ctr(&rc5, &ct).unwrap(), pt);
+        }
+
+        let ct = encrypt_ctr(&rc5, [0; 32]).unwrap();
+        let nonce = u64::from_le_bytes(ct[..8].try_into().unwrap());
+        let keystream = Iterator::take(Keystream::new(&rc5, nonce), 32).collect::<Vec<_>>();
+        assert_eq!(ct[8..], keystream);
+
+        assert_eq!(
+            decrypt_ctr(&rc5, [0; 7]),
+            Err(Error::CiphertextTooShort { actual: 7, min: 8 })
+        );
+    }
+
+    #[test]
+    fn ctr_small_words() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        assert_eq!(encrypt_ctr(&rc5, [0; 8]), Err(Error::InvalidWordSize(32)));
+        assert_eq!(decrypt_ctr(&rc5, [0; 12]), Err(Error::InvalidWordSize(32)));
+        let rc5 = RC5::<u8>::new(vec![0x42; 16]).unwrap();
+        assert_eq!(encrypt_ctr(&rc5, [0; 8]), Err(Error::InvalidWordSize(8)));
+    }
+}
@@ -0,0 +1,190 @@
+//! Block-batch kernels selected at runtime.
+//!
+//! A single compiled binary can run on machines with different instruction sets.
+//! When an RC5 instance is created, the best kernel available on the current
+//! machine is detected, and bulk encryption and decryption are dispatched to it.
+//!
+//! The AVX2 kernel processes 8 blocks of RC5-32 at once.
+//! There are no vector kernels for other word sizes and architectures yet,
+//! they always use the scalar block function.
+
+use crate::Word;
+
+/// A block-batch kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    /// Portable block-by-block implementation.
+    Scalar,
+    /// 8 blocks of RC5-32 at once using 256-bit AVX2 vectors.
+    Avx2,
+}
+
+impl Kernel {
+    /// Detects the best kernel for a given word available on the current machine.
+    pub(crate) fn detect<W: Word>() -> Self {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if W::BITS == 32 && is_x86_feature_detected!("avx2") {
+            return Self::Avx2;
+        }
+
+        Self::Scalar
+    }
+}
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "encrypt", feature = "decrypt")
+))]
+pub(crate) mod avx2 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use zeroize::Zeroize;
+
+    /// Number of bytes processed at once: 8 blocks of 2 words of 4 bytes.
+    pub(crate) const BATCH_SIZE: usize = 64;
+
+    /// Encrypts a buffer consisting of whole batches in place.
+    ///
+    /// Uses the same algorithm as `Block::encode`, but for 8 blocks at once.
+    ///
+    /// # Safety
+    ///
+    /// The current machine must support AVX2.
+    #[target_feature(enable = "avx2")]
+    #[cfg(feature = "encrypt")]
+    pub(crate) unsafe fn encrypt(key_table: &[u32], rounds: usize, data: &mut [u8]) {
+        let s = |i: usize| _mm256_set1_epi32(key_table[i] as i32);
+        for batch in data.chunks_exact_mut(BATCH_SIZE) {
+            let (mut a, mut b) = load(batch);
+
+            a = _mm256_add_epi32(a, s(0));
+            b = _mm256_add_epi32(b, s(1));
+            for i in 1..=rounds {
+                a = _mm256_add_epi32(rotate_left(_mm256_xor_si256(a, b), b), s(2 * i));
+                b = _mm256_add_epi32(rotate_left(_mm256_xor_si256(b, a), a), s(2 * i + 1));
+            }
+
+            store(batch, a, b);
+        }
+    }
+
+    /// Decrypts a buffer consisting of whole batches in place.
+    ///
+    /// Uses the same algorithm as `Block::decode`, but for 8 blocks at once.
+    ///
+    /// # Safety
+    ///
+    /// The current machine must support AVX2.
+    #[target_feature(enable = "avx2")]
+    #[cfg(feature = "decrypt")]
+    pub(crate) unsafe fn decrypt(key_table: &[u32], rounds: usize, data: &mut [u8]) {
+        let s = |i: usize| _mm256_set1_epi32(key_table[i] as i32);
+        for batch in data.chunks_exact_mut(BATCH_SIZE) {
+            let (mut a, mut b) = load(batch);
+
+            for i in (1..=rounds).rev() {
+                b = _mm256_xor_si256(rotate_right(_mm256_sub_epi32(b, s(2 * i + 1)), a), a);
+                a = _mm256_xor_si256(rotate_right(_mm256_sub_epi32(a, s(2 * i)), b), b);
+            }
+            b = _mm256_sub_epi32(b, s(1));
+            a = _mm256_sub_epi32(a, s(0));
+
+            store(batch, a, b);
+        }
+    }
+
+    /// Loads the `A` and `B` words of 8 little-endian blocks into two vectors.
+    #[target_feature(enable = "avx2")]
+    fn load(batch: &[u8]) -> (__m256i, __m256i) {
+        let (mut a, mut b) = ([0u32; 8], [0u32; 8]);
+        for (i, block) in batch.chunks_exact(8).enumerate() {
+            a[i] = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+            b[i] = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+        }
+
+        // SAFETY: arrays are exactly 256 bits long, unaligned loads are allowed.
+        let vectors = unsafe {
+            (
+                _mm256_loadu_si256(a.as_ptr() as *const __m256i),
+                _mm256_loadu_si256(b.as_ptr() as *const __m256i),
+            )
+        };
+
+        // Words hold plaintext or ciphertext, wipe the copies.
+        a.zeroize();
+        b.zeroize();
+        vectors
+    }
+
+    /// Stores two vectors of `A` and `B` words into 8 little-endian blocks.
+    #[target_feature(enable = "avx2")]
+    fn store(batch: &mut [u8], a: __m256i, b: __m256i) {
+        let (mut aw, mut bw) = ([0u32; 8], [0u32; 8]);
+
+        // SAFETY: arrays are exactly 256 bits long, unaligned stores are allowed.
+        unsafe {
+            _mm256_storeu_si256(aw.as_mut_ptr() as *mut __m256i, a);
+            _mm256_storeu_si256(bw.as_mut_ptr() as *mut __m256i, b);
+        }
+
+        for (i, block) in batch.chunks_exact_mut(8).enumerate() {
+            block[..4].copy_from_slice(&aw[i].to_le_bytes());
+            block[4..].copy_from_slice(&bw[i].to_le_bytes());
+        }
+
+        aw.zeroize();
+        bw.zeroize();
+    }
+
+    /// Rotates every lane of `x` to the left by the corresponding lane of `n` modulo 32.
+    #[target_feature(enable = "avx2")]
+    #[cfg(feature = "encrypt")]
+    fn rotate_left(x: __m256i, n: __m256i) -> __m256i {
+        let n = _mm256_and_si256(n, _mm256_set1_epi32(31));
+        let m = _mm256_sub_epi32(_mm256_set1_epi32(32), n);
+        // Shifting by 32 or more bits yields zero, so `n = 0` works as expected.
+        _mm256_or_si256(_mm256_sllv_epi32(x, n), _mm256_srlv_epi32(x, m))
+    }
+
+    /// Rotates every lane of `x` to the right by the corresponding lane of `n` modulo 32.
+    #[target_feature(enable = "avx2")]
+    #[cfg(feature = "decrypt")]
+    fn rotate_right(x: __m256i, n: __m256i) -> __m256i {
+        let n = _mm256_and_si256(n, _mm256_set1_epi32(31));
+        let m = _mm256_sub_epi32(_mm256_set1_epi32(32), n);
+        _mm256_or_si256(_mm256_srlv_epi32(x, n), _mm256_sllv_epi32(x, m))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(all(feature = "encrypt", feature = "decrypt"))]
+    use crate::{SecretKey, RC5};
+
+    #[test]
+    #[cfg(all(feature = "encrypt", feature = "decrypt"))]
+    fn kernels_agree() {
+        let key = SecretKey::random(16);
+        let rc5 = RC5::<u32>::from_secret_key(&key, 12).unwrap();
+        let scalar = RC5 {
+            kernel: Kernel::Scalar,
+            ..RC5::<u32>::from_secret_key(&key, 12).unwrap()
+        };
+
+        // 3 whole batches and 3 remaining blocks.
+        let pt = SecretKey::random(3 * 64 + 3 * 8).expose_with(|bytes| bytes.to_vec());
+        let ct = rc5.encode(&pt);
+        assert_eq!(ct, scalar.encode(&pt));
+        assert_eq!(rc5.decode(&ct), pt);
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(Kernel::detect::<u16>(), Kernel::Scalar);
+        assert_eq!(Kernel::detect::<u64>(), Kernel::Scalar);
+    }
+}
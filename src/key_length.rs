@@ -0,0 +1,78 @@
+//! A validated length of a secret key.
+//!
+//! According to *4. The RC5 Algorithm* of the [original paper](https://www.grc.com/r&d/rc5.pdf),
+//! `b` is a byte, so a secret key is from 0 to 255 bytes long.
+
+use std::{convert::TryFrom, fmt};
+
+use crate::Error;
+
+/// Number of bytes in a secret key (`b`), from 0 to 255.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct KeyLength(u8);
+
+impl KeyLength {
+    /// The maximum key length.
+    pub const MAX: Self = Self(u8::MAX);
+
+    /// Validates a key length.
+    pub fn new(len: usize) -> Result<Self, Error> {
+        u8::try_from(len)
+            .map(Self)
+            .map_err(|_| Error::InvalidSecretKeyLength {
+                actual: len,
+                min: 0,
+                max: Self::MAX.into(),
+            })
+    }
+
+    /// The key length as a byte, as stored in a control block.
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl From<u8> for KeyLength {
+    fn from(len: u8) -> Self {
+        Self(len)
+    }
+}
+
+impl From<KeyLength> for usize {
+    fn from(len: KeyLength) -> Self {
+        len.0 as usize
+    }
+}
+
+impl TryFrom<usize> for KeyLength {
+    type Error = Error;
+
+    fn try_from(len: usize) -> Result<Self, Error> {
+        Self::new(len)
+    }
+}
+
+impl fmt::Display for KeyLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new() {
+        assert_eq!(KeyLength::new(0).unwrap().get(), 0);
+        assert_eq!(KeyLength::new(255).unwrap(), KeyLength::MAX);
+        assert!(matches!(
+            KeyLength::new(256),
+            Err(Error::InvalidSecretKeyLength {
+                actual: 256,
+                max: 255,
+                ..
+            })
+        ));
+    }
+}
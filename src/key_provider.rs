@@ -0,0 +1,91 @@
+//! Pluggable sources of key material.
+//!
+//! A key provider resolves a key ID to a secret key, so that integrations
+//! with Vault, AWS KMS, a database-backed keystore, etc. can be written
+//! outside of this crate and plugged into it.
+
+use std::{collections::HashMap, future::Future};
+
+use crate::{Error, SecretKey};
+
+/// A synchronous source of key material.
+pub trait KeyProvider {
+    /// Resolves a key ID to a secret key.
+    fn key(&self, key_id: &str) -> Result<SecretKey, Error>;
+}
+
+/// An asynchronous source of key material, e.g. a remote key management service.
+pub trait AsyncKeyProvider {
+    /// Resolves a key ID to a secret key.
+    fn key(&self, key_id: &str) -> impl Future<Output = Result<SecretKey, Error>> + Send;
+}
+
+/// An in-memory key provider, mostly useful for tests and examples.
+///
+/// Keys are moved into the provider, which owns them
+/// and hands out copies of them on request.
+impl KeyProvider for HashMap<String, SecretKey> {
+    fn key(&self, key_id: &str) -> Result<SecretKey, Error> {
+        self.get(key_id)
+            .map(|key| SecretKey::new(key.secret().clone()))
+            .unwrap_or_else(|| Err(Error::KeyNotFound(key_id.to_string())))
+    }
+}
+
+#[cfg(all(test, feature = "encrypt"))]
+mod tests {
+    use std::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+    use crate::RC5;
+
+    struct Remote(HashMap<String, SecretKey>);
+
+    impl AsyncKeyProvider for Remote {
+        async fn key(&self, key_id: &str) -> Result<SecretKey, Error> {
+            self.0.key(key_id)
+        }
+    }
+
+    /// Polls a future that never waits to completion.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut context = Context::from_waker(Waker::noop());
+        match pin!(future).poll(&mut context) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("future is not ready"),
+        }
+    }
+
+    fn keys() -> HashMap<String, SecretKey> {
+        let mut keys = HashMap::new();
+        keys.insert("a".to_string(), SecretKey::new(vec![1; 16]).unwrap());
+        keys
+    }
+
+    #[test]
+    fn key_provider() {
+        let keys = keys();
+        let pt = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let expected = RC5::<u32>::new(vec![1; 16]).unwrap().encode(&pt);
+
+        let rc5 = RC5::<u32>::from_key_provider(&keys, "a", 16).unwrap();
+        assert_eq!(rc5.encode(&pt), expected);
+        assert!(matches!(
+            RC5::<u32>::from_key_provider(&keys, "b", 16),
+            Err(Error::KeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn async_key_provider() {
+        let remote = Remote(keys());
+        let pt = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let expected = RC5::<u32>::new(vec![1; 16]).unwrap().encode(&pt);
+
+        let rc5 = block_on(RC5::<u32>::from_async_key_provider(&remote, "a", 16)).unwrap();
+        assert_eq!(rc5.encode(&pt), expected);
+    }
+}
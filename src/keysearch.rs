@@ -0,0 +1,239 @@
+//! Known-plaintext key search.
+//!
+//! Given a single plaintext/ciphertext block pair, tests candidate keys
+//! (from a dictionary, a `Mask` or any other iterator) and returns the ones
+//! that encrypt the plaintext to the ciphertext. It is the core of password
+//! recovery and CTF workflows.
+//!
+//! Every candidate needs a key expansion, which is much slower than encrypting
+//! a block, so candidates are only checked against a single block, without
+//! audit hooks, whitening or vector kernels, and spread over several threads.
+//! A match on a single block may be a false positive for keys much longer
+//! than the block, so matches should be confirmed on more data.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+    mem,
+    ops::RangeInclusive,
+    sync::Mutex,
+};
+
+use crate::{
+    compat_profile::ByteOrder, Block, Error, ExpandedKeyTable, FromLeBytes, SecretKey, ToLeBytes,
+    Word, RC5,
+};
+
+/// A known plaintext/ciphertext block pair to search the key for.
+pub struct KnownPlaintext<W: Word> {
+    plaintext: (W, W),
+    ciphertext: (W, W),
+    number_of_rounds: usize,
+}
+
+impl<W> KnownPlaintext<W>
+where
+    W: Word,
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Number of candidates a thread takes at once.
+    const BATCH_SIZE: usize = 256;
+
+    /// Creates a known plaintext from plain text and its cipher text,
+    /// encrypted with a given number of rounds.
+    ///
+    /// Only the first block of each is used, so a known prefix of a message is enough.
+    pub fn new(
+        plaintext: &[u8],
+        ciphertext: &[u8],
+        number_of_rounds: usize,
+    ) -> Result<Self, Error> {
+        ExpandedKeyTable::<W>::len(number_of_rounds)?;
+        let block_size = 2 * W::BYTES;
+        for block in [plaintext, ciphertext] {
+            if block.len() < block_size {
+                return Err(Error::InvalidInputLength {
+                    actual: block.len(),
+                    block_size,
+                });
+            }
+        }
+
+        Ok(Self {
+            plaintext: Block::<W>::read(&plaintext[..block_size], ByteOrder::LittleEndian).words(),
+            ciphertext: Block::<W>::read(&ciphertext[..block_size], ByteOrder::LittleEndian)
+                .words(),
+            number_of_rounds,
+        })
+    }
+
+    /// Checks whether a candidate key encrypts the plain text to the cipher text.
+    ///
+    /// Candidates that aren't valid keys (longer than 255 bytes) never match.
+    pub fn matches(&self, key: &SecretKey) -> bool {
+        let ctx = match ExpandedKeyTable::new(key, self.number_of_rounds) {
+            Ok(table) => RC5::<W>::from_key_table(table),
+            Err(_) => return false,
+        };
+
+        let (a, b) = self.plaintext;
+        Block::new(a, b).encode(&ctx).words() == self.ciphertext
+    }
+
+    /// Tests every candidate key using a given number of threads
+    /// and returns the matching keys in the order of the candidates.
+    pub fn search<I>(&self, candidates: I, threads: usize) -> Vec<SecretKey>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send,
+        Self: Sync,
+    {
+        let candidates = Mutex::new(candidates.into_iter().enumerate());
+        let matches = Mutex::new(Vec::new());
+
+        let work = || loop {
+            let batch = candidates
+                .lock()
+                .unwrap()
+                .by_ref()
+                .take(Self::BATCH_SIZE)
+                .collect::<Vec<_>>();
+            if batch.is_empty() {
+                break;
+            }
+
+            for (i, candidate) in batch {
+                // Candidates are wiped once tested.
+                match SecretKey::new(candidate) {
+                    Ok(key) if self.matches(&key) => matches.lock().unwrap().push((i, key)),
+                    _ => {}
+                }
+            }
+        };
+
+        std::thread::scope(|scope| {
+            for _ in 1..threads.max(1) {
+                scope.spawn(work);
+            }
+            work();
+        });
+
+        let mut matches = mem::take(&mut *matches.lock().unwrap());
+        matches.sort_by_key(|(i, _)| *i);
+        matches.into_iter().map(|(_, key)| key).collect()
+    }
+}
+
+/// Candidate keys matching a mask: a range of allowed values for every byte.
+///
+/// Known bytes are single-value ranges, e.g. `[b'k'..=b'k', b'0'..=b'9']`
+/// yields `k0` to `k9`. Keys are produced in lexicographic order.
+#[derive(Debug, Clone)]
+pub struct Mask {
+    ranges: Vec<RangeInclusive<u8>>,
+    /// The next candidate, `None` when exhausted.
+    next: Option<Vec<u8>>,
+}
+
+impl Mask {
+    /// Creates a mask from the ranges of allowed values of every byte.
+    pub fn new(ranges: Vec<RangeInclusive<u8>>) -> Self {
+        let next = ranges
+            .iter()
+            .map(|range| (!range.is_empty()).then_some(*range.start()))
+            .collect();
+        Self { ranges, next }
+    }
+
+    /// Every key of a given length.
+    pub fn all(len: usize) -> Self {
+        Self::new(vec![0..=255; len])
+    }
+
+    /// Total number of candidates matching the mask, `None` if it doesn't fit into `u128`.
+    pub fn candidates(&self) -> Option<u128> {
+        self.ranges.iter().try_fold(1u128, |n, range| {
+            if range.is_empty() {
+                Some(0)
+            } else {
+                n.checked_mul(u128::from(range.end() - range.start()) + 1)
+            }
+        })
+    }
+}
+
+impl Iterator for Mask {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let current = self.next.take()?;
+
+        // Ranges are incremented like an odometer, the last byte first.
+        let mut next = current.clone();
+        for (byte, range) in next.iter_mut().zip(self.ranges.iter()).rev() {
+            if *byte < *range.end() {
+                *byte += 1;
+                self.next = Some(next);
+                break;
+            }
+            *byte = *range.start();
+        }
+
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask() {
+        let keys = Mask::new(vec![1..=2, 7..=7, 0..=1]).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![vec![1, 7, 0], vec![1, 7, 1], vec![2, 7, 0], vec![2, 7, 1]]
+        );
+        assert_eq!(Mask::new(vec![1..=2, 7..=7, 0..=1]).candidates(), Some(4));
+        assert_eq!(Mask::all(2).candidates(), Some(65536));
+        assert_eq!(Mask::new(vec![]).collect::<Vec<_>>(), vec![vec![]]);
+        #[allow(clippy::reversed_empty_ranges)]
+        let mut empty = Mask::new(vec![2..=1]);
+        assert_eq!(empty.candidates(), Some(0));
+        assert_eq!(empty.next(), None);
+    }
+
+    #[test]
+    fn search() {
+        let key = vec![0x13, 0x37, 0x42];
+        let pt = [1, 2, 3, 4, 5, 6, 7, 8];
+        let ct = RC5::<u32>::new_with_rounds(key.clone(), 12)
+            .unwrap()
+            .encode(pt);
+
+        let known = KnownPlaintext::<u32>::new(&pt, &ct, 12).unwrap();
+        assert!(KnownPlaintext::<u32>::new(&[&pt[..], &[0; 8]].concat(), &ct, 12).is_ok());
+        let candidates = Mask::new(vec![0x13..=0x13, 0..=255, 0x40..=0x4f]);
+        for threads in [1, 4] {
+            let found = known.search(candidates.clone(), threads);
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].secret(), &key);
+        }
+
+        let dictionary = "password\nletmein\n".lines().map(Vec::from);
+        assert!(known.search(dictionary, 2).is_empty());
+
+        assert!(matches!(
+            KnownPlaintext::<u32>::new(&pt[..7], &ct, 12),
+            Err(Error::InvalidInputLength { actual: 7, .. })
+        ));
+        assert!(matches!(
+            KnownPlaintext::<u32>::new(&pt, &ct, 256),
+            Err(Error::InvalidNumberOfRounds {
+                actual: 256,
+                max: 255
+            })
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+//! Raw counter (CTR) mode keystream.
+//!
+//! The keystream is the concatenation of the encrypted counter blocks
+//! `(nonce, 0)`, `(nonce, 1)`, ..., exactly as used by `RingEncryptor`.
+//! Protocols that XOR the keystream into their own buffers, or skip over
+//! parts of it, can use it directly instead of going through the encrypt API.
+//!
+//! The keystream ends after `2^w` blocks, when the counter would wrap around.
+//! A nonce must never be reused with the same key.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+    io::{self, Read},
+};
+
+use zeroize::Zeroize;
+
+use crate::{
+    block_cipher::{BlockCipher, MAX_BLOCK_SIZE},
+    Block, Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// A counter mode keystream of a given nonce.
+///
+/// Yields keystream bytes as an `Iterator` and a `Read` source.
+pub struct Keystream<'a, W: Word> {
+    cipher: &'a RC5<W>,
+    /// The first word of every counter block.
+    nonce: W,
+    /// Index of the next counter block.
+    counter: W,
+    /// Set once the counter has wrapped around.
+    exhausted: bool,
+    /// The current keystream block.
+    block: [u8; MAX_BLOCK_SIZE],
+    /// Index of the next unused byte of the current block.
+    pos: usize,
+}
+
+impl<'a, W: Word> Keystream<'a, W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    /// Creates the keystream of a given nonce, which must be unique for the key.
+    pub fn new(cipher: &'a RC5<W>, nonce: W) -> Self {
+        Self {
+            cipher,
+            nonce,
+            counter: W::zero(),
+            exhausted: false,
+            block: [0; MAX_BLOCK_SIZE],
+            pos: Self::BLOCK_SIZE,
+        }
+    }
+
+    /// Number of keystream bytes left.
+    pub fn remaining(&self) -> u128 {
+        let blocks = match self.exhausted {
+            true => 0,
+            false => W::max_value().to_u128().unwrap() - self.counter.to_u128().unwrap() + 1,
+        };
+        (Self::BLOCK_SIZE - self.pos) as u128 + blocks * Self::BLOCK_SIZE as u128
+    }
+
+    /// XORs the next `data.len()` keystream bytes into `data`.
+    ///
+    /// Fails without changing `data` if the keystream is too short.
+    pub fn apply(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        if data.len() as u128 > self.remaining() {
+            return Err(Error::CounterExhausted);
+        }
+
+        for byte in data.iter_mut() {
+            if self.pos == Self::BLOCK_SIZE {
+                self.next_block();
+            }
+            *byte ^= self.block[self.pos];
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    /// Skips the next `n` keystream bytes, without encrypting the skipped blocks.
+    ///
+    /// Fails without skipping anything if the keystream is too short.
+    pub fn discard(&mut self, n: u64) -> Result<(), Error> {
+        let n = n as u128;
+        if n > self.remaining() {
+            return Err(Error::CounterExhausted);
+        }
+
+        let block_size = Self::BLOCK_SIZE as u128;
+        let left = (block_size - self.pos as u128).min(n);
+        self.pos += left as usize;
+        let (blocks, rest) = ((n - left) / block_size, (n - left) % block_size);
+
+        if blocks > 0 {
+            match W::from(blocks) {
+                Some(blocks) => {
+                    self.counter = self.counter.wrapping_add(&blocks);
+                    self.exhausted = self.counter.is_zero();
+                }
+                // The rest of the keystream, all `2^w` blocks of it.
+                None => self.exhausted = true,
+            }
+        }
+        if rest > 0 {
+            self.next_block();
+            self.pos = rest as usize;
+        }
+        Ok(())
+    }
+
+    /// Encrypts the next counter block, there must be one left.
+    fn next_block(&mut self) {
+        let block = &mut self.block[..Self::BLOCK_SIZE];
+        Block::new(self.nonce, self.counter).write_ordered(
+            block,
+            self.cipher.byte_order,
+            self.cipher.word_order,
+        );
+        self.cipher.encrypt_block(block);
+
+        self.counter = self.counter.wrapping_add(&W::one());
+        self.exhausted = self.counter.is_zero();
+        self.pos = 0;
+    }
+}
+
+impl<W: Word> Iterator for Keystream<'_, W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let mut byte = [0];
+        self.apply(&mut byte).ok()?;
+        Some(byte[0])
+    }
+}
+
+/// The buffered keystream block is wiped.
+impl<W: Word> Drop for Keystream<'_, W> {
+    fn drop(&mut self) {
+        self.block.zeroize();
+    }
+}
+
+/// Reads keystream bytes, the end of the keystream is the end of file.
+impl<W: Word> Read for Keystream<'_, W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.remaining().min(buf.len() as u128) as usize;
+        let buf = &mut buf[..n];
+        buf.fill(0);
+        self.apply(buf)?;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RingEncryptor;
+
+    #[test]
+    fn matches_ring_encryptor() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let pt = (0..100).collect::<Vec<u8>>();
+        let mut ct = vec![0; 100];
+        let mut encryptor = RingEncryptor::<u32, 128>::new(&rc5, 7);
+        encryptor.push(&pt);
+        encryptor.flush(&mut ct).unwrap();
+
+        let mut data = pt.clone();
+        let mut keystream = Keystream::new(&rc5, 7);
+        keystream.apply(&mut data[..13]).unwrap();
+        keystream.apply(&mut data[13..]).unwrap();
+        assert_eq!(data, ct);
+
+        let xored = pt.iter().zip(Keystream::new(&rc5, 7)).map(|(p, k)| p ^ k);
+        assert_eq!(xored.collect::<Vec<_>>(), ct);
+
+        let mut read = vec![0; 100];
+        Keystream::new(&rc5, 7).read_exact(&mut read).unwrap();
+        let xored = pt.iter().zip(&read).map(|(p, k)| p ^ k);
+        assert_eq!(xored.collect::<Vec<_>>(), ct);
+    }
+
+    #[test]
+    fn discard() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let full = Iterator::take(Keystream::new(&rc5, 3), 100).collect::<Vec<_>>();
+        for n in [0, 3, 8, 21, 64] {
+            let mut keystream = Keystream::new(&rc5, 3);
+            keystream.discard(5).unwrap();
+            keystream.discard(n).unwrap();
+            let rest = Iterator::take(keystream, 20).collect::<Vec<_>>();
+            assert_eq!(rest, full[5 + n as usize..25 + n as usize]);
+        }
+    }
+
+    #[test]
+    fn exhausted() {
+        let rc5 = RC5::<u8>::new(vec![0x42; 16]).unwrap();
+        let mut keystream = Keystream::new(&rc5, 0);
+        assert_eq!(keystream.remaining(), 512);
+        keystream.discard(509).unwrap();
+        assert!(matches!(
+            keystream.apply(&mut [0; 4]),
+            Err(Error::CounterExhausted)
+        ));
+        assert_eq!(Iterator::by_ref(&mut keystream).count(), 3);
+        assert_eq!(keystream.read(&mut [0; 4]).unwrap(), 0);
+
+        let mut keystream = Keystream::new(&rc5, 0);
+        assert!(keystream.discard(513).is_err());
+        keystream.discard(512).unwrap();
+        assert_eq!(keystream.remaining(), 0);
+    }
+}
@@ -0,0 +1,332 @@
+//! Encrypted key-value store.
+//!
+//! A `KvStore` keeps values encrypted at rest in a pluggable `Backend`,
+//! a file (`FileBackend`), a `BTreeMap` in memory or any other storage.
+//! Each value is encrypted and authenticated with CCM (see `modes::Ccm`)
+//! under a fresh random nonce, with its key as the associated data:
+//!
+//! ```text
+//! stored value = nonce (12 bytes) || CCM(nonce, key, value) || tag
+//! ```
+//!
+//! Keys are stored in the clear, so that the backend can look them up,
+//! but a value can't be modified or moved to another key without failing
+//! to authenticate. An old value of a key can still be restored by whoever
+//! can write to the backend, as nothing records which value is the latest.
+//!
+//! Random 96-bit nonces are safe for billions of writes under one key.
+//! Only 64-bit words are supported, so that the nonces fit into CCM blocks,
+//! which limits values to 16 MiB. There's no sled backend, as sled isn't
+//! a dependency of this crate; it can be plugged in by implementing `Backend`.
+
+use std::{
+    collections::BTreeMap,
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use rand::{thread_rng, RngCore};
+
+use crate::{modes::Ccm, Error, FromLeBytes, ToLeBytes, Word, RC5};
+
+/// Length of the nonce of a value in bytes.
+const NONCE_LEN: usize = 12;
+
+/// A storage of the encrypted values.
+pub trait Backend {
+    /// Returns the value stored with a key, if any.
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Stores a value with a key, replacing the previous one.
+    fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Result<(), Error>;
+
+    /// Removes the value stored with a key, returning whether there was one.
+    fn remove(&mut self, key: &[u8]) -> Result<bool, Error>;
+}
+
+impl Backend for BTreeMap<Vec<u8>, Vec<u8>> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(BTreeMap::get(self, key).cloned())
+    }
+
+    fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Result<(), Error> {
+        BTreeMap::insert(self, key.to_vec(), value);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Result<bool, Error> {
+        Ok(BTreeMap::remove(self, key).is_some())
+    }
+}
+
+/// A backend stored in a single file.
+///
+/// All entries are kept in memory and the whole file is rewritten on every
+/// change: written to a temporary file that is synced to disk and atomically
+/// renamed over the previous one, so the file always contains either the old
+/// or the new entries. Suited to small stores, such as configuration secrets.
+///
+/// Each entry is stored as the key and the value, both prefixed
+/// with their 4-byte little-endian lengths.
+pub struct FileBackend {
+    path: PathBuf,
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl FileBackend {
+    /// Opens the backend stored in a file at a given path,
+    /// which is created on the first change if it doesn't exist.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let entries = match fs::read(&path) {
+            Ok(bytes) => Self::decode(&bytes).ok_or(Error::InvalidStoreState)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, entries })
+    }
+
+    fn decode(mut bytes: &[u8]) -> Option<BTreeMap<Vec<u8>, Vec<u8>>> {
+        fn field<'a>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+            let len = bytes.get(..4)?;
+            let len = usize::try_from(u32::from_le_bytes(len.try_into().ok()?)).ok()?;
+            let field = bytes.get(4..4usize.checked_add(len)?)?;
+            *bytes = &bytes[4 + len..];
+            Some(field)
+        }
+
+        let mut entries = BTreeMap::new();
+        while !bytes.is_empty() {
+            let key = field(&mut bytes)?;
+            let value = field(&mut bytes)?;
+            entries.insert(key.to_vec(), value.to_vec());
+        }
+        Some(entries)
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        for (key, value) in &self.entries {
+            for field in [key, value] {
+                let len = u32::try_from(field.len()).map_err(|_| Error::InputTooLarge)?;
+                bytes.extend_from_slice(&len.to_le_bytes());
+                bytes.extend_from_slice(field);
+            }
+        }
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&bytes)?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        // Make the rename itself durable, where directories can be synced.
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+
+        Ok(())
+    }
+}
+
+impl Backend for FileBackend {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Result<(), Error> {
+        let previous = self.entries.insert(key.to_vec(), value);
+        let result = self.save();
+        if result.is_err() {
+            // Keep the entries in line with the file.
+            match previous {
+                Some(previous) => self.entries.insert(key.to_vec(), previous),
+                None => self.entries.remove(key),
+            };
+        }
+        result
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let previous = match self.entries.remove(key) {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+        if let Err(e) = self.save() {
+            self.entries.insert(key.to_vec(), previous);
+            return Err(e);
+        }
+        Ok(true)
+    }
+}
+
+/// A key-value store that encrypts the values in a backend.
+pub struct KvStore<B: Backend, W: Word> {
+    backend: B,
+    ccm: Ccm<W>,
+}
+
+impl<B: Backend, W: Word> KvStore<B, W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Creates a store with tags of `tag_len` bytes (an even number from 4 up to 16)
+    /// over a backend.
+    ///
+    /// Fails for word sizes other than 64 bits.
+    pub fn new(backend: B, cipher: RC5<W>, tag_len: usize) -> Result<Self, Error> {
+        if W::BITS != 64 {
+            return Err(Error::InvalidWordSize(W::BITS));
+        }
+        Ok(Self {
+            backend,
+            ccm: Ccm::new(cipher, tag_len)?,
+        })
+    }
+
+    /// Returns the value of a key, if any.
+    ///
+    /// Fails with `Error::AuthenticationFailed` if the stored value
+    /// was tampered with or stored with another key.
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, Error> {
+        let key = key.as_ref();
+        let stored = match self.backend.get(key)? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        if stored.len() < NONCE_LEN {
+            return Err(Error::CiphertextTooShort {
+                actual: stored.len(),
+                min: NONCE_LEN + self.ccm.tag_len(),
+            });
+        }
+        let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+        self.ccm.decrypt(nonce, key, ciphertext).map(Some)
+    }
+
+    /// Encrypts a value and stores it with a key, replacing the previous one.
+    pub fn insert(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<(), Error> {
+        let key = key.as_ref();
+        let mut nonce = [0; NONCE_LEN];
+        thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = self.ccm.encrypt(&nonce, key, value)?;
+        let mut stored = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        stored.extend_from_slice(&nonce);
+        stored.extend_from_slice(&ciphertext);
+        self.backend.insert(key, stored)
+    }
+
+    /// Removes the value of a key, returning whether there was one.
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) -> Result<bool, Error> {
+        self.backend.remove(key.as_ref())
+    }
+
+    /// Returns the backend.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rc5() -> RC5<u64> {
+        RC5::new(vec![0x42; 16]).unwrap()
+    }
+
+    #[test]
+    fn memory_backend() {
+        let mut store = KvStore::new(BTreeMap::new(), rc5(), 16).unwrap();
+        assert_eq!(store.get("user"), Ok(None));
+        store.insert("user", "alice").unwrap();
+        store.insert("password", "hunter2").unwrap();
+        store.insert("user", "bob").unwrap();
+        assert_eq!(store.get("user").unwrap().unwrap(), b"bob");
+        assert_eq!(store.get("password").unwrap().unwrap(), b"hunter2");
+
+        // Same values are stored under different nonces.
+        store.insert("other", "bob").unwrap();
+        assert_eq!(store.remove("password"), Ok(true));
+        assert_eq!(store.remove("password"), Ok(false));
+        assert_eq!(store.get("password"), Ok(None));
+
+        let backend = store.into_inner();
+        let stored = &backend[&b"user"[..]];
+        assert_eq!(stored.len(), 12 + 3 + 16);
+        assert_ne!(stored, &backend[&b"other"[..]]);
+    }
+
+    #[test]
+    fn tampered() {
+        let mut store = KvStore::new(BTreeMap::new(), rc5(), 16).unwrap();
+        store.insert("a", "first").unwrap();
+        store.insert("b", "second").unwrap();
+        let mut backend = store.into_inner();
+
+        // A value moved to another key doesn't authenticate.
+        let a = backend[&b"a"[..]].clone();
+        backend.insert(b"b".to_vec(), a.clone());
+        let mut tampered = a;
+        tampered[13] ^= 1;
+        backend.insert(b"a".to_vec(), tampered);
+        backend.insert(b"c".to_vec(), vec![0; 5]);
+
+        let store = KvStore::new(backend, rc5(), 16).unwrap();
+        assert_eq!(store.get("a"), Err(Error::AuthenticationFailed));
+        assert_eq!(store.get("b"), Err(Error::AuthenticationFailed));
+        assert!(matches!(
+            store.get("c"),
+            Err(Error::CiphertextTooShort { actual: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn file_backend() {
+        let path = std::env::temp_dir().join(format!("rc5-kv-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut store = KvStore::new(FileBackend::open(&path).unwrap(), rc5(), 16).unwrap();
+        store.insert("user", "alice").unwrap();
+        store.insert("empty", "").unwrap();
+        store.insert("gone", "soon").unwrap();
+        assert_eq!(store.remove("gone"), Ok(true));
+
+        let store = KvStore::new(FileBackend::open(&path).unwrap(), rc5(), 16).unwrap();
+        assert_eq!(store.get("user").unwrap().unwrap(), b"alice");
+        assert_eq!(store.get("empty").unwrap().unwrap(), b"");
+        assert_eq!(store.get("gone"), Ok(None));
+        assert!(!fs::read(&path).unwrap().windows(5).any(|w| w == b"alice"));
+
+        fs::write(&path, [1, 0, 0, 0, b'k', 9, 0, 0, 0]).unwrap();
+        assert!(matches!(
+            FileBackend::open(&path),
+            Err(Error::InvalidStoreState)
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid() {
+        let rc5_32 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        assert!(matches!(
+            KvStore::new(BTreeMap::new(), rc5_32, 8),
+            Err(Error::InvalidWordSize(32))
+        ));
+        assert!(matches!(
+            KvStore::new(BTreeMap::new(), rc5(), 3),
+            Err(Error::InvalidTagLength(3))
+        ));
+    }
+}
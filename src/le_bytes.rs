@@ -13,7 +13,7 @@ use std::convert::{TryFrom, TryInto};
 /// Used to convert byte arrays in little-endian
 /// byte order to integer values.
 pub trait FromLeBytes {
-    type T: TryFrom<Vec<u8>>;
+    type T: TryFrom<Vec<u8>> + AsMut<[u8]> + Default;
 
     /// Create a native endian integer value from
     /// its representation as a byte array in little endian.
@@ -23,7 +23,7 @@ pub trait FromLeBytes {
 /// Used to convert integer values to
 /// byte arrays in little-endian byte order.
 pub trait ToLeBytes {
-    type T: TryInto<Vec<u8>>;
+    type T: TryInto<Vec<u8>> + AsRef<[u8]>;
 
     /// Returns the memory representation of this integer as
     /// a byte array in little-endian byte order.
@@ -56,10 +56,12 @@ macro_rules! to_le_bytes_impl {
     };
 }
 
+from_le_bytes_impl!(u8);
 from_le_bytes_impl!(u16);
 from_le_bytes_impl!(u32);
 from_le_bytes_impl!(u64);
 
+to_le_bytes_impl!(u8);
 to_le_bytes_impl!(u16);
 to_le_bytes_impl!(u32);
 to_le_bytes_impl!(u64);
@@ -8,37 +8,149 @@
 //! choice of parameters `w` and `r`:
 //!
 //! * `w` - Word size, in bits.
-//!         Allowable sizes are 16, 32 and 64.
-//!         Nominal size is 32 bits.
+//!   Allowable sizes are 8, 16, 32 and 64 (8 is only meant for testing).
+//!   Nominal size is 32 bits.
 //! * `r` - Number of rounds from 0 to 255.
-//!         Choosing larger number of rounds provides an
-//!         increased level of security.
+//!   Choosing larger number of rounds provides an
+//!   increased level of security.
 //!
 //! Notational convention: RC5-w/r/b, where `b` is the length of a secret key.
+//!
+//! Encryption and decryption are enabled by the default `encrypt` and `decrypt` features.
+//! Firmware that only needs one direction can disable the other one to shrink
+//! the code size (the integration tests need both).
 
 pub mod rc5;
 pub use rc5::RC5;
 
+#[cfg(feature = "analysis")]
+pub mod analysis;
+
+pub mod audit;
+pub use audit::AuditHook;
+
 pub mod block;
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
 use block::Block;
 
+mod block_cipher;
+
+pub mod cipher_cache;
+pub use cipher_cache::CipherCache;
+
+pub mod compat_profile;
+pub use compat_profile::CompatProfile;
+
 pub mod control_block;
-pub use control_block::ControlBlock;
+pub use control_block::{ControlBlock, ControlBlockV2};
+
+#[cfg(feature = "encrypt")]
+pub mod datagram;
+#[cfg(feature = "encrypt")]
+pub use datagram::Datagram;
+
+pub mod env;
 
 pub mod error;
 pub use error::Error;
 
+#[cfg(all(feature = "encrypt", feature = "decrypt"))]
+pub mod fs;
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub mod iv_prefix;
+
+pub mod kernel;
+
+pub mod key_length;
+pub use key_length::KeyLength;
+
+pub mod key_provider;
+pub use key_provider::{AsyncKeyProvider, KeyProvider};
+
+#[cfg(feature = "encrypt")]
+pub mod keysearch;
+
+#[cfg(feature = "encrypt")]
+pub mod keystream;
+#[cfg(feature = "encrypt")]
+pub use keystream::Keystream;
+
+#[cfg(feature = "kv")]
+pub mod kv;
+#[cfg(feature = "kv")]
+pub use kv::KvStore;
+
 pub mod le_bytes;
 use le_bytes::{FromLeBytes, ToLeBytes};
 
+#[cfg(feature = "masked")]
+pub mod masked;
+
 pub mod magic_const;
+
+pub mod memory_budget;
 use magic_const::HasPQ;
+pub use memory_budget::MemoryBudget;
 
 pub mod word;
 pub use word::Word;
 
+pub mod modes;
+
+pub mod nonce;
+pub use nonce::NonceSequence;
+
+pub mod permute;
+
+pub mod params;
+pub use params::{Params, Rc5Params};
+
+pub mod security_level;
+pub use security_level::SecurityLevel;
+
+#[cfg(all(feature = "encrypt", feature = "decrypt"))]
+pub mod record;
+#[cfg(all(feature = "encrypt", feature = "decrypt"))]
+pub use record::RecordCipher;
+
+#[cfg(feature = "encrypt")]
+pub mod record_stream;
+#[cfg(feature = "encrypt")]
+pub use record_stream::{RecordReader, RecordWriter};
+
+#[cfg(feature = "encrypt")]
+pub mod ring_encryptor;
+#[cfg(feature = "encrypt")]
+pub use ring_encryptor::RingEncryptor;
+
+pub mod rotation;
+pub use rotation::RotationSchedule;
+
+pub mod scratch;
+pub use scratch::ScratchSpace;
+
 pub mod secret_key;
-pub use secret_key::SecretKey;
+pub use secret_key::{SecretKey, SecretKeyRef};
+
+pub mod size;
+pub use size::Padding;
+
+pub mod small_buf;
+pub use small_buf::SmallBuf;
+
+#[cfg(feature = "encrypt")]
+pub mod spill_file;
+#[cfg(feature = "encrypt")]
+pub use spill_file::SpillFile;
+
+pub mod throughput;
+pub use throughput::Throughput;
+
+pub mod whitening;
 
 pub mod expanded_key_table;
-use expanded_key_table::ExpandedKeyTable;
+pub use expanded_key_table::{BorrowedStorage, ExpandedKeyTable, KeyExpansion, KeyTableStorage};
+
+#[cfg(feature = "shamir")]
+pub mod shamir;
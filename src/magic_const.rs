@@ -11,7 +11,7 @@ pub trait HasPQ {
 }
 
 // There is an algorithm to compute P and Q constants for arbitrary word-size `w`,
-// but already precomputed values for w = 16, 32 and 64 are suitable for our purposes.
+// but already precomputed values for w = 8, 16, 32 and 64 are suitable for our purposes.
 //
 // We can implement this algorithm later if we need to use word sizes larger than 64 bits.
 
@@ -35,6 +35,7 @@ macro_rules! has_pq_impl {
     };
 }
 
+has_pq_impl!(u8, 0xb7, 0x9f);
 has_pq_impl!(u16, 0xb7e1, 0x9e37);
 has_pq_impl!(u32, 0xb7e15163, 0x9e3779b9);
 has_pq_impl!(u64, 0xb7e151628aed2a6b, 0x9e3779b97f4a7c15);
@@ -0,0 +1,375 @@
+//! First-order masked implementation of the block function.
+//!
+//! On smartcard-like devices an attacker measuring the power consumption
+//! of many encryptions can recover the key with differential power analysis
+//! (DPA), since every intermediate value of the regular implementation
+//! depends on the key and on known data. Here every secret intermediate value
+//! `x` is instead split into two random shares `(x0, x1)` with `x = x0 ^ x1`
+//! (boolean masking), so that no single value handled by the processor
+//! is correlated with the secret.
+//!
+//! - Registers `A` and `B` are masked with fresh randomness as soon as a block is read.
+//! - The round keys are stored masked, and the masks are refreshed before every block.
+//! - XOR and rotations by constants are applied to each share separately.
+//! - Additions and subtractions modulo `2^w` are computed directly on the shares,
+//!   with a ripple-carry adder built from masked AND gates
+//!   (the first-order ISW gadget), instead of converting to arithmetic masking.
+//! - Data-dependent rotations are computed by a masked barrel shifter:
+//!   `lg(w)` conditional rotations by `2^j`, each selected by a masked
+//!   bit of the rotation amount, so the amount is never unmasked.
+//!
+//! Limitations:
+//!
+//! - This only protects against first-order attacks. Attacks combining
+//!   two points of a trace (or two shares) are out of scope.
+//! - The masking is done in software. The compiler may reorder or merge
+//!   operations on the shares, and the hardware may leak the combination
+//!   of two shares through glitches or register transitions.
+//!   None of this has been verified on real hardware.
+//! - The key expansion is not masked, it should be done once,
+//!   in a trusted environment.
+//! - It is much slower than the regular implementation (about `w` masked
+//!   AND gates per addition) and only supports the little-endian byte order
+//!   of the paper without key whitening.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+    ops::BitXor,
+};
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+use crate::{compat_profile::ByteOrder, Block};
+use crate::{Error, ExpandedKeyTable, FromLeBytes, SecretKey, ToLeBytes, Word};
+
+/// A first-order masked implementation of RC5.
+///
+/// Produces the same cipher text as `RC5` with the same key and number of rounds.
+#[cfg_attr(not(any(feature = "encrypt", feature = "decrypt")), allow(dead_code))]
+pub struct MaskedRC5<W: Word> {
+    /// Masked expanded key table.
+    key_table: Vec<Masked<W>>,
+    number_of_rounds: usize,
+    /// Source of the fresh masks.
+    rng: StdRng,
+}
+
+impl<W> MaskedRC5<W>
+where
+    W: Word,
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    /// Block is a pair of words.
+    const BLOCK_SIZE: usize = 2 * W::BYTES;
+
+    /// Creates a new masked RC5 instance for a given secret key with
+    /// a default reasonable number of rounds.
+    pub fn new(secret_key: impl Into<Vec<u8>>) -> Result<Self, Error> {
+        Self::new_with_rounds(secret_key, W::ROUNDS)
+    }
+
+    /// Creates a new masked RC5 instance for a given secret key and a number of rounds.
+    pub fn new_with_rounds(
+        secret_key: impl Into<Vec<u8>>,
+        number_of_rounds: usize,
+    ) -> Result<Self, Error> {
+        let secret_key = SecretKey::new(secret_key.into())?;
+        let table = ExpandedKeyTable::new(&secret_key, number_of_rounds)?;
+
+        let mut rng = StdRng::from_entropy();
+        let key_table = table
+            .words()
+            .iter()
+            .map(|&s| Masked::new(s, &mut rng))
+            .collect();
+
+        Ok(Self {
+            key_table,
+            number_of_rounds,
+            rng,
+        })
+    }
+
+    /// Encrypts plain text, which must consist of whole blocks.
+    #[cfg(feature = "encrypt")]
+    pub fn encode(&mut self, plaintext: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+        self.process(plaintext.as_ref(), Self::encrypt_block)
+    }
+
+    /// Decrypts cipher text, which must consist of whole blocks.
+    #[cfg(feature = "decrypt")]
+    pub fn decode(&mut self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+        self.process(ciphertext.as_ref(), Self::decrypt_block)
+    }
+
+    /// Copies the input and processes it block by block.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn process(
+        &mut self,
+        input: &[u8],
+        process: fn(&mut Self, Masked<W>, Masked<W>) -> MaskedBlock<W>,
+    ) -> Result<Vec<u8>, Error> {
+        if !input.len().is_multiple_of(Self::BLOCK_SIZE) {
+            return Err(Error::InvalidInputLength {
+                actual: input.len(),
+                block_size: Self::BLOCK_SIZE,
+            });
+        }
+
+        let mut output = input.to_vec();
+        for block in output.chunks_mut(Self::BLOCK_SIZE) {
+            self.refresh_key_table();
+
+            let (a, b) = Block::<W>::read(block, ByteOrder::LittleEndian).words();
+            let (a, b) = (Masked::new(a, &mut self.rng), Masked::new(b, &mut self.rng));
+
+            let (a, b) = process(self, a, b);
+            Block::new(a.unmask(), b.unmask()).write(block, ByteOrder::LittleEndian);
+        }
+        Ok(output)
+    }
+
+    /// Re-randomizes the shares of every round key.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn refresh_key_table(&mut self) {
+        for s in self.key_table.iter_mut() {
+            *s = s.refresh(&mut self.rng);
+        }
+    }
+
+    /// Encrypts a masked block, see `Block::encode` for the unmasked algorithm.
+    #[cfg(feature = "encrypt")]
+    fn encrypt_block(&mut self, mut a: Masked<W>, mut b: Masked<W>) -> MaskedBlock<W> {
+        let rng = &mut self.rng;
+        let s = &self.key_table;
+
+        a = a.add(s[0], rng);
+        b = b.add(s[1], rng);
+        for i in 1..=self.number_of_rounds {
+            a = (a ^ b).rotate_left_by(b, rng).add(s[2 * i], rng);
+            b = (b ^ a).rotate_left_by(a, rng).add(s[2 * i + 1], rng);
+        }
+        (a, b)
+    }
+
+    /// Decrypts a masked block, see `Block::decode` for the unmasked algorithm.
+    #[cfg(feature = "decrypt")]
+    fn decrypt_block(&mut self, mut a: Masked<W>, mut b: Masked<W>) -> MaskedBlock<W> {
+        let rng = &mut self.rng;
+        let s = &self.key_table;
+
+        for i in (1..=self.number_of_rounds).rev() {
+            b = b.sub(s[2 * i + 1], rng).rotate_right_by(a, rng) ^ a;
+            a = a.sub(s[2 * i], rng).rotate_right_by(b, rng) ^ b;
+        }
+        b = b.sub(s[1], rng);
+        a = a.sub(s[0], rng);
+        (a, b)
+    }
+}
+
+impl<W: Word> Drop for MaskedRC5<W> {
+    fn drop(&mut self) {
+        for s in self.key_table.iter_mut() {
+            s.0.zeroize();
+            s.1.zeroize();
+        }
+    }
+}
+
+/// Masked registers `A` and `B`.
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+type MaskedBlock<W> = (Masked<W>, Masked<W>);
+
+/// A boolean-masked word: a pair of shares `(x0, x1)` of `x = x0 ^ x1`.
+#[derive(Clone, Copy)]
+struct Masked<W: Word>(W, W);
+
+impl<W: Word> Masked<W> {
+    /// Masks a word with a fresh random mask.
+    fn new(x: W, rng: &mut StdRng) -> Self {
+        let mask = random_word(rng);
+        Self(x ^ mask, mask)
+    }
+
+    /// Combines the shares, revealing the masked word.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn unmask(self) -> W {
+        self.0 ^ self.1
+    }
+
+    /// Re-masks the word with a fresh random mask, without unmasking it.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn refresh(self, rng: &mut StdRng) -> Self {
+        let mask = random_word(rng);
+        Self(self.0 ^ mask, self.1 ^ mask)
+    }
+
+    /// Bitwise NOT, only one of the shares is inverted.
+    #[cfg(feature = "decrypt")]
+    fn not(self) -> Self {
+        Self(!self.0, self.1)
+    }
+
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn shl(self, n: usize) -> Self {
+        Self(self.0 << n, self.1 << n)
+    }
+
+    #[cfg(feature = "encrypt")]
+    fn rotate_left(self, n: u32) -> Self {
+        Self(self.0.rotate_left(n), self.1.rotate_left(n))
+    }
+
+    #[cfg(feature = "decrypt")]
+    fn rotate_right(self, n: u32) -> Self {
+        Self(self.0.rotate_right(n), self.1.rotate_right(n))
+    }
+
+    /// Masked bitwise AND (the ISW gadget for two shares).
+    ///
+    /// The other operand is refreshed first, since both operands
+    /// may be derived from the same shares.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn and(self, other: Self, rng: &mut StdRng) -> Self {
+        let Self(x0, x1) = self;
+        let Self(y0, y1) = other.refresh(rng);
+        let r = random_word(rng);
+
+        // The order of operations matters: `r` is added first,
+        // so that no partial result depends on both shares of an operand.
+        let z0 = (x0 & y0) ^ r;
+        let z1 = (x1 & y1) ^ ((r ^ (x0 & y1)) ^ (x1 & y0));
+        Self(z0, z1)
+    }
+
+    /// Masked addition modulo `2^w`.
+    ///
+    /// The sum is computed as `x + y = s + 2c`, where `s = x ^ y` and
+    /// `c = x & y`, moving the carries one bit to the left at a time.
+    /// Always takes `w` steps, whatever the values are.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn add(self, other: Self, rng: &mut StdRng) -> Self {
+        let mut s = self ^ other;
+        let mut c = self.and(other, rng);
+        for _ in 1..W::BITS {
+            let carry = c.shl(1);
+            c = s.and(carry, rng);
+            s = s ^ carry;
+        }
+        s
+    }
+
+    /// Masked subtraction modulo `2^w`: `x - y = !(!x + y)`.
+    #[cfg(feature = "decrypt")]
+    fn sub(self, other: Self, rng: &mut StdRng) -> Self {
+        self.not().add(other, rng).not()
+    }
+
+    /// Masked rotation to the left by a masked amount (modulo `w`).
+    #[cfg(feature = "encrypt")]
+    fn rotate_left_by(self, n: Self, rng: &mut StdRng) -> Self {
+        self.rotate_by(n, rng, Self::rotate_left)
+    }
+
+    /// Masked rotation to the right by a masked amount (modulo `w`).
+    #[cfg(feature = "decrypt")]
+    fn rotate_right_by(self, n: Self, rng: &mut StdRng) -> Self {
+        self.rotate_by(n, rng, Self::rotate_right)
+    }
+
+    /// Barrel shifter: for every bit `j` of the amount,
+    /// `x = x ^ ((x ^ rotate(x, 2^j)) & bit_j)`, where `bit_j` is the bit
+    /// expanded to a whole word. Expanding each share of the bit gives
+    /// the shares of the expanded bit, so it is never unmasked.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn rotate_by(self, n: Self, rng: &mut StdRng, rotate: fn(Self, u32) -> Self) -> Self {
+        let mut x = self;
+        for j in 0..W::BITS.trailing_zeros() {
+            let bit = Self(expand_bit(n.0, j), expand_bit(n.1, j));
+            let diff = x ^ rotate(x, 1 << j);
+            x = x ^ diff.and(bit, rng);
+        }
+        x
+    }
+}
+
+impl<W: Word> BitXor for Masked<W> {
+    type Output = Self;
+
+    fn bitxor(self, other: Self) -> Self {
+        Self(self.0 ^ other.0, self.1 ^ other.1)
+    }
+}
+
+/// Expands bit `j` of a word to a word of all zeros or all ones, without branching.
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+fn expand_bit<W: Word>(x: W, j: u32) -> W {
+    W::zero().wrapping_sub(&((x >> j as usize) & W::one()))
+}
+
+/// Draws a uniformly random word.
+fn random_word<W: Word>(rng: &mut StdRng) -> W {
+    W::from(rng.next_u64() >> (64 - W::BITS)).unwrap()
+}
+
+#[cfg(all(test, any(feature = "encrypt", feature = "decrypt")))]
+mod tests {
+    use super::*;
+    #[cfg(all(feature = "encrypt", feature = "decrypt"))]
+    use crate::RC5;
+
+    #[test]
+    fn gadgets() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for x in 0..=255u8 {
+            for y in [0, 1, 0x7f, 0x80, 0xa5, 0xff, x.rotate_left(3)] {
+                let (mx, my) = (Masked::new(x, &mut rng), Masked::new(y, &mut rng));
+                assert_eq!(mx.and(my, &mut rng).unmask(), x & y);
+                assert_eq!(mx.add(my, &mut rng).unmask(), x.wrapping_add(y));
+                #[cfg(feature = "encrypt")]
+                assert_eq!(
+                    mx.rotate_left_by(my, &mut rng).unmask(),
+                    x.rotate_left_by(y)
+                );
+                #[cfg(feature = "decrypt")]
+                {
+                    assert_eq!(mx.sub(my, &mut rng).unmask(), x.wrapping_sub(y));
+                    assert_eq!(
+                        mx.rotate_right_by(my, &mut rng).unmask(),
+                        x.rotate_right_by(y)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "encrypt", feature = "decrypt"))]
+    fn matches_rc5() {
+        let key = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+            0x0E, 0x0F,
+        ];
+        let pt = (0..64).collect::<Vec<u8>>();
+
+        let rc5 = RC5::<u32>::new_with_rounds(key, 12).unwrap();
+        let mut masked = MaskedRC5::<u32>::new_with_rounds(key, 12).unwrap();
+        let ct = masked.encode(&pt).unwrap();
+        assert_eq!(ct, rc5.encode(&pt));
+        assert_eq!(masked.decode(&ct).unwrap(), pt);
+
+        let rc5 = RC5::<u64>::new(key).unwrap();
+        let mut masked = MaskedRC5::<u64>::new(key).unwrap();
+        assert_eq!(masked.encode(&pt).unwrap(), rc5.encode(&pt));
+
+        assert!(matches!(
+            masked.encode([0; 15]),
+            Err(Error::InvalidInputLength { actual: 15, .. })
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+//! Limits on internal buffering.
+//!
+//! Some operations copy their input into an internal buffer before encrypting it,
+//! e.g. to gather scattered buffers (`RC5::encode_vectored`) or to process many
+//! messages at once (`RC5::encode_many`). Services with hard memory limits
+//! can cap such buffers with a `MemoryBudget`, operations that would exceed it
+//! fail with `Error::MemoryBudgetExceeded` instead of allocating.
+//!
+//! The budget doesn't cover the output returned to the caller.
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+use crate::Error;
+
+/// Maximum number of bytes an operation may buffer internally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemoryBudget(usize);
+
+impl MemoryBudget {
+    /// No limit, the default.
+    pub const UNLIMITED: Self = Self(usize::MAX);
+
+    /// A budget of a given number of bytes.
+    pub fn new(bytes: usize) -> Self {
+        Self(bytes)
+    }
+
+    /// The number of bytes of the budget.
+    pub fn bytes(self) -> usize {
+        self.0
+    }
+
+    /// Checks that a buffer of a given length fits into the budget.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn check(self, requested: usize) -> Result<(), Error> {
+        if requested > self.0 {
+            return Err(Error::MemoryBudgetExceeded {
+                requested,
+                budget: self.0,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+#[cfg(all(test, any(feature = "encrypt", feature = "decrypt")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        assert!(MemoryBudget::new(16).check(16).is_ok());
+        assert_eq!(
+            MemoryBudget::new(16).check(17),
+            Err(Error::MemoryBudgetExceeded {
+                requested: 17,
+                budget: 16
+            })
+        );
+        assert!(MemoryBudget::default().check(usize::MAX).is_ok());
+    }
+}
@@ -0,0 +1,172 @@
+//! Modes of operation.
+//!
+//! Modes encrypt messages longer than a block with the block function of an `RC5` instance.
+//! Every mode is a type holding a (cheaply cloned) `RC5` instance
+//! and the state of the mode, e.g. a nonce or an initialization vector.
+//!
+//! Modes encrypting messages in place implement `BlockMode`, so that code
+//! can be written once for any of them. `RC5::encode` encrypts every block
+//! independently, which is `Ecb`: prefer one of the other modes.
+
+#[cfg(feature = "encrypt")]
+use crate::block_cipher::MAX_BLOCK_SIZE;
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+use crate::{block_cipher::BlockCipher, Error};
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub mod cbc_pad;
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub use cbc_pad::Rc5CbcPad;
+
+#[cfg(feature = "encrypt")]
+pub mod ccm;
+#[cfg(feature = "encrypt")]
+pub use ccm::Ccm;
+
+#[cfg(feature = "encrypt")]
+pub mod cfb;
+#[cfg(feature = "encrypt")]
+pub use cfb::{Cfb, Cfb8};
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub mod cts;
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub use cts::Cts;
+
+#[cfg(feature = "encrypt")]
+pub mod ctr;
+#[cfg(feature = "encrypt")]
+pub use ctr::Ctr;
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub mod ecb;
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub use ecb::Ecb;
+
+#[cfg(feature = "encrypt")]
+pub mod ocb;
+#[cfg(feature = "encrypt")]
+pub use ocb::Ocb;
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub mod pcbc;
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub use pcbc::Pcbc;
+
+#[cfg(feature = "encrypt")]
+pub mod xts;
+#[cfg(feature = "encrypt")]
+pub use xts::Xts;
+
+/// A mode of operation encrypting and decrypting messages in place.
+///
+/// Stateful modes (e.g. `Ctr` or `Pcbc`) continue where the previous call
+/// stopped, so a message can be processed in pieces. Which lengths are valid
+/// depends on the mode, e.g. `Ecb` only accepts block-aligned data.
+pub trait BlockMode {
+    /// Encrypts data in place.
+    #[cfg(feature = "encrypt")]
+    fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error>;
+
+    /// Decrypts data in place.
+    #[cfg(feature = "decrypt")]
+    fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error>;
+}
+
+/// Encrypts block-aligned data in place in CBC mode.
+#[cfg(feature = "encrypt")]
+pub(crate) fn cbc_encrypt<C: BlockCipher>(
+    cipher: &C,
+    iv: &[u8],
+    data: &mut [u8],
+) -> Result<(), Error> {
+    check_aligned::<C>(data)?;
+    for i in (0..data.len()).step_by(C::BLOCK_SIZE) {
+        let (prev, block) = data.split_at_mut(i);
+        let block = &mut block[..C::BLOCK_SIZE];
+        match i {
+            0 => xor(block, iv),
+            _ => xor(block, &prev[i - C::BLOCK_SIZE..]),
+        }
+        cipher.encrypt_block(block);
+    }
+    Ok(())
+}
+
+/// Decrypts block-aligned data in place in CBC mode.
+#[cfg(feature = "decrypt")]
+pub(crate) fn cbc_decrypt<C: BlockCipher>(
+    cipher: &C,
+    iv: &[u8],
+    data: &mut [u8],
+) -> Result<(), Error> {
+    check_aligned::<C>(data)?;
+    // Backwards, so that the previous cipher text block is still in place.
+    for i in (0..data.len()).step_by(C::BLOCK_SIZE).rev() {
+        let (prev, block) = data.split_at_mut(i);
+        let block = &mut block[..C::BLOCK_SIZE];
+        cipher.decrypt_block(block);
+        match i {
+            0 => xor(block, iv),
+            _ => xor(block, &prev[i - C::BLOCK_SIZE..]),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+fn check_aligned<C: BlockCipher>(data: &[u8]) -> Result<(), Error> {
+    if !data.len().is_multiple_of(C::BLOCK_SIZE) {
+        return Err(Error::InvalidInputLength {
+            actual: data.len(),
+            block_size: C::BLOCK_SIZE,
+        });
+    }
+    Ok(())
+}
+
+/// Removes the PKCS #7 padding of decrypted block-aligned data.
+///
+/// The padding is 1 to block size bytes, each holding the length of the padding.
+#[cfg(feature = "decrypt")]
+pub(crate) fn unpad(data: &mut Vec<u8>, block_size: usize) -> Result<(), Error> {
+    let padding = data.last().copied().unwrap_or_default() as usize;
+    let valid = (1..=block_size).contains(&padding)
+        && padding <= data.len()
+        && data[data.len() - padding..]
+            .iter()
+            .all(|&b| b as usize == padding);
+    if !valid {
+        return Err(Error::InvalidPadding);
+    }
+    data.truncate(data.len() - padding);
+    Ok(())
+}
+
+/// Encodes an index (e.g. a sector or record number) into the first bytes
+/// of a block in little-endian order, zero-padded to the block size.
+///
+/// Fails with the index if it doesn't fit into a block of RC5-8 or RC5-16.
+#[cfg(feature = "encrypt")]
+pub(crate) fn index_block(index: u64, block_size: usize) -> Result<[u8; MAX_BLOCK_SIZE], u64> {
+    let mut block = [0; MAX_BLOCK_SIZE];
+    block[..8].copy_from_slice(&index.to_le_bytes());
+    if block[block_size..].iter().any(|&b| b != 0) {
+        return Err(index);
+    }
+    Ok(block)
+}
+
+/// Compares tags in constant time.
+#[cfg(feature = "encrypt")]
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// XORs `key` into `block`, up to the shorter of them.
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+pub(crate) fn xor(block: &mut [u8], key: &[u8]) {
+    for (b, k) in block.iter_mut().zip(key) {
+        *b ^= k;
+    }
+}
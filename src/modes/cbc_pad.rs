@@ -0,0 +1,304 @@
+//! RC5-CBC-Pad (RFC 2040, section 7).
+//!
+//! CBC mode with an initialization vector of a block, padding the plain text
+//! with 1 to block size bytes, each holding the number of padding bytes.
+//! The cipher text is always longer than the plain text, by up to a block.
+//!
+//! The parameters of the algorithm (version, rounds, block size and IV)
+//! are encoded as in PKCS #5 v2.0 (RFC 2898, appendix B.2.4),
+//! i.e. the DER encoding of
+//!
+//! ```text
+//! RC5-CBC-Parameters ::= SEQUENCE {
+//!     version INTEGER {v1-0(16)} (v1-0),
+//!     rounds INTEGER (8..127),
+//!     blockSizeInBits INTEGER (64 | 128),
+//!     iv OCTET STRING OPTIONAL }
+//! ```
+//!
+//! which only covers RC5-32 and RC5-64.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use zeroize::Zeroizing;
+
+use crate::{
+    audit::Operation,
+    block_cipher::{BlockCipher, MAX_BLOCK_SIZE},
+    Error, FromLeBytes, SecretKey, ToLeBytes, Word, RC5,
+};
+
+/// The RC5-CBC-Pad algorithm with an initialization vector.
+#[derive(Clone)]
+pub struct Rc5CbcPad<W: Word> {
+    cipher: RC5<W>,
+    iv: [u8; MAX_BLOCK_SIZE],
+}
+
+impl<W: Word> Rc5CbcPad<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Version 1.0 of RC5, the only one.
+    pub const VERSION: u8 = 0x10;
+
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    /// Numbers of rounds allowed by the parameters encoding.
+    const ROUNDS: std::ops::RangeInclusive<usize> = 8..=127;
+
+    /// Creates the algorithm with an initialization vector of a block.
+    pub fn new(cipher: RC5<W>, iv: &[u8]) -> Result<Self, Error> {
+        let mut algorithm = Self {
+            cipher,
+            iv: [0; MAX_BLOCK_SIZE],
+        };
+        algorithm.set_iv(iv)?;
+        Ok(algorithm)
+    }
+
+    /// Creates the algorithm for a secret key and DER encoded parameters,
+    /// which must include an IV and match the block size of `W`.
+    pub fn from_parameters(secret_key: &SecretKey, parameters: &[u8]) -> Result<Self, Error> {
+        let (version, rounds, block_size_in_bits, iv) =
+            parse_parameters(parameters).ok_or(Error::InvalidParameters)?;
+        if version != u64::from(Self::VERSION)
+            || !Self::ROUNDS.contains(&(rounds as usize))
+            || block_size_in_bits != 8 * Self::BLOCK_SIZE as u64
+        {
+            return Err(Error::InvalidParameters);
+        }
+
+        let cipher = RC5::from_secret_key(secret_key, rounds as usize)?;
+        Self::new(cipher, iv.ok_or(Error::InvalidParameters)?)
+    }
+
+    /// Sets the initialization vector, which should be unique per message.
+    pub fn set_iv(&mut self, iv: &[u8]) -> Result<(), Error> {
+        if iv.len() != Self::BLOCK_SIZE {
+            return Err(Error::InvalidIvLength {
+                actual: iv.len(),
+                expected: Self::BLOCK_SIZE,
+            });
+        }
+        self.iv[..Self::BLOCK_SIZE].copy_from_slice(iv);
+        Ok(())
+    }
+
+    /// DER encoding of the parameters, including the IV.
+    ///
+    /// Fails for the word sizes the encoding doesn't cover,
+    /// and with `Error::InvalidParameters` for numbers of rounds outside of 8 to 127.
+    pub fn parameters(&self) -> Result<Vec<u8>, Error> {
+        let rounds = self.cipher.number_of_rounds;
+        if !Self::ROUNDS.contains(&rounds) {
+            return Err(Error::InvalidParameters);
+        }
+        let block_size_in_bits: &[u8] = match Self::BLOCK_SIZE {
+            8 => &[0x40],
+            // 128 has the sign bit set, so it needs a leading zero byte.
+            16 => &[0x00, 0x80],
+            _ => return Err(Error::InvalidWordSize(W::BITS)),
+        };
+
+        let mut fields = Vec::new();
+        der(&mut fields, INTEGER, &[Self::VERSION]);
+        der(&mut fields, INTEGER, &[rounds as u8]);
+        der(&mut fields, INTEGER, block_size_in_bits);
+        der(&mut fields, OCTET_STRING, &self.iv[..Self::BLOCK_SIZE]);
+
+        let mut parameters = Vec::new();
+        der(&mut parameters, SEQUENCE, &fields);
+        Ok(parameters)
+    }
+
+    /// Encrypts plain text of any length.
+    #[cfg(feature = "encrypt")]
+    pub fn encrypt(&self, plaintext: impl AsRef<[u8]>) -> Vec<u8> {
+        let plaintext = plaintext.as_ref();
+        let padding = Self::BLOCK_SIZE - plaintext.len() % Self::BLOCK_SIZE;
+
+        let mut data = Zeroizing::new(plaintext.to_vec());
+        data.resize(plaintext.len() + padding, padding as u8);
+        super::cbc_encrypt(&self.cipher, &self.iv[..Self::BLOCK_SIZE], &mut data)
+            .expect("padded to the block size");
+        self.cipher.audit(Operation::Encrypt, plaintext.len());
+        std::mem::take(&mut *data)
+    }
+
+    /// Decrypts cipher text, removing the padding.
+    #[cfg(feature = "decrypt")]
+    pub fn decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+        let ciphertext = ciphertext.as_ref();
+        if ciphertext.is_empty() {
+            return Err(Error::CiphertextTooShort {
+                actual: 0,
+                min: Self::BLOCK_SIZE,
+            });
+        }
+
+        let mut data = Zeroizing::new(ciphertext.to_vec());
+        super::cbc_decrypt(&self.cipher, &self.iv[..Self::BLOCK_SIZE], &mut data)?;
+        self.cipher.audit(Operation::Decrypt, ciphertext.len());
+        super::unpad(&mut data, Self::BLOCK_SIZE)?;
+        Ok(std::mem::take(&mut *data))
+    }
+}
+
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const SEQUENCE: u8 = 0x30;
+
+/// Appends a DER element with a short (up to 127 bytes) value.
+fn der(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.extend_from_slice(&[tag, value.len() as u8]);
+    out.extend_from_slice(value);
+}
+
+/// Splits off a DER element of a given tag with a short (up to 127 bytes) value.
+fn read_der(bytes: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match bytes {
+        [t, len, rest @ ..] if *t == tag && *len < 0x80 && rest.len() >= *len as usize => {
+            Some(rest.split_at(*len as usize))
+        }
+        _ => None,
+    }
+}
+
+/// A non-negative DER integer of up to 8 bytes, without a sign byte.
+fn read_integer(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let (value, rest) = read_der(bytes, INTEGER)?;
+    let minimal = match value {
+        [] => false,
+        [0x00, next, ..] => next & 0x80 != 0,
+        [first, ..] => first & 0x80 == 0,
+    };
+    let digits = match value {
+        [0x00, digits @ ..] if !digits.is_empty() => digits,
+        digits => digits,
+    };
+    if !minimal || digits.len() > 8 {
+        return None;
+    }
+    Some((digits.iter().fold(0, |n, &b| n << 8 | u64::from(b)), rest))
+}
+
+/// Parses the version, rounds, block size in bits and the optional IV.
+fn parse_parameters(bytes: &[u8]) -> Option<(u64, u64, u64, Option<&[u8]>)> {
+    let (fields, rest) = read_der(bytes, SEQUENCE)?;
+    if !rest.is_empty() {
+        return None;
+    }
+
+    let (version, fields) = read_integer(fields)?;
+    let (rounds, fields) = read_integer(fields)?;
+    let (block_size_in_bits, fields) = read_integer(fields)?;
+    let iv = match fields {
+        [] => None,
+        fields => match read_der(fields, OCTET_STRING)? {
+            (iv, []) => Some(iv),
+            _ => return None,
+        },
+    };
+    Some((version, rounds, block_size_in_bits, iv))
+}
+
+#[cfg(all(test, feature = "encrypt"))]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn rc5_cbc_pad(key: &str, iv: &str) -> Rc5CbcPad<u32> {
+        let rc5 = RC5::<u32>::new_with_rounds(hex(key), 8).unwrap();
+        Rc5CbcPad::new(rc5, &hex(iv)).unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn rfc_2040_vectors() {
+        // RFC 2040, section 9, RC5_CBC_Pad test vectors.
+        let vectors = [
+            (
+                "0000000000000000",
+                "ffffffffffffffff",
+                "7875dbf6738c64788f34c3c681c99695",
+            ),
+            (
+                "0000000000000000",
+                "ffffffffffffffff7875dbf6738c647811223344556677",
+                "7875dbf6738c64787cb3f1df34f948117fd1a023a5bba217",
+            ),
+        ];
+        for (iv, pt, ct) in vectors {
+            let algorithm = rc5_cbc_pad("0102030405", iv);
+            assert_eq!(algorithm.encrypt(hex(pt)), hex(ct));
+            assert_eq!(algorithm.decrypt(hex(ct)).unwrap(), hex(pt));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn padding() {
+        let algorithm = rc5_cbc_pad("0102030405", "0102030405060708");
+        for len in 0..20 {
+            let ct = algorithm.encrypt(vec![7; len]);
+            assert_eq!(ct.len(), (len / 8 + 1) * 8);
+            assert_eq!(algorithm.decrypt(&ct).unwrap(), vec![7; len]);
+        }
+
+        let mut ct = algorithm.encrypt([7; 5]);
+        ct[0] ^= 1;
+        assert_eq!(algorithm.decrypt(&ct), Err(Error::InvalidPadding));
+        assert!(matches!(
+            algorithm.decrypt([0; 12]),
+            Err(Error::InvalidInputLength { actual: 12, .. })
+        ));
+        assert!(algorithm.decrypt([]).is_err());
+    }
+
+    #[test]
+    fn parameters() {
+        let algorithm = rc5_cbc_pad("0102030405", "0102030405060708");
+        let parameters = algorithm.parameters().unwrap();
+        assert_eq!(
+            parameters,
+            hex("301302011002010802014004080102030405060708")
+        );
+
+        let key = SecretKey::new(hex("0102030405")).unwrap();
+        let parsed = Rc5CbcPad::<u32>::from_parameters(&key, &parameters).unwrap();
+        assert_eq!(parsed.encrypt([1, 2, 3]), algorithm.encrypt([1, 2, 3]));
+
+        // The block size doesn't match RC5-64.
+        assert_eq!(
+            Rc5CbcPad::<u64>::from_parameters(&key, &parameters).err(),
+            Some(Error::InvalidParameters)
+        );
+        // No IV.
+        let no_iv = hex("3009020110020108020140");
+        assert!(Rc5CbcPad::<u32>::from_parameters(&key, &no_iv).is_err());
+
+        let rc5 = RC5::<u64>::new_with_rounds(hex("0102030405"), 16).unwrap();
+        let parameters = Rc5CbcPad::new(rc5, &[0; 16]).unwrap().parameters().unwrap();
+        assert_eq!(parameters[8..12], [0x02, 0x02, 0x00, 0x80]);
+        assert!(Rc5CbcPad::<u64>::from_parameters(&key, &parameters).is_ok());
+
+        for rounds in [4, 128] {
+            let rc5 = RC5::<u32>::new_with_rounds(hex("0102030405"), rounds).unwrap();
+            assert_eq!(
+                Rc5CbcPad::new(rc5, &[0; 8]).unwrap().parameters(),
+                Err(Error::InvalidParameters)
+            );
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! Counter with CBC-MAC (CCM) authenticated encryption (NIST SP 800-38C, RFC 3610).
+//!
+//! The tag is a CBC-MAC of the nonce, the associated data and the plain text,
+//! and the plain text and the tag are then encrypted in counter mode:
+//!
+//! ```text
+//! T = CBC-MAC(B[0] || encoded associated data || plain text)
+//! C = (P ^ E(A[1]) || E(A[2]) || ...) || (T ^ E(A[0]))
+//! ```
+//!
+//! where `B[0]` holds the flags, the nonce and the length of the plain text,
+//! and the counter blocks `A[i]` hold the nonce and `i`. Both are formatted
+//! as specified, with the length field `q = block size - 1 - nonce length`,
+//! so RC5-64 (128-bit blocks) follows the standard and the other word sizes
+//! scale it down to their blocks.
+//!
+//! Only the encryption of the block cipher is used, in both directions.
+//! A nonce must never be reused under the same key.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{ct_eq, xor};
+use crate::{
+    audit::Operation,
+    block_cipher::{BlockCipher, MAX_BLOCK_SIZE},
+    Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// CCM mode with a tag length.
+#[derive(Clone)]
+pub struct Ccm<W: Word> {
+    cipher: RC5<W>,
+    tag_len: usize,
+}
+
+impl<W: Word> Ccm<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    /// Creates CCM mode with tags of `tag_len` bytes,
+    /// an even number from 4 up to the block size.
+    ///
+    /// Longer tags make forgeries less likely, RC5-64 allows tags of up to 16 bytes.
+    pub fn new(cipher: RC5<W>, tag_len: usize) -> Result<Self, Error> {
+        if !(4..=Self::BLOCK_SIZE).contains(&tag_len) || !tag_len.is_multiple_of(2) {
+            return Err(Error::InvalidTagLength(tag_len));
+        }
+        Ok(Self { cipher, tag_len })
+    }
+
+    /// Length of the tags in bytes.
+    pub fn tag_len(&self) -> usize {
+        self.tag_len
+    }
+
+    /// Shortest valid nonce in bytes.
+    ///
+    /// Shorter nonces leave more room for the length of the plain text,
+    /// which must be less than `2^(8 * (block size - 1 - nonce length))` bytes.
+    pub fn min_nonce_len() -> usize {
+        // The length field holds at most 8 bytes.
+        Self::BLOCK_SIZE.saturating_sub(9).max(1)
+    }
+
+    /// Longest valid nonce in bytes (13 for RC5-64).
+    ///
+    /// RC5-8 blocks are too short for CCM, so it is 0 and no nonce is valid.
+    pub fn max_nonce_len() -> usize {
+        // The length field holds at least 2 bytes.
+        Self::BLOCK_SIZE.saturating_sub(3)
+    }
+
+    /// Encrypts and authenticates plain text, also authenticating
+    /// the associated data, and appends the tag to the cipher text.
+    pub fn encrypt(
+        &self,
+        nonce: &[u8],
+        associated_data: impl AsRef<[u8]>,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, Error> {
+        let plaintext = plaintext.as_ref();
+        self.check(nonce, plaintext.len())?;
+        self.cipher
+            .memory_budget
+            .check(plaintext.len() + self.tag_len)?;
+
+        let tag = self.tag(nonce, associated_data.as_ref(), plaintext);
+        let mut data = Zeroizing::new(plaintext.to_vec());
+        self.apply_keystream(nonce, &mut data);
+        self.cipher.audit(Operation::Encrypt, plaintext.len());
+        data.extend_from_slice(&tag[..self.tag_len]);
+        Ok(std::mem::take(&mut *data))
+    }
+
+    /// Verifies and decrypts cipher text produced by `encrypt`
+    /// with the same nonce and associated data.
+    ///
+    /// Nothing is returned unless the tag is valid.
+    pub fn decrypt(
+        &self,
+        nonce: &[u8],
+        associated_data: impl AsRef<[u8]>,
+        ciphertext: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, Error> {
+        let ciphertext = ciphertext.as_ref();
+        if ciphertext.len() < self.tag_len {
+            return Err(Error::CiphertextTooShort {
+                actual: ciphertext.len(),
+                min: self.tag_len,
+            });
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - self.tag_len);
+        self.check(nonce, ciphertext.len())?;
+        self.cipher.memory_budget.check(ciphertext.len())?;
+
+        let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+        self.apply_keystream(nonce, &mut plaintext);
+        self.cipher.audit(Operation::Decrypt, ciphertext.len());
+        let expected = self.tag(nonce, associated_data.as_ref(), &plaintext);
+        if !ct_eq(&expected[..self.tag_len], tag) {
+            return Err(Error::AuthenticationFailed);
+        }
+        Ok(std::mem::take(&mut *plaintext))
+    }
+
+    /// Checks the nonce length and that the message length fits the length field.
+    fn check(&self, nonce: &[u8], len: usize) -> Result<(), Error> {
+        let (min, max) = (Self::min_nonce_len(), Self::max_nonce_len());
+        if !(min..=max).contains(&nonce.len()) {
+            return Err(Error::InvalidNonceLength {
+                actual: nonce.len(),
+                min,
+                max,
+            });
+        }
+        let q = Self::BLOCK_SIZE - 1 - nonce.len();
+        if q < 8 && len as u64 >> (8 * q) != 0 {
+            return Err(Error::InputTooLarge);
+        }
+        Ok(())
+    }
+
+    /// The encrypted tag, `T ^ E(A[0])`.
+    fn tag(&self, nonce: &[u8], associated_data: &[u8], plaintext: &[u8]) -> [u8; MAX_BLOCK_SIZE] {
+        let bs = Self::BLOCK_SIZE;
+        let q = bs - 1 - nonce.len();
+
+        // B[0]
+        let mut b0 = [0; MAX_BLOCK_SIZE];
+        let adata = if associated_data.is_empty() { 0 } else { 0x40 };
+        b0[0] = adata | (((self.tag_len - 2) / 2) as u8) << 3 | (q - 1) as u8;
+        b0[1..=nonce.len()].copy_from_slice(nonce);
+        b0[bs - q..bs].copy_from_slice(&(plaintext.len() as u64).to_be_bytes()[8 - q..]);
+
+        let mut mac = CbcMac::new(&self.cipher);
+        mac.update(&b0[..bs]);
+        if !associated_data.is_empty() {
+            let len = associated_data.len() as u64;
+            if len < 0xFF00 {
+                mac.update(&(len as u16).to_be_bytes());
+            } else if len <= u64::from(u32::MAX) {
+                mac.update(&[0xFF, 0xFE]);
+                mac.update(&(len as u32).to_be_bytes());
+            } else {
+                mac.update(&[0xFF, 0xFF]);
+                mac.update(&len.to_be_bytes());
+            }
+            mac.update(associated_data);
+            mac.pad();
+        }
+        mac.update(plaintext);
+        mac.pad();
+
+        let mut tag = mac.state;
+        let mut s0 = self.counter_block(nonce, 0);
+        self.cipher.encrypt_block(&mut s0[..bs]);
+        xor(&mut tag[..bs], &s0[..bs]);
+        s0.zeroize();
+        tag
+    }
+
+    /// XORs the keystream `E(A[1]) || E(A[2]) || ...` into data.
+    fn apply_keystream(&self, nonce: &[u8], data: &mut [u8]) {
+        let bs = Self::BLOCK_SIZE;
+        for (i, chunk) in data.chunks_mut(bs).enumerate() {
+            let mut keystream = self.counter_block(nonce, i as u64 + 1);
+            self.cipher.encrypt_block(&mut keystream[..bs]);
+            xor(chunk, &keystream[..bs]);
+            keystream.zeroize();
+        }
+    }
+
+    /// The counter block `A[i]`, holding the flags, the nonce and the counter.
+    fn counter_block(&self, nonce: &[u8], i: u64) -> [u8; MAX_BLOCK_SIZE] {
+        let bs = Self::BLOCK_SIZE;
+        let q = bs - 1 - nonce.len();
+
+        let mut block = [0; MAX_BLOCK_SIZE];
+        block[0] = (q - 1) as u8;
+        block[1..=nonce.len()].copy_from_slice(nonce);
+        block[bs - q..bs].copy_from_slice(&i.to_be_bytes()[8 - q..]);
+        block
+    }
+}
+
+/// CBC-MAC over a stream of bytes, with an all-zero IV.
+struct CbcMac<'a, W: Word> {
+    cipher: &'a RC5<W>,
+    state: [u8; MAX_BLOCK_SIZE],
+    /// Number of bytes XORed into the current block.
+    pos: usize,
+}
+
+impl<'a, W: Word> CbcMac<'a, W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    fn new(cipher: &'a RC5<W>) -> Self {
+        Self {
+            cipher,
+            state: [0; MAX_BLOCK_SIZE],
+            pos: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.state[self.pos] ^= byte;
+            self.pos += 1;
+            if self.pos == Self::BLOCK_SIZE {
+                self.cipher
+                    .encrypt_block(&mut self.state[..Self::BLOCK_SIZE]);
+                self.pos = 0;
+            }
+        }
+    }
+
+    /// Pads the current block with zeros.
+    fn pad(&mut self) {
+        if self.pos != 0 {
+            self.cipher
+                .encrypt_block(&mut self.state[..Self::BLOCK_SIZE]);
+            self.pos = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryBudget;
+
+    fn rc5() -> RC5<u64> {
+        RC5::new(vec![0x42; 16]).unwrap()
+    }
+
+    #[test]
+    fn encrypt_decrypt() {
+        let ccm = Ccm::new(rc5(), 16).unwrap();
+        let nonce = [7; 13];
+        for (aad_len, len) in [(0, 0), (0, 1), (5, 16), (20, 33), (0xFF00, 3)] {
+            let aad = vec![1; aad_len];
+            let pt = vec![2; len];
+            let ct = ccm.encrypt(&nonce, &aad, &pt).unwrap();
+            assert_eq!(ct.len(), len + 16);
+            assert_eq!(ccm.decrypt(&nonce, &aad, &ct).unwrap(), pt);
+        }
+    }
+
+    #[test]
+    fn format() {
+        let rc5 = rc5();
+        let ccm = Ccm::new(rc5.clone(), 8).unwrap();
+        let nonce = [7; 13];
+        let ct = ccm.encrypt(&nonce, [], [0; 16]).unwrap();
+
+        // The plain text is XORed with `E(A[1])`, where `A[1] = q - 1 || N || 1`.
+        let mut a1 = [1; 16];
+        a1[0] = 1;
+        a1[1..14].copy_from_slice(&nonce);
+        a1[14] = 0;
+        assert_eq!(ct[..16], rc5.encode(a1));
+
+        // The tag is `E(B[0]) ^ E(A[0])`, where `B[0] = flags || N || 16`
+        // with the flags `(8 - 2) / 2 << 3 | q - 1`.
+        let ct = ccm.encrypt(&nonce, [], []).unwrap();
+        let mut b0 = [0; 16];
+        b0[0] = 3 << 3 | 1;
+        b0[1..14].copy_from_slice(&nonce);
+        let mut a0 = a1;
+        a0[15] = 0;
+        let mut tag = rc5.encode(b0);
+        xor(&mut tag, &rc5.encode(a0));
+        assert_eq!(ct, tag[..8]);
+    }
+
+    #[test]
+    fn authentication() {
+        let ccm = Ccm::new(rc5(), 12).unwrap();
+        let nonce = [7; 11];
+        let ct = ccm.encrypt(&nonce, b"header", b"message").unwrap();
+
+        for i in 0..ct.len() {
+            let mut forged = ct.clone();
+            forged[i] ^= 0x01;
+            assert_eq!(
+                ccm.decrypt(&nonce, b"header", &forged),
+                Err(Error::AuthenticationFailed)
+            );
+        }
+        assert!(ccm.decrypt(&nonce, b"Header", &ct).is_err());
+        assert!(ccm.decrypt(&[8; 11], b"header", &ct).is_err());
+        assert_eq!(
+            ccm.decrypt(&nonce, b"header", &ct[..11]),
+            Err(Error::CiphertextTooShort {
+                actual: 11,
+                min: 12
+            })
+        );
+    }
+
+    #[test]
+    fn memory_budget() {
+        let ccm = Ccm::new(rc5().with_memory_budget(MemoryBudget::new(32)), 16).unwrap();
+        let nonce = [7; 13];
+        let ct = ccm.encrypt(&nonce, [], [0; 16]).unwrap();
+        assert_eq!(
+            ccm.encrypt(&nonce, [], [0; 17]),
+            Err(Error::MemoryBudgetExceeded {
+                requested: 33,
+                budget: 32
+            })
+        );
+        assert!(ccm.decrypt(&nonce, [], &ct).is_ok());
+
+        let ccm = Ccm::new(rc5().with_memory_budget(MemoryBudget::new(8)), 16).unwrap();
+        assert_eq!(
+            ccm.decrypt(&nonce, [], &ct),
+            Err(Error::MemoryBudgetExceeded {
+                requested: 16,
+                budget: 8
+            })
+        );
+    }
+
+    #[test]
+    fn parameters() {
+        assert_eq!(Ccm::<u64>::min_nonce_len(), 7);
+        assert_eq!(Ccm::<u64>::max_nonce_len(), 13);
+        assert_eq!(Ccm::<u32>::min_nonce_len(), 1);
+        assert_eq!(Ccm::<u32>::max_nonce_len(), 5);
+        assert_eq!(Ccm::<u8>::max_nonce_len(), 0);
+
+        for tag_len in [0, 2, 5, 18] {
+            assert!(matches!(
+                Ccm::new(rc5(), tag_len),
+                Err(Error::InvalidTagLength(_))
+            ));
+        }
+        assert!(Ccm::new(RC5::<u32>::new(vec![0x42; 16]).unwrap(), 10).is_err());
+        assert!(Ccm::new(RC5::<u8>::new(vec![0x42; 16]).unwrap(), 4).is_err());
+
+        let ccm = Ccm::new(rc5(), 16).unwrap();
+        assert_eq!(
+            ccm.encrypt(&[0; 6], [], []),
+            Err(Error::InvalidNonceLength {
+                actual: 6,
+                min: 7,
+                max: 13
+            })
+        );
+        assert!(ccm.encrypt(&[0; 14], [], []).is_err());
+
+        // A nonce of 5 bytes leaves 2 bytes for the plain text length of RC5-32.
+        let ccm = Ccm::new(RC5::<u32>::new(vec![0x42; 16]).unwrap(), 8).unwrap();
+        assert!(ccm.encrypt(&[0; 5], [], vec![0; 0xFFFF]).is_ok());
+        assert_eq!(
+            ccm.encrypt(&[0; 5], [], vec![0; 0x10000]),
+            Err(Error::InputTooLarge)
+        );
+        let ct = ccm.encrypt(&[0; 1], [], vec![0; 0x10000]).unwrap();
+        assert_eq!(ccm.decrypt(&[0; 1], [], ct).unwrap(), vec![0; 0x10000]);
+    }
+}
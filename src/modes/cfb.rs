@@ -0,0 +1,276 @@
+//! Cipher feedback (CFB) mode.
+//!
+//! The plain text is XORed with the encryption of the previous cipher text,
+//! starting with the encryption of an initialization vector:
+//!
+//! * `Cfb` - full-block CFB, feeding back a block of cipher text at a time.
+//! * `Cfb8` - 8-bit CFB, feeding back a byte at a time (a block encryption per byte).
+//!
+//! Both encrypt messages of any length without padding, which are meant
+//! to interoperate with legacy systems that encrypted with RC5-CFB.
+//! Decryption only uses the encryption direction of the block function.
+//! An IV must never be reused with the same key.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use zeroize::Zeroize;
+
+use super::BlockMode;
+use crate::{
+    audit::Operation,
+    block_cipher::{BlockCipher, MAX_BLOCK_SIZE},
+    Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// Full-block CFB mode.
+///
+/// Consecutive calls continue where the previous one stopped,
+/// so a message can be encrypted in pieces.
+#[derive(Clone)]
+pub struct Cfb<W: Word> {
+    cipher: RC5<W>,
+    /// The current keystream block, the encryption of the previous cipher text block.
+    keystream: [u8; MAX_BLOCK_SIZE],
+    /// The cipher text block being collected.
+    feedback: [u8; MAX_BLOCK_SIZE],
+    /// Index of the next byte of the current block.
+    pos: usize,
+}
+
+impl<W: Word> Cfb<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    /// Creates CFB mode with an initialization vector of a block.
+    pub fn new(cipher: RC5<W>, iv: &[u8]) -> Result<Self, Error> {
+        let mut feedback = [0; MAX_BLOCK_SIZE];
+        feedback[..Self::BLOCK_SIZE].copy_from_slice(check_iv::<W>(iv)?);
+
+        // The IV is encrypted by the first call.
+        Ok(Self {
+            cipher,
+            keystream: [0; MAX_BLOCK_SIZE],
+            feedback,
+            pos: Self::BLOCK_SIZE,
+        })
+    }
+
+    /// Encrypts data in place.
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            self.refill();
+            *byte ^= self.keystream[self.pos];
+            self.feedback[self.pos] = *byte;
+            self.pos += 1;
+        }
+        self.cipher.audit(Operation::Encrypt, data.len());
+    }
+
+    /// Decrypts data in place.
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            self.refill();
+            self.feedback[self.pos] = *byte;
+            *byte ^= self.keystream[self.pos];
+            self.pos += 1;
+        }
+        self.cipher.audit(Operation::Decrypt, data.len());
+    }
+
+    /// Encrypts the collected cipher text block once it is complete.
+    fn refill(&mut self) {
+        if self.pos == Self::BLOCK_SIZE {
+            self.keystream = self.feedback;
+            self.cipher
+                .encrypt_block(&mut self.keystream[..Self::BLOCK_SIZE]);
+            self.pos = 0;
+        }
+    }
+}
+
+impl<W: Word> BlockMode for Cfb<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    #[cfg(feature = "encrypt")]
+    fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Cfb::encrypt(self, data);
+        Ok(())
+    }
+
+    #[cfg(feature = "decrypt")]
+    fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Cfb::decrypt(self, data);
+        Ok(())
+    }
+}
+
+/// The buffered keystream block is wiped.
+impl<W: Word> Drop for Cfb<W> {
+    fn drop(&mut self) {
+        self.keystream.zeroize();
+    }
+}
+
+/// 8-bit CFB mode.
+///
+/// Consecutive calls continue where the previous one stopped,
+/// so a message can be encrypted in pieces.
+#[derive(Clone)]
+pub struct Cfb8<W: Word> {
+    cipher: RC5<W>,
+    /// The last block of input to the block function:
+    /// the IV followed by the cipher text so far.
+    register: [u8; MAX_BLOCK_SIZE],
+}
+
+impl<W: Word> Cfb8<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    /// Creates 8-bit CFB mode with an initialization vector of a block.
+    pub fn new(cipher: RC5<W>, iv: &[u8]) -> Result<Self, Error> {
+        let mut register = [0; MAX_BLOCK_SIZE];
+        register[..Self::BLOCK_SIZE].copy_from_slice(check_iv::<W>(iv)?);
+        Ok(Self { cipher, register })
+    }
+
+    /// Encrypts data in place.
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            *byte ^= self.keystream_byte();
+            self.shift(*byte);
+        }
+        self.cipher.audit(Operation::Encrypt, data.len());
+    }
+
+    /// Decrypts data in place.
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            let ciphertext = *byte;
+            *byte ^= self.keystream_byte();
+            self.shift(ciphertext);
+        }
+        self.cipher.audit(Operation::Decrypt, data.len());
+    }
+
+    /// The first byte of the encryption of the register.
+    fn keystream_byte(&self) -> u8 {
+        let mut block = self.register;
+        self.cipher.encrypt_block(&mut block[..Self::BLOCK_SIZE]);
+        let byte = block[0];
+        block.zeroize();
+        byte
+    }
+
+    /// Shifts a cipher text byte into the register.
+    fn shift(&mut self, ciphertext: u8) {
+        let register = &mut self.register[..Self::BLOCK_SIZE];
+        register.rotate_left(1);
+        register[Self::BLOCK_SIZE - 1] = ciphertext;
+    }
+}
+
+impl<W: Word> BlockMode for Cfb8<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    #[cfg(feature = "encrypt")]
+    fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Cfb8::encrypt(self, data);
+        Ok(())
+    }
+
+    #[cfg(feature = "decrypt")]
+    fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Cfb8::decrypt(self, data);
+        Ok(())
+    }
+}
+
+/// Checks that an IV is a block long.
+fn check_iv<W: Word>(iv: &[u8]) -> Result<&[u8], Error> {
+    if iv.len() != 2 * W::BYTES {
+        return Err(Error::InvalidIvLength {
+            actual: iv.len(),
+            expected: 2 * W::BYTES,
+        });
+    }
+    Ok(iv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IV: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    #[test]
+    fn cfb() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let pt = (0..100).collect::<Vec<u8>>();
+
+        let mut ct = pt.clone();
+        Cfb::new(rc5.clone(), &IV).unwrap().encrypt(&mut ct);
+        // C[0] = P[0] ^ E(IV), C[1] = P[1] ^ E(C[0]).
+        let keystream = rc5.encode(IV);
+        assert!((0..8).all(|i| ct[i] == pt[i] ^ keystream[i]));
+        let keystream = rc5.encode(&ct[..8]);
+        assert!((8..16).all(|i| ct[i] == pt[i] ^ keystream[i - 8]));
+
+        // In pieces of any length.
+        let mut cfb = Cfb::new(rc5.clone(), &IV).unwrap();
+        let mut pieces = pt.clone();
+        for piece in pieces.chunks_mut(13) {
+            cfb.encrypt(piece);
+        }
+        assert_eq!(pieces, ct);
+
+        let mut cfb = Cfb::new(rc5, &IV).unwrap();
+        for piece in ct.chunks_mut(11) {
+            cfb.decrypt(piece);
+        }
+        assert_eq!(ct, pt);
+    }
+
+    #[test]
+    fn cfb8() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let pt = (0..20).collect::<Vec<u8>>();
+
+        let mut ct = pt.clone();
+        Cfb8::new(rc5.clone(), &IV).unwrap().encrypt(&mut ct);
+        assert_eq!(ct[0], pt[0] ^ rc5.encode(IV)[0]);
+        let register = [2, 3, 4, 5, 6, 7, 8, ct[0]];
+        assert_eq!(ct[1], pt[1] ^ rc5.encode(register)[0]);
+
+        let mut cfb8 = Cfb8::new(rc5, &IV).unwrap();
+        cfb8.decrypt(&mut ct[..7]);
+        cfb8.decrypt(&mut ct[7..]);
+        assert_eq!(ct, pt);
+    }
+
+    #[test]
+    fn invalid_iv() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        assert!(matches!(
+            Cfb::new(rc5.clone(), &[0; 16]),
+            Err(Error::InvalidIvLength {
+                actual: 16,
+                expected: 8
+            })
+        ));
+        assert!(Cfb8::new(rc5, &[0; 7]).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Counter (CTR) mode.
+//!
+//! The plain text is XORed with the keystream of a nonce (see `Keystream`),
+//! so messages of any length are encrypted without padding
+//! and decryption is the same operation as encryption.
+//! A nonce must never be reused with the same key.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use super::BlockMode;
+use crate::{audit::Operation, Error, FromLeBytes, Keystream, ToLeBytes, Word, RC5};
+
+/// Counter mode with a given nonce.
+///
+/// Consecutive calls continue the keystream where the previous one stopped,
+/// so a message can be encrypted in pieces.
+#[derive(Clone)]
+pub struct Ctr<W: Word> {
+    cipher: RC5<W>,
+    nonce: W,
+    /// Position in the keystream in bytes.
+    position: u64,
+}
+
+impl<W: Word> Ctr<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Creates counter mode with a given nonce, which must be unique for the key.
+    pub fn new(cipher: RC5<W>, nonce: W) -> Self {
+        Self {
+            cipher,
+            nonce,
+            position: 0,
+        }
+    }
+
+    /// Position in the keystream in bytes.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Moves to a given position in the keystream,
+    /// e.g. to decrypt a part of a message.
+    pub fn seek(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    /// Encrypts data in place.
+    ///
+    /// Fails without changing `data` if the keystream is too short.
+    pub fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        self.apply(data, Operation::Encrypt)
+    }
+
+    /// Decrypts data in place, the same as `Ctr::encrypt`.
+    pub fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        self.apply(data, Operation::Decrypt)
+    }
+
+    /// XORs the keystream at the current position into `data`,
+    /// which is audited as an encryption.
+    pub fn apply_keystream(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        self.apply(data, Operation::Encrypt)
+    }
+
+    fn apply(&mut self, data: &mut [u8], operation: Operation) -> Result<(), Error> {
+        let mut keystream = Keystream::new(&self.cipher, self.nonce);
+        keystream.discard(self.position)?;
+        keystream.apply(data)?;
+        self.position += data.len() as u64;
+        self.cipher.audit(operation, data.len());
+        Ok(())
+    }
+}
+
+impl<W: Word> BlockMode for Ctr<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    #[cfg(feature = "encrypt")]
+    fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Ctr::encrypt(self, data)
+    }
+
+    #[cfg(feature = "decrypt")]
+    fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Ctr::decrypt(self, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let pt = (0..100).collect::<Vec<u8>>();
+
+        let mut ct = pt.clone();
+        Ctr::new(rc5.clone(), 5).encrypt(&mut ct).unwrap();
+        let keystream = Iterator::take(Keystream::new(&rc5, 5), 100);
+        let xored = pt.iter().zip(keystream).map(|(p, k)| p ^ k);
+        assert_eq!(xored.collect::<Vec<_>>(), ct);
+
+        // In pieces of any length.
+        let mut ctr = Ctr::new(rc5.clone(), 5);
+        let mut pieces = pt.clone();
+        for piece in pieces.chunks_mut(13) {
+            ctr.encrypt(piece).unwrap();
+        }
+        assert_eq!(pieces, ct);
+        assert_eq!(ctr.position(), 100);
+
+        let mut ctr = Ctr::new(rc5, 5);
+        ctr.seek(50);
+        ctr.decrypt(&mut ct[50..]).unwrap();
+        assert_eq!(ct[50..], pt[50..]);
+    }
+
+    #[test]
+    fn exhausted() {
+        let rc5 = RC5::<u8>::new(vec![0x42; 16]).unwrap();
+        let mut ctr = Ctr::new(rc5, 0);
+        ctr.seek(510);
+        let mut data = [1; 3];
+        assert_eq!(ctr.encrypt(&mut data), Err(Error::CounterExhausted));
+        assert_eq!(data, [1; 3]);
+        assert_eq!(ctr.position(), 510);
+    }
+}
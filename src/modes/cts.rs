@@ -0,0 +1,240 @@
+//! RC5-CTS, CBC with ciphertext stealing (RFC 2040, section 8).
+//!
+//! Messages of any length of at least a block are encrypted without expanding them.
+//! All but the last two blocks are encrypted as in CBC mode. The last full block
+//! `P[n - 1]` and the last (possibly partial) block `P[n]` of `L` bytes are
+//! processed as described by the RFC:
+//!
+//! ```text
+//! E[n - 1] = E(P[n - 1] ^ C[n - 2])
+//! C[n] = the first L bytes of E[n - 1]
+//! C[n - 1] = E(E[n - 1] ^ (P[n] padded with zeros))
+//! ```
+//!
+//! A message of exactly one block is encrypted as in CBC mode.
+//! The last block can be complete, then the last two blocks of
+//! CBC cipher text are swapped.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+#[cfg(feature = "decrypt")]
+use zeroize::Zeroizing;
+
+use super::{xor, BlockMode};
+use crate::{
+    audit::Operation,
+    block_cipher::{BlockCipher, MAX_BLOCK_SIZE},
+    Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// RC5-CTS mode with an initialization vector.
+#[derive(Clone)]
+pub struct Cts<W: Word> {
+    cipher: RC5<W>,
+    iv: [u8; MAX_BLOCK_SIZE],
+}
+
+impl<W: Word> Cts<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    /// Creates RC5-CTS mode with an initialization vector of a block.
+    pub fn new(cipher: RC5<W>, iv: &[u8]) -> Result<Self, Error> {
+        if iv.len() != Self::BLOCK_SIZE {
+            return Err(Error::InvalidIvLength {
+                actual: iv.len(),
+                expected: Self::BLOCK_SIZE,
+            });
+        }
+
+        let mut block = [0; MAX_BLOCK_SIZE];
+        block[..Self::BLOCK_SIZE].copy_from_slice(iv);
+        Ok(Self { cipher, iv: block })
+    }
+
+    /// Encrypts a message of at least a block in place.
+    #[cfg(feature = "encrypt")]
+    pub fn encrypt(&self, data: &mut [u8]) -> Result<(), Error> {
+        let bs = Self::BLOCK_SIZE;
+        let len = data.len();
+        match self.split(data)? {
+            None => super::cbc_encrypt(&self.cipher, self.iv(), data)?,
+            Some((head, last_full, last)) => {
+                super::cbc_encrypt(&self.cipher, self.iv(), head)?;
+                let prev = match head.len() {
+                    0 => self.iv(),
+                    len => &head[len - bs..],
+                };
+
+                // E[n - 1] = E(P[n - 1] ^ C[n - 2]).
+                xor(last_full, prev);
+                self.cipher.encrypt_block(last_full);
+                // C[n] is the head of E[n - 1], C[n - 1] = E(E[n - 1] ^ P[n] || 0...).
+                for (e, p) in last_full.iter_mut().zip(last.iter_mut()) {
+                    *p ^= *e;
+                    std::mem::swap(e, p);
+                }
+                self.cipher.encrypt_block(last_full);
+            }
+        }
+        self.cipher.audit(Operation::Encrypt, len);
+        Ok(())
+    }
+
+    /// Decrypts a message of at least a block in place.
+    #[cfg(feature = "decrypt")]
+    pub fn decrypt(&self, data: &mut [u8]) -> Result<(), Error> {
+        let bs = Self::BLOCK_SIZE;
+        let len = data.len();
+        match self.split(data)? {
+            None => super::cbc_decrypt(&self.cipher, self.iv(), data)?,
+            Some((head, last_full, last)) => {
+                let mut prev = Zeroizing::new([0; MAX_BLOCK_SIZE]);
+                match head.len() {
+                    0 => prev[..bs].copy_from_slice(self.iv()),
+                    len => prev[..bs].copy_from_slice(&head[len - bs..]),
+                }
+
+                // X[n] = D(C[n - 1]) ^ C[n] || 0..., P[n] is its head, E[n - 1] = C[n] || its tail.
+                self.cipher.decrypt_block(last_full);
+                for (x, c) in last_full.iter_mut().zip(last.iter_mut()) {
+                    *x ^= *c;
+                    std::mem::swap(x, c);
+                }
+                // P[n - 1] = D(E[n - 1]) ^ C[n - 2].
+                self.cipher.decrypt_block(last_full);
+                xor(last_full, &prev[..bs]);
+
+                super::cbc_decrypt(&self.cipher, self.iv(), head)?;
+            }
+        }
+        self.cipher.audit(Operation::Decrypt, len);
+        Ok(())
+    }
+
+    fn iv(&self) -> &[u8] {
+        &self.iv[..Self::BLOCK_SIZE]
+    }
+
+    /// Splits a message into its block-aligned head, the last full block
+    /// and the last block of 1 to block size bytes, or returns `None`
+    /// for a message of a single block.
+    #[allow(clippy::type_complexity)]
+    fn split<'d>(
+        &self,
+        data: &'d mut [u8],
+    ) -> Result<Option<(&'d mut [u8], &'d mut [u8], &'d mut [u8])>, Error> {
+        let bs = Self::BLOCK_SIZE;
+        if data.len() < bs {
+            return Err(Error::InvalidInputLength {
+                actual: data.len(),
+                block_size: bs,
+            });
+        }
+        if data.len() == bs {
+            return Ok(None);
+        }
+
+        let last_len = (data.len() - 1) % bs + 1;
+        let (head, tail) = data.split_at_mut(data.len() - last_len - bs);
+        let (last_full, last) = tail.split_at_mut(bs);
+        Ok(Some((head, last_full, last)))
+    }
+}
+
+impl<W: Word> BlockMode for Cts<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    #[cfg(feature = "encrypt")]
+    fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Cts::encrypt(self, data)
+    }
+
+    #[cfg(feature = "decrypt")]
+    fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Cts::decrypt(self, data)
+    }
+}
+
+#[cfg(all(test, feature = "encrypt"))]
+mod tests {
+    use super::*;
+
+    const IV: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn rfc_construction() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let cts = Cts::new(rc5.clone(), &IV).unwrap();
+        let pt = (0..21).collect::<Vec<u8>>();
+        let mut ct = pt.clone();
+        cts.encrypt(&mut ct).unwrap();
+
+        // C[0], as in CBC mode.
+        let mut x = pt[..8].to_vec();
+        xor(&mut x, &IV);
+        let c0 = rc5.encode(&x);
+        assert_eq!(ct[..8], c0);
+        // E[1] = E(P[1] ^ C[0]), C[2] is its head.
+        let mut x = pt[8..16].to_vec();
+        xor(&mut x, &c0);
+        let e1 = rc5.encode(&x);
+        assert_eq!(ct[16..], e1[..5]);
+        // C[1] = E(E[1] ^ P[2] || 0...).
+        let mut d = e1.clone();
+        xor(&mut d, &pt[16..]);
+        assert_eq!(ct[8..16], rc5.encode(&d));
+
+        cts.decrypt(&mut ct).unwrap();
+        assert_eq!(ct, pt);
+    }
+
+    #[test]
+    fn block_aligned() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let cts = Cts::new(rc5.clone(), &IV).unwrap();
+        let pt = [7; 24];
+
+        let mut cbc = pt;
+        super::super::cbc_encrypt(&rc5, &IV, &mut cbc).unwrap();
+        let mut ct = pt;
+        cts.encrypt(&mut ct).unwrap();
+        // The last two blocks of CBC cipher text are swapped.
+        assert_eq!(ct[..8], cbc[..8]);
+        assert_eq!(ct[8..16], cbc[16..]);
+        assert_eq!(ct[16..], cbc[8..16]);
+
+        // A single block is encrypted as in CBC mode.
+        let mut ct = [7; 8];
+        cts.encrypt(&mut ct).unwrap();
+        assert_eq!(ct, cbc[..8]);
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn lengths() {
+        let rc5 = RC5::<u16>::new(vec![0x42; 16]).unwrap();
+        let cts = Cts::new(rc5, &[9; 4]).unwrap();
+        let pt = (0..30).collect::<Vec<u8>>();
+        for len in 4..30 {
+            let mut data = pt[..len].to_vec();
+            cts.encrypt(&mut data).unwrap();
+            assert_ne!(data, pt[..len]);
+            cts.decrypt(&mut data).unwrap();
+            assert_eq!(data, pt[..len]);
+        }
+        assert!(matches!(
+            cts.encrypt(&mut [0; 3]),
+            Err(Error::InvalidInputLength { actual: 3, .. })
+        ));
+    }
+}
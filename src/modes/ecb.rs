@@ -0,0 +1,100 @@
+//! Electronic codebook (ECB) mode.
+//!
+//! Every block is encrypted independently, which is what `RC5::encode` does:
+//!
+//! ```text
+//! C[i] = E(P[i])
+//! ```
+//!
+//! Equal plain text blocks encrypt to equal cipher text blocks, so patterns
+//! of the plain text remain visible. Only use it for random data of a few blocks
+//! (e.g. wrapping keys) or for compatibility, otherwise prefer another mode.
+//! The plain text must be a multiple of the block size.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use super::BlockMode;
+use crate::{Error, FromLeBytes, ToLeBytes, Word, RC5};
+
+/// ECB mode.
+#[derive(Clone)]
+pub struct Ecb<W: Word> {
+    cipher: RC5<W>,
+}
+
+impl<W: Word> Ecb<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Creates ECB mode.
+    pub fn new(cipher: RC5<W>) -> Self {
+        Self { cipher }
+    }
+
+    /// Encrypts block-aligned data in place.
+    #[cfg(feature = "encrypt")]
+    pub fn encrypt(&self, data: &mut [u8]) -> Result<(), Error> {
+        self.cipher.encode_in_place(data, 0)
+    }
+
+    /// Decrypts block-aligned data in place.
+    #[cfg(feature = "decrypt")]
+    pub fn decrypt(&self, data: &mut [u8]) -> Result<(), Error> {
+        self.cipher.decode_in_place(data, 0)
+    }
+}
+
+impl<W: Word> BlockMode for Ecb<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    #[cfg(feature = "encrypt")]
+    fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Ecb::encrypt(self, data)
+    }
+
+    #[cfg(feature = "decrypt")]
+    fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Ecb::decrypt(self, data)
+    }
+}
+
+#[cfg(all(test, feature = "encrypt"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn same_as_encode() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let ecb = Ecb::new(rc5.clone());
+
+        let pt = (0..64).collect::<Vec<u8>>();
+        let mut data = pt.clone();
+        ecb.encrypt(&mut data).unwrap();
+        assert_eq!(data, rc5.encode(&pt));
+        ecb.decrypt(&mut data).unwrap();
+        assert_eq!(data, pt);
+
+        assert!(matches!(
+            ecb.encrypt(&mut [0; 12]),
+            Err(Error::InvalidInputLength {
+                actual: 12,
+                block_size: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn equal_blocks() {
+        let ecb = Ecb::new(RC5::<u32>::new(vec![0x42; 16]).unwrap());
+        let mut data = [7; 16];
+        ecb.encrypt(&mut data).unwrap();
+        assert_eq!(data[..8], data[8..]);
+    }
+}
@@ -0,0 +1,425 @@
+//! OCB authenticated encryption (RFC 7253, OCB3).
+//!
+//! Every block is encrypted once with a key and position dependent offset,
+//! which also makes it authenticated: there's no separate MAC pass as in
+//! encrypt-then-MAC or CCM, so OCB is about as fast as ECB.
+//!
+//! ```text
+//! Offset[i] = Offset[i - 1] ^ L[ntz(i)], Offset[0] derived from the nonce
+//! C[i] = Offset[i] ^ E(P[i] ^ Offset[i])
+//! T = E(P[1] ^ P[2] ^ ... ^ Offset ^ L[$]) ^ HASH(associated data)
+//! ```
+//!
+//! where `L[i]` are multiples of `L[*] = E(0)` in the field of 128-bit blocks.
+//! OCB is only specified for 128-bit blocks, i.e. RC5-64.
+//! A nonce must never be reused under the same key.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use zeroize::{Zeroize, Zeroizing};
+
+#[cfg(feature = "decrypt")]
+use super::ct_eq;
+use crate::{
+    audit::Operation, block_cipher::BlockCipher, Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// Size of a block in bytes (RC5-64).
+const BLOCK_SIZE: usize = 16;
+
+/// OCB mode with a tag length.
+#[derive(Clone)]
+pub struct Ocb<W: Word> {
+    cipher: RC5<W>,
+    tag_len: usize,
+    /// `L[*] = E(0)`.
+    l_star: u128,
+    /// `L[$] = double(L[*])`.
+    l_dollar: u128,
+    /// `L[0] = double(L[$])`, `L[i]` is computed on the fly.
+    l_0: u128,
+}
+
+impl<W: Word> Ocb<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Longest valid nonce in bytes.
+    pub const MAX_NONCE_LEN: usize = 15;
+
+    /// Creates OCB mode with tags of 1 up to 16 bytes.
+    ///
+    /// Fails for word sizes other than 64 bits.
+    pub fn new(cipher: RC5<W>, tag_len: usize) -> Result<Self, Error> {
+        if <RC5<W> as BlockCipher>::BLOCK_SIZE != BLOCK_SIZE {
+            return Err(Error::InvalidWordSize(W::BITS));
+        }
+        if !(1..=BLOCK_SIZE).contains(&tag_len) {
+            return Err(Error::InvalidTagLength(tag_len));
+        }
+
+        let mut ocb = Self {
+            cipher,
+            tag_len,
+            l_star: 0,
+            l_dollar: 0,
+            l_0: 0,
+        };
+        ocb.l_star = ocb.encrypt_block(0);
+        ocb.l_dollar = double(ocb.l_star);
+        ocb.l_0 = double(ocb.l_dollar);
+        Ok(ocb)
+    }
+
+    /// Length of the tags in bytes.
+    pub fn tag_len(&self) -> usize {
+        self.tag_len
+    }
+
+    /// Encrypts and authenticates plain text, also authenticating
+    /// the associated data, and appends the tag to the cipher text.
+    ///
+    /// The nonce is 1 up to 15 bytes long.
+    pub fn encrypt(
+        &self,
+        nonce: &[u8],
+        associated_data: impl AsRef<[u8]>,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, Error> {
+        let plaintext = plaintext.as_ref();
+        let mut offset = self.initial_offset(nonce)?;
+        self.cipher
+            .memory_budget
+            .check(plaintext.len() + self.tag_len)?;
+        let mut checksum = Zeroizing::new(0);
+        let mut data = Zeroizing::new(plaintext.to_vec());
+
+        let mut blocks = data.chunks_exact_mut(BLOCK_SIZE);
+        for (i, block) in (1..).zip(&mut blocks) {
+            offset ^= self.l(i);
+            let p = from_block(block);
+            *checksum ^= p;
+            let c = offset ^ self.encrypt_block(p ^ offset);
+            block.copy_from_slice(&c.to_be_bytes());
+        }
+        let last = blocks.into_remainder();
+        if !last.is_empty() {
+            *checksum ^= padded(last);
+            offset ^= self.l_star;
+            self.xor_pad(last, offset);
+        }
+
+        self.cipher.audit(Operation::Encrypt, plaintext.len());
+        let tag = self.tag(*checksum, offset, associated_data.as_ref());
+        data.extend_from_slice(&tag.to_be_bytes()[..self.tag_len]);
+        Ok(std::mem::take(&mut *data))
+    }
+
+    /// Verifies and decrypts cipher text produced by `encrypt`
+    /// with the same nonce and associated data.
+    ///
+    /// Nothing is returned unless the tag is valid.
+    #[cfg(feature = "decrypt")]
+    pub fn decrypt(
+        &self,
+        nonce: &[u8],
+        associated_data: impl AsRef<[u8]>,
+        ciphertext: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, Error> {
+        let ciphertext = ciphertext.as_ref();
+        if ciphertext.len() < self.tag_len {
+            return Err(Error::CiphertextTooShort {
+                actual: ciphertext.len(),
+                min: self.tag_len,
+            });
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - self.tag_len);
+
+        let mut offset = self.initial_offset(nonce)?;
+        self.cipher.memory_budget.check(ciphertext.len())?;
+        let mut checksum = Zeroizing::new(0);
+        let mut data = Zeroizing::new(ciphertext.to_vec());
+
+        let mut blocks = data.chunks_exact_mut(BLOCK_SIZE);
+        for (i, block) in (1..).zip(&mut blocks) {
+            offset ^= self.l(i);
+            let p = offset ^ self.decrypt_block(from_block(block) ^ offset);
+            *checksum ^= p;
+            block.copy_from_slice(&p.to_be_bytes());
+        }
+        let last = blocks.into_remainder();
+        if !last.is_empty() {
+            offset ^= self.l_star;
+            self.xor_pad(last, offset);
+            *checksum ^= padded(last);
+        }
+        self.cipher.audit(Operation::Decrypt, ciphertext.len());
+
+        let expected = self.tag(*checksum, offset, associated_data.as_ref());
+        if !ct_eq(&expected.to_be_bytes()[..self.tag_len], tag) {
+            return Err(Error::AuthenticationFailed);
+        }
+        Ok(std::mem::take(&mut *data))
+    }
+
+    /// `Offset[0]`, the bits `bottom..bottom + 128` of
+    /// `Stretch = Ktop || (Ktop[0..64] ^ Ktop[8..72])`.
+    fn initial_offset(&self, nonce: &[u8]) -> Result<u128, Error> {
+        if !(1..=Self::MAX_NONCE_LEN).contains(&nonce.len()) {
+            return Err(Error::InvalidNonceLength {
+                actual: nonce.len(),
+                min: 1,
+                max: Self::MAX_NONCE_LEN,
+            });
+        }
+
+        // `Nonce = TAGLEN mod 128 (7 bits) || 0...0 || 1 || N`
+        let mut block = [0; BLOCK_SIZE];
+        block[BLOCK_SIZE - nonce.len()..].copy_from_slice(nonce);
+        block[BLOCK_SIZE - 1 - nonce.len()] |= 1;
+        block[0] |= ((self.tag_len * 8 % 128) as u8) << 1;
+        let nonce = from_block(&block);
+
+        let bottom = (nonce & 0x3F) as u32;
+        let ktop = self.encrypt_block(nonce & !0x3F);
+        let stretch = ((ktop ^ ktop << 8) >> 64) as u64;
+        Ok(match bottom {
+            0 => ktop,
+            _ => ktop << bottom | u128::from(stretch >> (64 - bottom)),
+        })
+    }
+
+    /// The encrypted tag, `E(checksum ^ offset ^ L[$]) ^ HASH(associated data)`.
+    fn tag(&self, checksum: u128, offset: u128, associated_data: &[u8]) -> u128 {
+        self.encrypt_block(checksum ^ offset ^ self.l_dollar) ^ self.hash(associated_data)
+    }
+
+    /// `HASH`, a sum of offset encryptions of the associated data blocks.
+    fn hash(&self, associated_data: &[u8]) -> u128 {
+        let (mut sum, mut offset) = (0, 0);
+        let blocks = associated_data.chunks_exact(BLOCK_SIZE);
+        let last = blocks.remainder();
+        for (i, block) in (1..).zip(blocks) {
+            offset ^= self.l(i);
+            sum ^= self.encrypt_block(from_block(block) ^ offset);
+        }
+        if !last.is_empty() {
+            offset ^= self.l_star;
+            sum ^= self.encrypt_block(padded(last) ^ offset);
+        }
+        sum
+    }
+
+    /// XORs the pad `E(offset)` into a partial last block.
+    fn xor_pad(&self, last: &mut [u8], offset: u128) {
+        let mut pad = self.encrypt_block(offset).to_be_bytes();
+        for (b, p) in last.iter_mut().zip(&pad) {
+            *b ^= p;
+        }
+        pad.zeroize();
+    }
+
+    /// `L[ntz(i)]`, the offset increment of the `i`th block.
+    fn l(&self, i: u64) -> u128 {
+        (0..i.trailing_zeros()).fold(self.l_0, |l, _| double(l))
+    }
+
+    fn encrypt_block(&self, block: u128) -> u128 {
+        let mut block = block.to_be_bytes();
+        self.cipher.encrypt_block(&mut block);
+        u128::from_be_bytes(block)
+    }
+
+    #[cfg(feature = "decrypt")]
+    fn decrypt_block(&self, block: u128) -> u128 {
+        let mut block = block.to_be_bytes();
+        self.cipher.decrypt_block(&mut block);
+        u128::from_be_bytes(block)
+    }
+}
+
+/// The key-dependent offsets are wiped.
+impl<W: Word> Drop for Ocb<W> {
+    fn drop(&mut self) {
+        self.l_star.zeroize();
+        self.l_dollar.zeroize();
+        self.l_0.zeroize();
+    }
+}
+
+/// Multiplies a block by `x` modulo `x^128 + x^7 + x^2 + x + 1`.
+fn double(block: u128) -> u128 {
+    (block << 1) ^ ((block >> 127) * 0x87)
+}
+
+fn from_block(block: &[u8]) -> u128 {
+    u128::from_be_bytes(block.try_into().expect("a full block"))
+}
+
+/// A partial block padded with `1 || 0...0`.
+fn padded(last: &[u8]) -> u128 {
+    let mut block = [0; BLOCK_SIZE];
+    block[..last.len()].copy_from_slice(last);
+    block[last.len()] = 0x80;
+    from_block(&block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "decrypt")]
+    use crate::MemoryBudget;
+
+    #[cfg(feature = "decrypt")]
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn bytes(len: u8) -> Vec<u8> {
+        (0..len).collect()
+    }
+
+    fn rc5() -> RC5<u64> {
+        RC5::new_with_rounds(bytes(16), 24).unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn vectors() {
+        // Generated with a reference implementation checked against
+        // the RFC 7253 sample results for AES.
+        let vectors = [
+            (
+                "BBAA99887766554433221100",
+                0,
+                0,
+                16,
+                "f5d3f499f0ecf9ca09425aa62b981afc",
+            ),
+            (
+                "BBAA99887766554433221101",
+                8,
+                8,
+                16,
+                "76d673367a92a84a6f3663998f45b276ed6c8a8bc0fb4728",
+            ),
+            (
+                "BBAA99887766554433221102",
+                40,
+                40,
+                12,
+                "d043a785b0f17d4f6183ab0e86327719c186be4a6a7373168de6f15c\
+                 34eb1240495c2707a62beae23993a6666439c2de700a1366",
+            ),
+            (
+                "BBAA99887766554433221103",
+                0,
+                23,
+                16,
+                "4aeb6b332e61a9c5dfffe375a0572aa15078ff897e8edc29fb7fae9bf2b90331395ebac55ff712",
+            ),
+            ("BBAA99887766554433221104", 17, 0, 8, "457f409b37ae2d66"),
+        ];
+        for (nonce, aad_len, len, tag_len, ct) in vectors {
+            let ocb = Ocb::new(rc5(), tag_len).unwrap();
+            let (nonce, aad, pt) = (hex(nonce), bytes(aad_len), bytes(len));
+            assert_eq!(ocb.encrypt(&nonce, &aad, &pt).unwrap(), hex(ct));
+            assert_eq!(ocb.decrypt(&nonce, &aad, hex(ct)).unwrap(), pt);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn long_messages() {
+        let ocb = Ocb::new(rc5(), 16).unwrap();
+        for len in [0, 15, 16, 17, 255, 1000] {
+            let pt = vec![7; len];
+            let ct = ocb.encrypt(&[1; 12], [2; 33], &pt).unwrap();
+            assert_eq!(ct.len(), len + 16);
+            assert_eq!(ocb.decrypt(&[1; 12], [2; 33], &ct).unwrap(), pt);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn authentication() {
+        let ocb = Ocb::new(rc5(), 16).unwrap();
+        let nonce = [7; 12];
+        let ct = ocb.encrypt(&nonce, b"header", [1; 40]).unwrap();
+
+        for i in 0..ct.len() {
+            let mut forged = ct.clone();
+            forged[i] ^= 0x01;
+            assert_eq!(
+                ocb.decrypt(&nonce, b"header", &forged),
+                Err(Error::AuthenticationFailed)
+            );
+        }
+        assert!(ocb.decrypt(&nonce, b"Header", &ct).is_err());
+        assert!(ocb.decrypt(&[8; 12], b"header", &ct).is_err());
+        assert_eq!(
+            ocb.decrypt(&nonce, [], &ct[..15]),
+            Err(Error::CiphertextTooShort {
+                actual: 15,
+                min: 16
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn memory_budget() {
+        let ocb = Ocb::new(rc5().with_memory_budget(MemoryBudget::new(32)), 16).unwrap();
+        let nonce = [7; 12];
+        let ct = ocb.encrypt(&nonce, [], [0; 16]).unwrap();
+        assert_eq!(
+            ocb.encrypt(&nonce, [], [0; 17]),
+            Err(Error::MemoryBudgetExceeded {
+                requested: 33,
+                budget: 32
+            })
+        );
+        assert!(ocb.decrypt(&nonce, [], &ct).is_ok());
+
+        let ocb = Ocb::new(rc5().with_memory_budget(MemoryBudget::new(8)), 16).unwrap();
+        assert_eq!(
+            ocb.decrypt(&nonce, [], &ct),
+            Err(Error::MemoryBudgetExceeded {
+                requested: 16,
+                budget: 8
+            })
+        );
+    }
+
+    #[test]
+    fn parameters() {
+        assert_eq!(
+            Ocb::new(RC5::<u32>::new(bytes(16)).unwrap(), 8).err(),
+            Some(Error::InvalidWordSize(32))
+        );
+        assert_eq!(Ocb::new(rc5(), 0).err(), Some(Error::InvalidTagLength(0)));
+        assert_eq!(Ocb::new(rc5(), 17).err(), Some(Error::InvalidTagLength(17)));
+
+        let ocb = Ocb::new(rc5(), 16).unwrap();
+        assert_eq!(ocb.tag_len(), 16);
+        assert!(ocb.encrypt(&[0; 1], [], []).is_ok());
+        assert!(ocb.encrypt(&[0; 15], [], []).is_ok());
+        for len in [0, 16] {
+            assert_eq!(
+                ocb.encrypt(&vec![0; len], [], []),
+                Err(Error::InvalidNonceLength {
+                    actual: len,
+                    min: 1,
+                    max: 15
+                })
+            );
+        }
+    }
+}
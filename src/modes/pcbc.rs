@@ -0,0 +1,197 @@
+//! Propagating cipher block chaining (PCBC) mode.
+//!
+//! Both the plain text and the cipher text of the previous block are XORed
+//! into the next plain text block before encrypting it:
+//!
+//! ```text
+//! C[i] = E(P[i] ^ P[i - 1] ^ C[i - 1]), where P[-1] ^ C[-1] = IV
+//! ```
+//!
+//! Only meant for compatibility with old protocols using it (e.g. Kerberos v4).
+//! The plain text must be a multiple of the block size.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use zeroize::Zeroize;
+
+use super::{xor, BlockMode};
+use crate::{
+    audit::Operation,
+    block_cipher::{BlockCipher, MAX_BLOCK_SIZE},
+    Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// PCBC mode.
+///
+/// Consecutive calls continue the chain where the previous one stopped,
+/// so a message can be encrypted in block-aligned pieces.
+#[derive(Clone)]
+pub struct Pcbc<W: Word> {
+    cipher: RC5<W>,
+    /// The value XORed into the next plain text block,
+    /// the IV or `P[i - 1] ^ C[i - 1]`.
+    chain: [u8; MAX_BLOCK_SIZE],
+}
+
+impl<W: Word> Pcbc<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    /// Creates PCBC mode with an initialization vector of a block.
+    pub fn new(cipher: RC5<W>, iv: &[u8]) -> Result<Self, Error> {
+        if iv.len() != Self::BLOCK_SIZE {
+            return Err(Error::InvalidIvLength {
+                actual: iv.len(),
+                expected: Self::BLOCK_SIZE,
+            });
+        }
+
+        let mut chain = [0; MAX_BLOCK_SIZE];
+        chain[..Self::BLOCK_SIZE].copy_from_slice(iv);
+        Ok(Self { cipher, chain })
+    }
+
+    /// Encrypts block-aligned data in place.
+    #[cfg(feature = "encrypt")]
+    pub fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        self.check_len(data)?;
+        for block in data.chunks_mut(Self::BLOCK_SIZE) {
+            let chain = &mut self.chain[..Self::BLOCK_SIZE];
+            xor(block, chain);
+            // The chain becomes P[i], then P[i] ^ C[i].
+            xor(chain, block);
+            self.cipher.encrypt_block(block);
+            xor(chain, block);
+        }
+        self.cipher.audit(Operation::Encrypt, data.len());
+        Ok(())
+    }
+
+    /// Decrypts block-aligned data in place.
+    #[cfg(feature = "decrypt")]
+    pub fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        self.check_len(data)?;
+        for block in data.chunks_mut(Self::BLOCK_SIZE) {
+            let chain = &mut self.chain[..Self::BLOCK_SIZE];
+            let mut ciphertext = [0; MAX_BLOCK_SIZE];
+            ciphertext[..Self::BLOCK_SIZE].copy_from_slice(block);
+
+            self.cipher.decrypt_block(block);
+            xor(block, chain);
+            // The next chain is P[i] ^ C[i].
+            chain.copy_from_slice(block);
+            xor(chain, &ciphertext);
+        }
+        self.cipher.audit(Operation::Decrypt, data.len());
+        Ok(())
+    }
+
+    fn check_len(&self, data: &[u8]) -> Result<(), Error> {
+        if !data.len().is_multiple_of(Self::BLOCK_SIZE) {
+            return Err(Error::InvalidInputLength {
+                actual: data.len(),
+                block_size: Self::BLOCK_SIZE,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<W: Word> BlockMode for Pcbc<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    #[cfg(feature = "encrypt")]
+    fn encrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Pcbc::encrypt(self, data)
+    }
+
+    #[cfg(feature = "decrypt")]
+    fn decrypt(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        Pcbc::decrypt(self, data)
+    }
+}
+
+/// The chaining value reveals plain text together with the cipher text, so it is wiped.
+impl<W: Word> Drop for Pcbc<W> {
+    fn drop(&mut self) {
+        self.chain.zeroize();
+    }
+}
+
+#[cfg(all(test, feature = "encrypt"))]
+mod tests {
+    use super::*;
+
+    const IV: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn encrypt_decrypt() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let pt = (0..32).collect::<Vec<u8>>();
+
+        let mut ct = pt.clone();
+        Pcbc::new(rc5.clone(), &IV)
+            .unwrap()
+            .encrypt(&mut ct)
+            .unwrap();
+        let mut block = pt[..8].to_vec();
+        xor(&mut block, &IV);
+        assert_eq!(ct[..8], rc5.encode(&block));
+        let mut block = pt[8..16].to_vec();
+        xor(&mut block, &pt[..8]);
+        xor(&mut block, &ct[..8]);
+        assert_eq!(ct[8..16], rc5.encode(&block));
+
+        // In block-aligned pieces.
+        let mut pcbc = Pcbc::new(rc5.clone(), &IV).unwrap();
+        let mut pieces = pt.clone();
+        pcbc.encrypt(&mut pieces[..8]).unwrap();
+        pcbc.encrypt(&mut pieces[8..]).unwrap();
+        assert_eq!(pieces, ct);
+
+        let mut pcbc = Pcbc::new(rc5, &IV).unwrap();
+        pcbc.decrypt(&mut ct[..16]).unwrap();
+        pcbc.decrypt(&mut ct[16..]).unwrap();
+        assert_eq!(ct, pt);
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn propagates_errors() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let pt = [7; 32];
+        let mut ct = pt;
+        Pcbc::new(rc5.clone(), &IV)
+            .unwrap()
+            .encrypt(&mut ct)
+            .unwrap();
+
+        // A corrupted block garbles every following block.
+        ct[3] ^= 1;
+        Pcbc::new(rc5, &IV).unwrap().decrypt(&mut ct).unwrap();
+        assert!(ct.chunks(8).all(|block| block != [7; 8]));
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        assert!(matches!(
+            Pcbc::new(rc5.clone(), &[0; 4]),
+            Err(Error::InvalidIvLength { actual: 4, .. })
+        ));
+        let mut pcbc = Pcbc::new(rc5, &IV).unwrap();
+        assert!(matches!(
+            pcbc.encrypt(&mut [0; 12]),
+            Err(Error::InvalidInputLength { actual: 12, .. })
+        ));
+    }
+}
@@ -0,0 +1,258 @@
+//! XTS mode for sector-based encryption (IEEE 1619).
+//!
+//! Every sector (or any other fixed location, e.g. a page) is encrypted
+//! independently with a tweak derived from its index, so equal plain text
+//! encrypts differently in different sectors, and any sector can be
+//! rewritten without touching the others:
+//!
+//! ```text
+//! T[0] = E2(sector index), T[j] = T[j - 1] * α
+//! C[j] = E1(P[j] ^ T[j]) ^ T[j]
+//! ```
+//!
+//! where `E1` and `E2` are encryptions under two independent keys
+//! and `α` is the generator of the field of blocks (see `mul_alpha`).
+//! A sector that isn't block-aligned uses ciphertext stealing for its
+//! partial last block, so that the cipher text is as long as the plain text.
+//!
+//! IEEE 1619 only specifies 128-bit blocks (RC5-64), the other block sizes
+//! use a primitive polynomial of their own size.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use zeroize::Zeroize;
+
+use super::{index_block, xor};
+use crate::{
+    audit::Operation,
+    block_cipher::{BlockCipher, MAX_BLOCK_SIZE},
+    Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// XTS mode with a data key and a tweak key.
+#[derive(Clone)]
+pub struct Xts<W: Word> {
+    /// Encrypts the data (`E1`).
+    cipher: RC5<W>,
+    /// Encrypts the sector indices into tweaks (`E2`).
+    tweak_cipher: RC5<W>,
+}
+
+/// A tweak of a block, wiped when dropped.
+struct Tweak([u8; MAX_BLOCK_SIZE]);
+
+impl Drop for Tweak {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<W: Word> Xts<W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    /// Creates XTS mode with two instances of independent keys:
+    /// one encrypting the data, the other one encrypting the tweaks.
+    pub fn new(cipher: RC5<W>, tweak_cipher: RC5<W>) -> Self {
+        Self {
+            cipher,
+            tweak_cipher,
+        }
+    }
+
+    /// Encrypts a sector of at least a block in place.
+    pub fn encrypt_sector(&self, sector_index: u64, data: &mut [u8]) -> Result<(), Error> {
+        let bs = Self::BLOCK_SIZE;
+        let mut tweak = self.tweak(sector_index, data)?;
+        let (full, partial) = (data.len() / bs, data.len() % bs);
+        // With a partial last block, the last full block is processed with stealing.
+        let plain = if partial == 0 { full } else { full - 1 };
+
+        for block in data[..plain * bs].chunks_mut(bs) {
+            self.encrypt_block(block, &tweak);
+            mul_alpha(&mut tweak.0[..bs]);
+        }
+
+        if partial > 0 {
+            let (last_full, last) = data[plain * bs..].split_at_mut(bs);
+            // CC = XTS(P[m - 1], T[m - 1]), C[m] is its head, its tail is stolen.
+            self.encrypt_block(last_full, &tweak);
+            mul_alpha(&mut tweak.0[..bs]);
+            last.swap_with_slice(&mut last_full[..partial]);
+            // C[m - 1] = XTS(P[m] || tail of CC, T[m]).
+            self.encrypt_block(last_full, &tweak);
+        }
+        self.cipher.audit(Operation::Encrypt, data.len());
+        Ok(())
+    }
+
+    /// Decrypts a sector of at least a block in place.
+    #[cfg(feature = "decrypt")]
+    pub fn decrypt_sector(&self, sector_index: u64, data: &mut [u8]) -> Result<(), Error> {
+        let bs = Self::BLOCK_SIZE;
+        let mut tweak = self.tweak(sector_index, data)?;
+        let (full, partial) = (data.len() / bs, data.len() % bs);
+        let plain = if partial == 0 { full } else { full - 1 };
+
+        for block in data[..plain * bs].chunks_mut(bs) {
+            self.decrypt_block(block, &tweak);
+            mul_alpha(&mut tweak.0[..bs]);
+        }
+
+        if partial > 0 {
+            // The last full block of cipher text uses the tweak after this one.
+            let mut next = Tweak(tweak.0);
+            mul_alpha(&mut next.0[..bs]);
+
+            let (last_full, last) = data[plain * bs..].split_at_mut(bs);
+            self.decrypt_block(last_full, &next);
+            last.swap_with_slice(&mut last_full[..partial]);
+            self.decrypt_block(last_full, &tweak);
+        }
+        self.cipher.audit(Operation::Decrypt, data.len());
+        Ok(())
+    }
+
+    /// Encrypts the sector index into the tweak of the first block.
+    fn tweak(&self, sector_index: u64, data: &[u8]) -> Result<Tweak, Error> {
+        if data.len() < Self::BLOCK_SIZE {
+            return Err(Error::InvalidInputLength {
+                actual: data.len(),
+                block_size: Self::BLOCK_SIZE,
+            });
+        }
+
+        let mut tweak =
+            Tweak(index_block(sector_index, Self::BLOCK_SIZE).map_err(Error::SectorIndexTooLarge)?);
+        self.tweak_cipher
+            .encrypt_block(&mut tweak.0[..Self::BLOCK_SIZE]);
+        Ok(tweak)
+    }
+
+    fn encrypt_block(&self, block: &mut [u8], tweak: &Tweak) {
+        xor(block, &tweak.0);
+        self.cipher.encrypt_block(block);
+        xor(block, &tweak.0);
+    }
+
+    #[cfg(feature = "decrypt")]
+    fn decrypt_block(&self, block: &mut [u8], tweak: &Tweak) {
+        xor(block, &tweak.0);
+        self.cipher.decrypt_block(block);
+        xor(block, &tweak.0);
+    }
+}
+
+/// Multiplies a little-endian block by `α`, i.e. `x` modulo the primitive
+/// polynomial of the block size: `x^16 + x^5 + x^3 + x^2 + 1`,
+/// `x^32 + x^7 + x^5 + x^3 + x^2 + x + 1`, `x^64 + x^4 + x^3 + x + 1`
+/// or `x^128 + x^7 + x^2 + x + 1` (the one of IEEE 1619).
+fn mul_alpha(block: &mut [u8]) {
+    let reduction = match block.len() {
+        2 => 0x2D,
+        4 => 0xAF,
+        8 => 0x1B,
+        16 => 0x87,
+        len => unreachable!("no block size of {} bytes", len),
+    };
+
+    let mut carry = 0;
+    for byte in block.iter_mut() {
+        let next = *byte >> 7;
+        *byte = *byte << 1 | carry;
+        carry = next;
+    }
+    if carry == 1 {
+        block[0] ^= reduction;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xts() -> Xts<u32> {
+        Xts::new(
+            RC5::new(vec![0x42; 16]).unwrap(),
+            RC5::new(vec![0x17; 16]).unwrap(),
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn block_aligned() {
+        let (xts, pt) = (xts(), [7; 32]);
+        let mut ct = pt;
+        xts.encrypt_sector(3, &mut ct).unwrap();
+
+        // C[0] = E1(P[0] ^ T) ^ T, where T = E2(3).
+        let tweak = xts.tweak_cipher.encode(3u64.to_le_bytes());
+        let mut block = [7; 8];
+        xor(&mut block, &tweak);
+        let mut block = xts.cipher.encode(block);
+        xor(&mut block, &tweak);
+        assert_eq!(ct[..8], block);
+
+        // Equal blocks encrypt differently within a sector and across sectors.
+        assert_ne!(ct[..8], ct[8..16]);
+        let mut other = pt;
+        xts.encrypt_sector(4, &mut other).unwrap();
+        assert_ne!(other, ct);
+
+        xts.decrypt_sector(3, &mut ct).unwrap();
+        assert_eq!(ct, pt);
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn ciphertext_stealing() {
+        let xts = xts();
+        let pt = (0..40).collect::<Vec<u8>>();
+        for len in 8..40 {
+            let mut ct = pt[..len].to_vec();
+            xts.encrypt_sector(9, &mut ct).unwrap();
+            assert_ne!(ct, pt[..len]);
+            // The blocks before the last full one are encrypted as without stealing.
+            let full = (len / 8).saturating_sub(1) * 8;
+            let mut aligned = pt[..full].to_vec();
+            if full > 0 {
+                xts.encrypt_sector(9, &mut aligned).unwrap();
+                assert_eq!(ct[..full], aligned);
+            }
+            xts.decrypt_sector(9, &mut ct).unwrap();
+            assert_eq!(ct, pt[..len]);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let xts = xts();
+        assert!(matches!(
+            xts.encrypt_sector(0, &mut [0; 7]),
+            Err(Error::InvalidInputLength { actual: 7, .. })
+        ));
+
+        let xts = Xts::new(
+            RC5::<u8>::new(vec![0x42; 16]).unwrap(),
+            RC5::<u8>::new(vec![0x17; 16]).unwrap(),
+        );
+        assert!(xts.encrypt_sector(0xFFFF, &mut [0; 5]).is_ok());
+        assert_eq!(
+            xts.encrypt_sector(0x10000, &mut [0; 5]),
+            Err(Error::SectorIndexTooLarge(0x10000))
+        );
+    }
+
+    #[test]
+    fn alpha() {
+        let mut block = [0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80];
+        mul_alpha(&mut block);
+        assert_eq!(block, [0x1B, 0x01, 0, 0, 0, 0, 0, 0x00]);
+    }
+}
@@ -0,0 +1,210 @@
+//! Persistent nonce counters.
+//!
+//! Counter-based nonces (IVs) are only safe if a counter value is never used
+//! twice under the same key, including across process restarts and crashes.
+//!
+//! A `NonceSequence` reserves counter values in batches and durably records
+//! the end of the current reservation in a `NonceStore` before handing out any
+//! value from it. After a restart (or a crash) the sequence resumes from the end
+//! of the last reservation, skipping the values that might have been used.
+
+use std::{
+    convert::TryFrom,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::Error;
+
+/// A durable storage of the nonce sequence state.
+pub trait NonceStore {
+    /// Loads the first counter value that has never been reserved,
+    /// or `None` if nothing has been stored yet.
+    fn load(&mut self) -> Result<Option<u64>, Error>;
+
+    /// Durably stores the first counter value that has never been reserved.
+    ///
+    /// The value must be persisted when this function returns.
+    fn store(&mut self, next: u64) -> Result<(), Error>;
+}
+
+/// A nonce store backed by a file.
+///
+/// The state is written to a temporary file that is synced to disk
+/// and atomically renamed over the previous state, so the file always
+/// contains either the old or the new state.
+pub struct FileNonceStore {
+    path: PathBuf,
+}
+
+impl FileNonceStore {
+    /// Creates a nonce store backed by a file at a given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl NonceStore for FileNonceStore {
+    fn load(&mut self) -> Result<Option<u64>, Error> {
+        match fs::read(&self.path) {
+            Ok(bytes) => {
+                let bytes =
+                    <[u8; 8]>::try_from(bytes.as_slice()).map_err(|_| Error::InvalidNonceState)?;
+                Ok(Some(u64::from_le_bytes(bytes)))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn store(&mut self, next: u64) -> Result<(), Error> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&next.to_le_bytes())?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        // Make the rename itself durable, where directories can be synced.
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+
+        Ok(())
+    }
+}
+
+/// A sequence of unique counter-based nonces surviving process restarts.
+pub struct NonceSequence<S: NonceStore> {
+    store: S,
+    /// Next counter value to hand out.
+    next: u64,
+    /// End of the current reservation (exclusive).
+    reserved_until: u64,
+    /// Number of counter values reserved at once.
+    reservation: u64,
+}
+
+impl<S: NonceStore> NonceSequence<S> {
+    /// Default number of counter values reserved at once.
+    pub const DEFAULT_RESERVATION: u64 = 1024;
+
+    /// Creates a nonce sequence resuming from the state in a given store.
+    pub fn new(store: S) -> Result<Self, Error> {
+        Self::new_with_reservation(store, Self::DEFAULT_RESERVATION)
+    }
+
+    /// Creates a nonce sequence that reserves a given number of values at once.
+    ///
+    /// Larger reservations mean fewer writes to the store,
+    /// but more skipped values after a restart.
+    pub fn new_with_reservation(mut store: S, reservation: u64) -> Result<Self, Error> {
+        let next = store.load()?.unwrap_or(0);
+        Ok(Self {
+            store,
+            next,
+            reserved_until: next,
+            reservation: reservation.max(1),
+        })
+    }
+
+    /// Returns the next unique counter value.
+    pub fn next_nonce(&mut self) -> Result<u64, Error> {
+        if self.next == self.reserved_until {
+            let reserved_until = self
+                .next
+                .checked_add(self.reservation)
+                .or((self.next < u64::MAX).then_some(u64::MAX))
+                .ok_or(Error::NonceExhausted)?;
+            self.store.store(reserved_until)?;
+            self.reserved_until = reserved_until;
+        }
+
+        let nonce = self.next;
+        self.next += 1;
+        Ok(nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory store counting writes.
+    #[derive(Default)]
+    struct MemoryStore {
+        next: Option<u64>,
+        writes: usize,
+    }
+
+    impl NonceStore for &mut MemoryStore {
+        fn load(&mut self) -> Result<Option<u64>, Error> {
+            Ok(self.next)
+        }
+
+        fn store(&mut self, next: u64) -> Result<(), Error> {
+            self.next = Some(next);
+            self.writes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn resumes_after_restart() {
+        let mut store = MemoryStore::default();
+
+        {
+            let mut seq = NonceSequence::new_with_reservation(&mut store, 10).unwrap();
+            let used = (0..15)
+                .map(|_| seq.next_nonce().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(used, (0..15).collect::<Vec<_>>());
+        }
+        assert_eq!(store.writes, 2);
+
+        // Values 15..20 might have been used before the "crash", so they are skipped.
+        let mut seq = NonceSequence::new_with_reservation(&mut store, 10).unwrap();
+        assert_eq!(seq.next_nonce().unwrap(), 20);
+    }
+
+    #[test]
+    fn exhausted() {
+        let mut store = MemoryStore {
+            next: Some(u64::MAX - 2),
+            writes: 0,
+        };
+        let mut seq = NonceSequence::new_with_reservation(&mut store, 10).unwrap();
+        assert_eq!(seq.next_nonce().unwrap(), u64::MAX - 2);
+        assert_eq!(seq.next_nonce().unwrap(), u64::MAX - 1);
+        assert!(matches!(seq.next_nonce(), Err(Error::NonceExhausted)));
+    }
+
+    #[test]
+    fn file_store() {
+        let path = std::env::temp_dir().join(format!("rc5-nonce-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut seq = NonceSequence::new(FileNonceStore::new(&path)).unwrap();
+        assert_eq!(seq.next_nonce().unwrap(), 0);
+        assert_eq!(seq.next_nonce().unwrap(), 1);
+
+        let mut seq = NonceSequence::new(FileNonceStore::new(&path)).unwrap();
+        assert_eq!(
+            seq.next_nonce().unwrap(),
+            NonceSequence::<FileNonceStore>::DEFAULT_RESERVATION
+        );
+
+        fs::write(&path, [1, 2, 3]).unwrap();
+        assert!(matches!(
+            NonceSequence::new(FileNonceStore::new(&path)),
+            Err(Error::InvalidNonceState)
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
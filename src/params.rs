@@ -0,0 +1,338 @@
+//! RC5 parameters packaged together.
+//!
+//! Unlike the `ControlBlock`, parameters don't include the secret key,
+//! so they are safe to log, store and pass around.
+
+use std::{convert::TryFrom, fmt};
+#[cfg(feature = "encrypt")]
+use std::{
+    convert::TryInto,
+    fmt::Debug,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    expanded_key_table::MAX_NUMBER_OF_ROUNDS, ControlBlock, Error, KeyLength, SecurityLevel, Word,
+};
+#[cfg(feature = "encrypt")]
+use crate::{Block, ExpandedKeyTable, FromLeBytes, SecretKey, ToLeBytes, RC5};
+
+/// Word size and number of rounds of an RC5 instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    /// Word size, in bits.
+    pub word_size: usize,
+    /// Number of rounds.
+    pub rounds: usize,
+}
+
+impl Params {
+    /// Number of blocks encrypted per measurement.
+    #[cfg(feature = "encrypt")]
+    const CALIBRATION_BLOCKS: usize = 1024;
+
+    /// Number of measurements, the fastest one is used
+    /// to reduce the noise from scheduling and frequency scaling.
+    #[cfg(feature = "encrypt")]
+    const CALIBRATION_SAMPLES: usize = 8;
+
+    /// Parameters with the default reasonable number of rounds for a given word.
+    pub fn nominal<W: Word>() -> Self {
+        Self {
+            word_size: W::BITS,
+            rounds: W::ROUNDS,
+        }
+    }
+
+    /// Parameters with the number of rounds of a given security level for a given word.
+    pub fn from_security_level<W: Word>(level: SecurityLevel) -> Self {
+        Self {
+            word_size: W::BITS,
+            rounds: level.rounds::<W>(),
+        }
+    }
+
+    /// Suggests parameters for a given word, such that encrypting a single block
+    /// takes as close to the `target` time as possible without exceeding it.
+    ///
+    /// Similar to the cost calibration of bcrypt, it is meant for users who want
+    /// "as slow as acceptable" parameters, e.g. for data at rest.
+    ///
+    /// The block function is benchmarked on the current machine,
+    /// so the result is not reproducible and should be stored along with the data.
+    /// The number of rounds is never lower than the default reasonable
+    /// number of rounds (`Word::ROUNDS`) and never higher than 255.
+    #[cfg(feature = "encrypt")]
+    pub fn calibrate<W: Word>(target: Duration) -> Self
+    where
+        <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+        <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+    {
+        let (min, max) = (W::ROUNDS, MAX_NUMBER_OF_ROUNDS);
+
+        // Time per block of the block function is linear in the number of rounds:
+        //
+        // t(r) = t(min) + (r - min) * (t(max) - t(min)) / (max - min)
+        let t_min = Self::measure::<W>(min).as_secs_f64();
+        let t_max = Self::measure::<W>(max).as_secs_f64();
+        let target = target.as_secs_f64();
+
+        let rounds = if target <= t_min {
+            min
+        } else if target >= t_max {
+            max
+        } else {
+            let per_round = (t_max - t_min) / (max - min) as f64;
+            min + ((target - t_min) / per_round) as usize
+        };
+
+        Self {
+            word_size: W::BITS,
+            rounds: rounds.clamp(min, max),
+        }
+    }
+
+    /// Measures the time it takes to encrypt a single block with a given number of rounds.
+    #[cfg(feature = "encrypt")]
+    fn measure<W: Word>(rounds: usize) -> Duration
+    where
+        <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+        <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+    {
+        let ctx = RC5::<W>::from_key_table(
+            ExpandedKeyTable::new(&SecretKey::random(16), rounds)
+                .expect("calibration uses a valid number of rounds"),
+        );
+
+        (0..Self::CALIBRATION_SAMPLES)
+            .map(|_| {
+                let mut block = Block::new(W::zero(), W::zero());
+                let start = Instant::now();
+                for _ in 0..Self::CALIBRATION_BLOCKS {
+                    block = black_box(block.encode(&ctx));
+                }
+                start.elapsed()
+            })
+            .min()
+            .unwrap_or_default()
+            / Self::CALIBRATION_BLOCKS as u32
+    }
+}
+
+/// Word size (`w`) of an RC5 instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WordSize {
+    /// 8-bit words, 16-bit blocks (only meant for testing).
+    W8,
+    /// 16-bit words, 32-bit blocks.
+    W16,
+    /// 32-bit words, 64-bit blocks (the nominal choice).
+    W32,
+    /// 64-bit words, 128-bit blocks.
+    W64,
+}
+
+impl WordSize {
+    /// Validates a word size in bits.
+    pub fn new(bits: usize) -> Result<Self, Error> {
+        match bits {
+            8 => Ok(Self::W8),
+            16 => Ok(Self::W16),
+            32 => Ok(Self::W32),
+            64 => Ok(Self::W64),
+            _ => Err(Error::InvalidWordSize(bits)),
+        }
+    }
+
+    /// The word size in bits.
+    pub fn bits(self) -> usize {
+        match self {
+            Self::W8 => 8,
+            Self::W16 => 16,
+            Self::W32 => 32,
+            Self::W64 => 64,
+        }
+    }
+}
+
+/// Number of rounds (`r`) of an RC5 instance, from 0 to 255.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rounds(u8);
+
+impl Rounds {
+    /// Validates a number of rounds.
+    pub fn new(rounds: usize) -> Result<Self, Error> {
+        u8::try_from(rounds)
+            .map(Self)
+            .map_err(|_| Error::InvalidNumberOfRounds {
+                actual: rounds,
+                max: MAX_NUMBER_OF_ROUNDS,
+            })
+    }
+
+    /// The number of rounds.
+    pub fn get(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Validated parameters of an RC5 instance: RC5-w/r/b.
+///
+/// Every combination of valid parts is valid, so once created
+/// the parameters can't be rejected by the constructors of `RC5`
+/// other than for not matching its word type or its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rc5Params {
+    /// Word size.
+    pub word: WordSize,
+    /// Number of rounds.
+    pub rounds: Rounds,
+    /// Length of the secret key.
+    pub key_len: KeyLength,
+}
+
+impl Rc5Params {
+    /// Validates parameters given as loose integers.
+    pub fn new(word_size: usize, rounds: usize, key_len: usize) -> Result<Self, Error> {
+        Ok(Self {
+            word: WordSize::new(word_size)?,
+            rounds: Rounds::new(rounds)?,
+            key_len: KeyLength::new(key_len)?,
+        })
+    }
+
+    /// Word size and number of rounds, without the key length.
+    pub fn params(&self) -> Params {
+        Params {
+            word_size: self.word.bits(),
+            rounds: self.rounds.get(),
+        }
+    }
+
+    /// Packages the parameters with a secret key into a control block of a given version.
+    pub fn to_control_block(&self, version: u8, key: Vec<u8>) -> Result<ControlBlock, Error> {
+        self.check_key_length(key.len())?;
+
+        Ok(ControlBlock {
+            v: version,
+            w: self.word.bits() as u8,
+            r: self.rounds.0,
+            b: self.key_len,
+            k: key,
+        })
+    }
+
+    /// Checks that the parameters match a given word and secret key.
+    pub(crate) fn validate<W: Word>(&self, key_len: usize) -> Result<(), Error> {
+        if self.word.bits() != W::BITS {
+            return Err(Error::InvalidWordSize(self.word.bits()));
+        }
+        self.check_key_length(key_len)
+    }
+
+    /// Checks that a secret key has the length of the parameters.
+    fn check_key_length(&self, key_len: usize) -> Result<(), Error> {
+        let expected = usize::from(self.key_len);
+        if key_len != expected {
+            return Err(Error::InvalidSecretKeyLength {
+                actual: key_len,
+                min: expected,
+                max: expected,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&ControlBlock> for Rc5Params {
+    type Error = Error;
+
+    fn try_from(cb: &ControlBlock) -> Result<Self, Error> {
+        Ok(Self {
+            word: WordSize::new(cb.w as usize)?,
+            rounds: Rounds(cb.r),
+            key_len: cb.b,
+        })
+    }
+}
+
+/// Formats the parameters as their RC5-w/r/b designation.
+impl fmt::Display for Rc5Params {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RC5-{}/{}/{}",
+            self.word.bits(),
+            self.rounds.get(),
+            self.key_len
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "encrypt")]
+    fn calibrate_bounds() {
+        assert_eq!(
+            Params::calibrate::<u32>(Duration::ZERO),
+            Params::nominal::<u32>()
+        );
+        assert_eq!(
+            Params::calibrate::<u64>(Duration::from_secs(1)),
+            Params {
+                word_size: 64,
+                rounds: 255
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "encrypt")]
+    fn calibrate() {
+        let params = Params::calibrate::<u16>(Duration::from_micros(1));
+        assert_eq!(params.word_size, 16);
+        assert!((u16::ROUNDS..=255).contains(&params.rounds));
+    }
+
+    #[test]
+    fn rc5_params() {
+        let params = Rc5Params::new(32, 12, 16).unwrap();
+        assert_eq!(params.to_string(), "RC5-32/12/16");
+        assert_eq!(
+            params.params(),
+            Params {
+                word_size: 32,
+                rounds: 12
+            }
+        );
+
+        assert!(matches!(
+            Rc5Params::new(24, 12, 16),
+            Err(Error::InvalidWordSize(24))
+        ));
+        assert!(matches!(
+            Rc5Params::new(32, 256, 16),
+            Err(Error::InvalidNumberOfRounds {
+                actual: 256,
+                max: 255
+            })
+        ));
+        assert!(matches!(
+            Rc5Params::new(32, 12, 256),
+            Err(Error::InvalidSecretKeyLength { actual: 256, .. })
+        ));
+    }
+
+    #[test]
+    fn rc5_params_control_block() {
+        let params = Rc5Params::new(64, 20, 3).unwrap();
+        let cb = params.to_control_block(0x10, vec![1, 2, 3]).unwrap();
+        assert_eq!(cb.to_string(), params.to_string());
+        assert_eq!(Rc5Params::try_from(&cb).unwrap(), params);
+        assert!(params.to_control_block(0x10, vec![1, 2]).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! Reversible 64-bit integer permutation for ID obfuscation.
+//!
+//! A block of RC5-32 is exactly 64 bits wide, so encrypting an integer
+//! as a single block is a keyed permutation of all 64-bit integers. It can be
+//! used to map sequential database IDs to non-sequential public tokens and back.
+//!
+//! When tokens must stay within a smaller domain (e.g. positive signed 64-bit
+//! integers), [cycle-walking](https://en.wikipedia.org/wiki/Format-preserving_encryption)
+//! is used: the block is encrypted repeatedly until the result falls within the domain.
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+use crate::{block_cipher::BlockCipher, Error, RC5};
+
+/// Smallest supported domain of `permute_below`.
+///
+/// Cycle-walking takes `2^64 / domain` encryptions on average,
+/// so domains are limited to at most 256 encryptions on average.
+pub const MIN_DOMAIN: u64 = 1 << 56;
+
+/// Maps a 64-bit integer to a public token.
+#[cfg(feature = "encrypt")]
+pub fn permute_u64(cipher: &RC5<u32>, id: u64) -> u64 {
+    let mut block = id.to_le_bytes();
+    cipher.encrypt_block(&mut block);
+    u64::from_le_bytes(block)
+}
+
+/// Maps a public token back to the original 64-bit integer.
+#[cfg(feature = "decrypt")]
+pub fn unpermute_u64(cipher: &RC5<u32>, token: u64) -> u64 {
+    let mut block = token.to_le_bytes();
+    cipher.decrypt_block(&mut block);
+    u64::from_le_bytes(block)
+}
+
+/// Maps an integer within `0..domain` to a public token within the same domain.
+#[cfg(feature = "encrypt")]
+pub fn permute_below(cipher: &RC5<u32>, id: u64, domain: u64) -> Result<u64, Error> {
+    validate(id, domain)?;
+
+    let mut token = permute_u64(cipher, id);
+    while token >= domain {
+        token = permute_u64(cipher, token);
+    }
+    Ok(token)
+}
+
+/// Maps a public token within `0..domain` back to the original integer.
+#[cfg(feature = "decrypt")]
+pub fn unpermute_below(cipher: &RC5<u32>, token: u64, domain: u64) -> Result<u64, Error> {
+    validate(token, domain)?;
+
+    let mut id = unpermute_u64(cipher, token);
+    while id >= domain {
+        id = unpermute_u64(cipher, id);
+    }
+    Ok(id)
+}
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+fn validate(value: u64, domain: u64) -> Result<(), Error> {
+    if domain < MIN_DOMAIN {
+        return Err(Error::InvalidDomain(domain));
+    }
+    if value >= domain {
+        return Err(Error::OutOfDomain(value));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "encrypt"))]
+mod tests {
+    use super::*;
+
+    fn cipher() -> RC5<u32> {
+        RC5::new(vec![
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+            0x0E, 0x0F,
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn permute_unpermute() {
+        let cipher = cipher();
+        for id in (0..1000).chain([u64::MAX - 1, u64::MAX]) {
+            let token = permute_u64(&cipher, id);
+            assert_eq!(unpermute_u64(&cipher, token), id);
+        }
+        assert_ne!(permute_u64(&cipher, 1), 1);
+        assert_ne!(permute_u64(&cipher, 1) + 1, permute_u64(&cipher, 2));
+    }
+
+    #[test]
+    #[cfg(feature = "decrypt")]
+    fn permute_below_domain() {
+        let cipher = cipher();
+        let domain = i64::MAX as u64 + 1;
+        for id in 0..1000 {
+            let token = permute_below(&cipher, id, domain).unwrap();
+            assert!(token < domain);
+            assert_eq!(unpermute_below(&cipher, token, domain).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn invalid_domain() {
+        let cipher = cipher();
+        assert!(matches!(
+            permute_below(&cipher, 1, 1000),
+            Err(Error::InvalidDomain(1000))
+        ));
+        assert!(matches!(
+            permute_below(&cipher, MIN_DOMAIN, MIN_DOMAIN),
+            Err(Error::OutOfDomain(_))
+        ));
+    }
+}
@@ -10,22 +10,88 @@
 //! * `1` - Easily broken.
 //! * `6` - Provides "some" security.
 //! * `>= 32` - Might be appropriate for applications where
-//!    security is the primary concern and speed is.
-//!    relatively unimportant.
+//!   security is the primary concern and speed is.
+//!   relatively unimportant.
 
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+use std::io::IoSlice;
 use std::{
     convert::{TryFrom, TryInto},
     fmt::Debug,
+    sync::Arc,
+};
+#[cfg(feature = "encrypt")]
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
 };
 
-use crate::{Block, Error, ExpandedKeyTable, FromLeBytes, SecretKey, ToLeBytes, Word};
+use zeroize::Zeroizing;
+
+#[cfg(feature = "encrypt")]
+use crate::Throughput;
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+use crate::{audit::Operation, block_cipher, Block, ScratchSpace, SmallBuf};
+use crate::{
+    audit::{AuditHook, Auditor},
+    block_cipher::BlockCipher,
+    compat_profile::{ByteOrder, WordOrder},
+    kernel::Kernel,
+    key_provider::{AsyncKeyProvider, KeyProvider},
+    whitening::Whitening,
+    CompatProfile, Error, ExpandedKeyTable, FromLeBytes, KeyTableStorage, MemoryBudget, Params,
+    Rc5Params, RotationSchedule, SecretKey, SecretKeyRef, SecurityLevel, ToLeBytes, Word,
+};
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "encrypt", feature = "decrypt")
+))]
+use crate::{expanded_key_table, kernel::avx2};
 
 /// An RC5 encryption algorithm instance.
-pub struct RC5<W> {
+///
+/// Cloning is cheap: clones share the expanded key table instead of
+/// copying or re-deriving it, so every worker of a server can have its own handle.
+///
+/// The expanded key table is kept in a heap-allocated vector by default,
+/// `RC5::from_key_table` creates an instance for a table in any other
+/// storage (see `ExpandedKeyTable::new_in`), e.g. a static buffer of firmware.
+pub struct RC5<W: Word, S: KeyTableStorage<W> = Zeroizing<Vec<W>>> {
     /// Expanded key vector of random binary words determined by the secret key.
-    pub(crate) expanded_key_table: ExpandedKeyTable<W>,
+    pub(crate) expanded_key_table: Arc<ExpandedKeyTable<W, S>>,
     /// Number of rounds.
     pub(crate) number_of_rounds: usize,
+    /// Order of bytes within a word.
+    pub(crate) byte_order: ByteOrder,
+    /// Order of words within a block.
+    pub(crate) word_order: WordOrder,
+    /// Optional key-usage audit hook.
+    pub(crate) auditor: Option<Auditor>,
+    /// Optional XEX-style key whitening.
+    pub(crate) whitening: Option<Whitening>,
+    /// Kernel used for bulk encryption and decryption.
+    pub(crate) kernel: Kernel,
+    /// How rotation amounts are chosen by the round function.
+    pub(crate) rotation_schedule: RotationSchedule,
+    /// Limit on internal buffering.
+    pub(crate) memory_budget: MemoryBudget,
+}
+
+/// Clones share the expanded key table, so instances of any storage are cloned.
+impl<W: Word, S: KeyTableStorage<W>> Clone for RC5<W, S> {
+    fn clone(&self) -> Self {
+        Self {
+            expanded_key_table: Arc::clone(&self.expanded_key_table),
+            number_of_rounds: self.number_of_rounds,
+            byte_order: self.byte_order,
+            word_order: self.word_order,
+            auditor: self.auditor.clone(),
+            whitening: self.whitening.clone(),
+            kernel: self.kernel,
+            rotation_schedule: self.rotation_schedule,
+            memory_budget: self.memory_budget,
+        }
+    }
 }
 
 impl<W> RC5<W>
@@ -36,76 +102,712 @@ where
 {
     /// Creates a new RC5 instance for a given secret key with
     /// a default reasonable number of rounds.
-    pub fn new(secret_key: Vec<u8>) -> Result<Self, Error> {
+    ///
+    /// The key can be anything convertible into a vector of bytes,
+    /// e.g. a `Vec<u8>` (moved without copying), a byte array, a slice or a `&str`.
+    pub fn new(secret_key: impl Into<Vec<u8>>) -> Result<Self, Error> {
         Self::new_with_rounds(secret_key, W::ROUNDS)
     }
 
     /// Creates a new RC5 instance for a given secret key and a number of rounds.
-    pub fn new_with_rounds(secret_key: Vec<u8>, number_of_rounds: usize) -> Result<Self, Error> {
-        let secret_key = SecretKey::new(secret_key)?;
+    pub fn new_with_rounds(
+        secret_key: impl Into<Vec<u8>>,
+        number_of_rounds: usize,
+    ) -> Result<Self, Error> {
+        let secret_key = SecretKey::new(secret_key.into())?;
 
-        // Setup an expanded key table that we're going to re-use for encryption/decryption.
-        let expanded_key_table = ExpandedKeyTable::new(&secret_key, number_of_rounds)?;
-
-        Ok(Self {
-            expanded_key_table,
-            number_of_rounds,
-        })
+        Self::from_secret_key(&secret_key, number_of_rounds)
 
         // Since we don't need to keep the secret key after creation of
         // the expanded key table, it is erased from memory
         // (by securely zeroing it) when the `secret_key` variable is dropped.
     }
 
-    /// Encrypts plain text.
-    pub fn encode(&self, plaintext: &[u8]) -> Vec<u8> {
-        let blocks = bytes_to_blocks(plaintext)
-            .iter()
-            .map(|b| b.encode(self))
-            .collect::<Vec<_>>();
-        blocks_to_bytes(&blocks)
+    /// Creates a new RC5 instance for a given secret key and a number of rounds.
+    ///
+    /// The key can be borrowed (see `SecretKeyRef`), so that it isn't copied.
+    pub fn from_secret_key<'k>(
+        secret_key: impl Into<SecretKeyRef<'k>>,
+        number_of_rounds: usize,
+    ) -> Result<Self, Error> {
+        // Setup an expanded key table that we're going to re-use for encryption/decryption.
+        let expanded_key_table = ExpandedKeyTable::new(secret_key, number_of_rounds)?;
+
+        Ok(Self::from_key_table(expanded_key_table))
     }
 
-    /// Decrypts cipher text.
-    pub fn decode(&self, ciphertext: &[u8]) -> Vec<u8> {
-        let blocks = bytes_to_blocks(ciphertext)
-            .iter()
-            .map(|b| b.decode(self))
-            .collect::<Vec<_>>();
-        blocks_to_bytes(&blocks)
+    /// Creates a new RC5 instance for a key resolved by a key provider.
+    pub fn from_key_provider(
+        provider: &impl KeyProvider,
+        key_id: &str,
+        number_of_rounds: usize,
+    ) -> Result<Self, Error> {
+        Self::from_secret_key(&provider.key(key_id)?, number_of_rounds)
+    }
+
+    /// Creates a new RC5 instance for a key resolved by an asynchronous key provider.
+    pub async fn from_async_key_provider(
+        provider: &impl AsyncKeyProvider,
+        key_id: &str,
+        number_of_rounds: usize,
+    ) -> Result<Self, Error> {
+        Self::from_secret_key(&provider.key(key_id).await?, number_of_rounds)
+    }
+
+    /// Creates a new RC5 instance for a given secret key and parameters.
+    pub fn new_with_params(secret_key: impl Into<Vec<u8>>, params: Params) -> Result<Self, Error> {
+        // The key is wiped if the parameters are rejected.
+        let mut secret_key = Zeroizing::new(secret_key.into());
+        let params = Rc5Params::new(params.word_size, params.rounds, secret_key.len())?;
+        Self::new_with_rc5_params(std::mem::take(&mut *secret_key), params)
+    }
+
+    /// Creates a new RC5 instance for a given secret key and validated parameters.
+    ///
+    /// This is the place where parameters are checked against the word type
+    /// of the instance and the length of the secret key.
+    pub fn new_with_rc5_params(
+        secret_key: impl Into<Vec<u8>>,
+        params: Rc5Params,
+    ) -> Result<Self, Error> {
+        let secret_key = SecretKey::new(secret_key.into())?;
+        params.validate::<W>(secret_key.len())?;
+        Self::from_secret_key(&secret_key, params.rounds.get())
+    }
+
+    /// Creates a new RC5 instance for a given secret key with
+    /// the number of rounds of a given security level.
+    pub fn new_with_security_level(
+        secret_key: impl Into<Vec<u8>>,
+        level: SecurityLevel,
+    ) -> Result<Self, Error> {
+        Self::new_with_rounds(secret_key, level.rounds::<W>())
+    }
+
+    /// Creates a new RC5 instance for a given secret key following
+    /// the conventions of a given compatibility profile.
+    pub fn new_with_profile(
+        secret_key: impl Into<Vec<u8>>,
+        profile: &CompatProfile,
+    ) -> Result<Self, Error> {
+        let rounds = profile.rounds.unwrap_or(W::ROUNDS);
+        let rc5 = Self::new_with_rounds(secret_key, rounds)?;
+
+        Ok(Self {
+            byte_order: profile.byte_order,
+            word_order: profile.word_order,
+            ..rc5
+        })
     }
 }
 
-fn bytes_to_blocks<W: Word>(bytes: &[u8]) -> Vec<Block<W>>
+impl<W, S> RC5<W, S>
 where
+    W: Word,
+    S: KeyTableStorage<W>,
     <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
 {
-    bytes_to_words(bytes)
-        .chunks(2)
-        .map(Block::from_words)
-        .collect()
+    /// Creates a new RC5 instance for an already expanded key table,
+    /// e.g. one expanded incrementally by a `KeyExpansion`.
+    ///
+    /// The number of rounds is determined by the length of the table.
+    pub fn from_key_table(expanded_key_table: ExpandedKeyTable<W, S>) -> Self {
+        let number_of_rounds = expanded_key_table.words().len() / 2 - 1;
+
+        Self {
+            expanded_key_table: Arc::new(expanded_key_table),
+            number_of_rounds,
+            byte_order: ByteOrder::LittleEndian,
+            word_order: WordOrder::AB,
+            auditor: None,
+            whitening: None,
+            kernel: Kernel::detect::<W>(),
+            rotation_schedule: RotationSchedule::DataDependent,
+            memory_budget: MemoryBudget::UNLIMITED,
+        }
+    }
+
+    /// Attaches an audit hook to the instance, which is invoked on every
+    /// encryption and decryption with a given key ID.
+    pub fn with_audit_hook(self, key_id: &str, hook: Arc<dyn AuditHook>) -> Self {
+        Self {
+            auditor: Some(Auditor {
+                key_id: key_id.to_string(),
+                hook,
+            }),
+            ..self
+        }
+    }
+
+    /// Enables XEX-style key whitening (see the `whitening` module).
+    ///
+    /// The whitening key must be twice the block size long: its first half
+    /// is XORed into every block before encryption, the second half after.
+    pub fn with_whitening(self, whitening_key: &SecretKey) -> Result<Self, Error> {
+        Ok(Self {
+            whitening: Some(Whitening::new(whitening_key, Self::BLOCK_SIZE)?),
+            ..self
+        })
+    }
+
+    /// Caps the internal buffers of the instance (see the `memory_budget` module).
+    pub fn with_memory_budget(self, memory_budget: MemoryBudget) -> Self {
+        Self {
+            memory_budget,
+            ..self
+        }
+    }
+
+    /// Sets how rotation amounts are chosen by the round function.
+    ///
+    /// `RotationSchedule::Constant` is a non-standard variant that doesn't
+    /// interoperate with RC5 (see the `rotation` module). The vector kernels
+    /// only implement the standard schedule, so it always uses the scalar kernel.
+    pub fn with_rotation_schedule(self, rotation_schedule: RotationSchedule) -> Self {
+        let kernel = match rotation_schedule {
+            RotationSchedule::DataDependent => Kernel::detect::<W>(),
+            RotationSchedule::Constant => Kernel::Scalar,
+        };
+
+        Self {
+            rotation_schedule,
+            kernel,
+            ..self
+        }
+    }
+
+    /// Kernel used for bulk encryption and decryption on the current machine.
+    pub fn kernel(&self) -> Kernel {
+        self.kernel
+    }
+
+    /// Encrypts a block-aligned buffer in place using the best available kernel.
+    #[cfg(feature = "encrypt")]
+    fn encrypt_bulk(&self, data: &mut [u8]) {
+        let done = match self.kernel {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            // Short messages aren't worth copying the key table for.
+            Kernel::Avx2
+                if self.byte_order == ByteOrder::LittleEndian
+                    && self.word_order == WordOrder::AB
+                    && data.len() >= avx2::BATCH_SIZE =>
+            {
+                let len = data.len() / avx2::BATCH_SIZE * avx2::BATCH_SIZE;
+                let batches = &mut data[..len];
+                let key_table = self.key_table_u32();
+                self.apply_whitening(batches, Whitening::pre);
+                // SAFETY: the AVX2 kernel is only selected if the machine supports it.
+                unsafe { avx2::encrypt(&key_table[..], self.number_of_rounds, batches) };
+                self.apply_whitening(batches, Whitening::post);
+                batches.len()
+            }
+            _ => 0,
+        };
+        block_cipher::encrypt_blocks(self, &mut data[done..]);
+    }
+
+    /// Decrypts a block-aligned buffer in place using the best available kernel.
+    #[cfg(feature = "decrypt")]
+    fn decrypt_bulk(&self, data: &mut [u8]) {
+        let done = match self.kernel {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            // Short messages aren't worth copying the key table for.
+            Kernel::Avx2
+                if self.byte_order == ByteOrder::LittleEndian
+                    && self.word_order == WordOrder::AB
+                    && data.len() >= avx2::BATCH_SIZE =>
+            {
+                let len = data.len() / avx2::BATCH_SIZE * avx2::BATCH_SIZE;
+                let batches = &mut data[..len];
+                let key_table = self.key_table_u32();
+                self.apply_whitening(batches, Whitening::post);
+                // SAFETY: the AVX2 kernel is only selected if the machine supports it.
+                unsafe { avx2::decrypt(&key_table[..], self.number_of_rounds, batches) };
+                self.apply_whitening(batches, Whitening::pre);
+                batches.len()
+            }
+            _ => 0,
+        };
+        block_cipher::decrypt_blocks(self, &mut data[done..]);
+    }
+
+    /// Splits a buffer into block-aligned ranges and processes them on scoped threads.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn parallel(&self, data: &mut [u8], threads: usize, process: fn(&Self, &mut [u8]))
+    where
+        Self: Sync,
+    {
+        // Ranges are multiples of the batch size of the vector kernels
+        // (which is a multiple of every block size), so that every thread
+        // can use them, and only the last range may end with a partial batch.
+        const ALIGNMENT: usize = 64;
+
+        let threads = threads.max(1);
+        let range = data.len().div_ceil(threads).div_ceil(ALIGNMENT) * ALIGNMENT;
+        if threads == 1 || range >= data.len() {
+            return process(self, data);
+        }
+
+        std::thread::scope(|scope| {
+            for chunk in data.chunks_mut(range) {
+                scope.spawn(move || process(self, chunk));
+            }
+        });
+    }
+
+    /// Applies a whitening key to every block of a buffer, if whitening is enabled.
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        any(feature = "encrypt", feature = "decrypt")
+    ))]
+    fn apply_whitening(&self, data: &mut [u8], apply: fn(&Whitening, &mut [u8])) {
+        if let Some(whitening) = &self.whitening {
+            for block in data.chunks_mut(Self::BLOCK_SIZE) {
+                apply(whitening, block);
+            }
+        }
+    }
+
+    /// Copy of the expanded key table as 32-bit words for the vector kernels.
+    ///
+    /// It is kept on the stack, so that bulk encryption doesn't allocate.
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        any(feature = "encrypt", feature = "decrypt")
+    ))]
+    fn key_table_u32(&self) -> Zeroizing<[u32; expanded_key_table::MAX_LEN]> {
+        let mut copy = Zeroizing::new([0; expanded_key_table::MAX_LEN]);
+        for (copy, w) in copy.iter_mut().zip(self.expanded_key_table.words()) {
+            *copy = w.to_u32().unwrap();
+        }
+        copy
+    }
+
+    /// Records an audit trail event, if there is an audit hook.
+    ///
+    /// Modes of operation record every message they process through it.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn audit(&self, operation: Operation, bytes: usize) {
+        if let Some(auditor) = &self.auditor {
+            auditor.record(operation, bytes);
+        }
+    }
+
+    /// Measures the encryption throughput of this instance on the current machine
+    /// by encrypting a buffer repeatedly for (at least) a given duration.
+    ///
+    /// Uses the same kernel and key whitening as the regular encryption,
+    /// but doesn't record audit events. Useful to pick chunk sizes, to decide
+    /// whether to enable parallelism and to log capacity numbers at startup.
+    #[cfg(feature = "encrypt")]
+    pub fn benchmark(&self, duration: Duration) -> Throughput {
+        // Large enough for the vector kernels, small enough to stay in the L1/L2 cache.
+        const BUFFER_SIZE: usize = 16 * 1024;
+
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut bytes = 0u64;
+        let start = Instant::now();
+        while bytes == 0 || start.elapsed() < duration {
+            self.encrypt_bulk(black_box(&mut buffer));
+            bytes += buffer.len() as u64;
+        }
+        Throughput::new(bytes, Self::BLOCK_SIZE, start.elapsed())
+    }
+
+    /// Encrypts plain text.
+    ///
+    /// Every block is encrypted independently, as in ECB mode (see `modes::Ecb`),
+    /// so equal plain text blocks yield equal cipher text blocks.
+    #[cfg(feature = "encrypt")]
+    pub fn encode(&self, plaintext: impl AsRef<[u8]>) -> Vec<u8> {
+        let plaintext = plaintext.as_ref();
+        // The buffer holds plaintext until every block is encrypted,
+        // make sure it is wiped if we bail out halfway through.
+        let mut ciphertext = Zeroizing::new(plaintext.to_vec());
+        self.encrypt_bulk(&mut ciphertext);
+        self.audit(Operation::Encrypt, plaintext.len());
+        std::mem::take(&mut *ciphertext)
+    }
+
+    /// Decrypts cipher text encrypted by `encode`.
+    #[cfg(feature = "decrypt")]
+    pub fn decode(&self, ciphertext: impl AsRef<[u8]>) -> Vec<u8> {
+        let ciphertext = ciphertext.as_ref();
+        // Partially decrypted output is wiped if we bail out halfway through.
+        let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+        self.decrypt_bulk(&mut plaintext);
+        self.audit(Operation::Decrypt, ciphertext.len());
+        std::mem::take(&mut *plaintext)
+    }
+
+    /// Encrypts plain text into a scratch space, without allocating
+    /// once the scratch space is large enough (see `ScratchSpace`).
+    #[cfg(feature = "encrypt")]
+    pub fn encode_with_scratch<'s>(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+        scratch: &'s mut ScratchSpace,
+    ) -> &'s [u8] {
+        let plaintext = plaintext.as_ref();
+        let ciphertext = scratch.reset(plaintext.len());
+        ciphertext.extend_from_slice(plaintext);
+        self.encrypt_bulk(ciphertext);
+        self.audit(Operation::Encrypt, plaintext.len());
+        ciphertext
+    }
+
+    /// Decrypts cipher text into a scratch space, without allocating
+    /// once the scratch space is large enough (see `ScratchSpace`).
+    #[cfg(feature = "decrypt")]
+    pub fn decode_with_scratch<'s>(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+        scratch: &'s mut ScratchSpace,
+    ) -> &'s [u8] {
+        let ciphertext = ciphertext.as_ref();
+        let plaintext = scratch.reset(ciphertext.len());
+        plaintext.extend_from_slice(ciphertext);
+        self.decrypt_bulk(plaintext);
+        self.audit(Operation::Decrypt, ciphertext.len());
+        plaintext
+    }
+
+    /// Encrypts plain text into a buffer holding up to `N` bytes inline,
+    /// which avoids a heap allocation per message for short messages.
+    #[cfg(feature = "encrypt")]
+    pub fn encode_small<const N: usize>(&self, plaintext: impl AsRef<[u8]>) -> SmallBuf<N> {
+        let plaintext = plaintext.as_ref();
+        let mut ciphertext = SmallBuf::from_slice(plaintext);
+        self.encrypt_bulk(&mut ciphertext);
+        self.audit(Operation::Encrypt, plaintext.len());
+        ciphertext
+    }
+
+    /// Decrypts cipher text into a buffer holding up to `N` bytes inline,
+    /// which avoids a heap allocation per message for short messages.
+    #[cfg(feature = "decrypt")]
+    pub fn decode_small<const N: usize>(&self, ciphertext: impl AsRef<[u8]>) -> SmallBuf<N> {
+        let ciphertext = ciphertext.as_ref();
+        let mut plaintext = SmallBuf::from_slice(ciphertext);
+        self.decrypt_bulk(&mut plaintext);
+        self.audit(Operation::Decrypt, ciphertext.len());
+        plaintext
+    }
+
+    /// Encrypts plain text using a given number of threads.
+    ///
+    /// The input is split into block-aligned ranges, one per thread,
+    /// and every thread shares the same expanded key table.
+    /// Uses scoped threads of the standard library, so it doesn't need a thread pool.
+    #[cfg(feature = "encrypt")]
+    pub fn encode_parallel(&self, plaintext: impl AsRef<[u8]>, threads: usize) -> Vec<u8>
+    where
+        Self: Sync,
+    {
+        let plaintext = plaintext.as_ref();
+        let mut ciphertext = Zeroizing::new(plaintext.to_vec());
+        self.parallel(&mut ciphertext, threads, Self::encrypt_bulk);
+        self.audit(Operation::Encrypt, plaintext.len());
+        std::mem::take(&mut *ciphertext)
+    }
+
+    /// Decrypts cipher text using a given number of threads.
+    ///
+    /// See `RC5::encode_parallel` for details.
+    #[cfg(feature = "decrypt")]
+    pub fn decode_parallel(&self, ciphertext: impl AsRef<[u8]>, threads: usize) -> Vec<u8>
+    where
+        Self: Sync,
+    {
+        let ciphertext = ciphertext.as_ref();
+        let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+        self.parallel(&mut plaintext, threads, Self::decrypt_bulk);
+        self.audit(Operation::Decrypt, ciphertext.len());
+        std::mem::take(&mut *plaintext)
+    }
+
+    /// Encrypts plain text of any length, padding the final partial block with zeros.
+    ///
+    /// For interop with legacy systems only: zero padding can't be removed
+    /// unambiguously, so the length of the plain text has to be known
+    /// to the receiver (see `RC5::decode_zero_padded`).
+    #[cfg(feature = "encrypt")]
+    pub fn encode_zero_padded(&self, plaintext: impl AsRef<[u8]>) -> Vec<u8> {
+        let plaintext = plaintext.as_ref();
+        let mut ciphertext = Zeroizing::new(plaintext.to_vec());
+        ciphertext.resize(
+            plaintext.len().div_ceil(Self::BLOCK_SIZE) * Self::BLOCK_SIZE,
+            0,
+        );
+        self.encrypt_bulk(&mut ciphertext);
+        self.audit(Operation::Encrypt, plaintext.len());
+        std::mem::take(&mut *ciphertext)
+    }
+
+    /// Decrypts cipher text produced by `RC5::encode_zero_padded`,
+    /// trimming the plain text to a given length.
+    ///
+    /// The cipher text must be exactly long enough for the plain text and its padding.
+    #[cfg(feature = "decrypt")]
+    pub fn decode_zero_padded(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+        plaintext_len: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let ciphertext = ciphertext.as_ref();
+        if !ciphertext.len().is_multiple_of(Self::BLOCK_SIZE) {
+            return Err(Error::InvalidInputLength {
+                actual: ciphertext.len(),
+                block_size: Self::BLOCK_SIZE,
+            });
+        }
+        if plaintext_len.div_ceil(Self::BLOCK_SIZE) * Self::BLOCK_SIZE != ciphertext.len() {
+            return Err(Error::InvalidPlaintextLength {
+                actual: plaintext_len,
+                ciphertext_len: ciphertext.len(),
+            });
+        }
+
+        let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+        self.decrypt_bulk(&mut plaintext);
+        self.audit(Operation::Decrypt, ciphertext.len());
+        plaintext.truncate(plaintext_len);
+        Ok(std::mem::take(&mut *plaintext))
+    }
+
+    /// Encrypts a packet in place, leaving its first `offset` bytes (the header) untouched.
+    ///
+    /// The rest of the packet (the payload) must be block-aligned.
+    #[cfg(feature = "encrypt")]
+    pub fn encode_in_place(&self, packet: &mut [u8], offset: usize) -> Result<(), Error> {
+        let payload = self.payload(packet, offset)?;
+        self.encrypt_bulk(payload);
+        self.audit(Operation::Encrypt, payload.len());
+        Ok(())
+    }
+
+    /// Decrypts a packet in place, leaving its first `offset` bytes (the header) untouched.
+    ///
+    /// The rest of the packet (the payload) must be block-aligned.
+    #[cfg(feature = "decrypt")]
+    pub fn decode_in_place(&self, packet: &mut [u8], offset: usize) -> Result<(), Error> {
+        let payload = self.payload(packet, offset)?;
+        self.decrypt_bulk(payload);
+        self.audit(Operation::Decrypt, payload.len());
+        Ok(())
+    }
+
+    /// Returns the payload of a packet following the header of a given length.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn payload<'a>(&self, packet: &'a mut [u8], offset: usize) -> Result<&'a mut [u8], Error> {
+        if offset > packet.len() {
+            return Err(Error::InvalidOffset {
+                offset,
+                len: packet.len(),
+            });
+        }
+
+        let payload = &mut packet[offset..];
+        if !payload.len().is_multiple_of(Self::BLOCK_SIZE) {
+            return Err(Error::InvalidInputLength {
+                actual: payload.len(),
+                block_size: Self::BLOCK_SIZE,
+            });
+        }
+
+        Ok(payload)
+    }
+
+    /// Encrypts plain text scattered across multiple buffers.
+    ///
+    /// Buffers don't have to be block-aligned individually,
+    /// only their total length does.
+    #[cfg(feature = "encrypt")]
+    pub fn encode_vectored(&self, plaintext: &[IoSlice]) -> Result<Vec<u8>, Error> {
+        let mut ciphertext = self.gather(plaintext)?;
+        self.encrypt_bulk(&mut ciphertext);
+        self.audit(Operation::Encrypt, ciphertext.len());
+        Ok(std::mem::take(&mut *ciphertext))
+    }
+
+    /// Decrypts cipher text scattered across multiple buffers.
+    ///
+    /// Buffers don't have to be block-aligned individually,
+    /// only their total length does.
+    #[cfg(feature = "decrypt")]
+    pub fn decode_vectored(&self, ciphertext: &[IoSlice]) -> Result<Vec<u8>, Error> {
+        let mut plaintext = self.gather(ciphertext)?;
+        self.decrypt_bulk(&mut plaintext);
+        self.audit(Operation::Decrypt, plaintext.len());
+        Ok(std::mem::take(&mut *plaintext))
+    }
+
+    /// Encrypts plain text scattered across multiple buffers into a scratch space
+    /// (see `RC5::encode_vectored` and `ScratchSpace`).
+    #[cfg(feature = "encrypt")]
+    pub fn encode_vectored_with_scratch<'s>(
+        &self,
+        plaintext: &[IoSlice],
+        scratch: &'s mut ScratchSpace,
+    ) -> Result<&'s [u8], Error> {
+        let ciphertext = scratch.reset(self.gathered_len(plaintext)?);
+        for buf in plaintext {
+            ciphertext.extend_from_slice(buf);
+        }
+        self.encrypt_bulk(ciphertext);
+        self.audit(Operation::Encrypt, ciphertext.len());
+        Ok(ciphertext)
+    }
+
+    /// Decrypts cipher text scattered across multiple buffers into a scratch space
+    /// (see `RC5::decode_vectored` and `ScratchSpace`).
+    #[cfg(feature = "decrypt")]
+    pub fn decode_vectored_with_scratch<'s>(
+        &self,
+        ciphertext: &[IoSlice],
+        scratch: &'s mut ScratchSpace,
+    ) -> Result<&'s [u8], Error> {
+        let plaintext = scratch.reset(self.gathered_len(ciphertext)?);
+        for buf in ciphertext {
+            plaintext.extend_from_slice(buf);
+        }
+        self.decrypt_bulk(plaintext);
+        self.audit(Operation::Decrypt, plaintext.len());
+        Ok(plaintext)
+    }
+
+    /// Encrypts many independent messages at once.
+    ///
+    /// Server workloads usually have many small messages rather than one huge buffer.
+    /// Blocks of all messages are processed together, so that the vector kernels
+    /// can fill their lanes with blocks of different messages.
+    #[cfg(feature = "encrypt")]
+    pub fn encode_many(&self, plaintexts: &[&[u8]]) -> Result<Vec<Vec<u8>>, Error> {
+        let mut ciphertexts = self.concat(plaintexts)?;
+        self.encrypt_bulk(&mut ciphertexts);
+        for plaintext in plaintexts {
+            self.audit(Operation::Encrypt, plaintext.len());
+        }
+        Ok(split(&ciphertexts, plaintexts))
+    }
+
+    /// Decrypts many independent messages at once.
+    ///
+    /// See `RC5::encode_many` for details.
+    #[cfg(feature = "decrypt")]
+    pub fn decode_many(&self, ciphertexts: &[&[u8]]) -> Result<Vec<Vec<u8>>, Error> {
+        let mut plaintexts = self.concat(ciphertexts)?;
+        self.decrypt_bulk(&mut plaintexts);
+        for ciphertext in ciphertexts {
+            self.audit(Operation::Decrypt, ciphertext.len());
+        }
+        Ok(split(&plaintexts, ciphertexts))
+    }
+
+    /// Concatenates block-aligned messages.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn concat(&self, messages: &[&[u8]]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        for message in messages {
+            if !message.len().is_multiple_of(Self::BLOCK_SIZE) {
+                return Err(Error::InvalidInputLength {
+                    actual: message.len(),
+                    block_size: Self::BLOCK_SIZE,
+                });
+            }
+        }
+        let bufs = messages.iter().map(|m| IoSlice::new(m)).collect::<Vec<_>>();
+        self.gather(&bufs)
+    }
+
+    /// Copies scattered buffers into a single block-aligned one.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn gather(&self, bufs: &[IoSlice]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let len = self.gathered_len(bufs)?;
+        let mut bytes = Zeroizing::new(Vec::new());
+        bytes
+            .try_reserve_exact(len)
+            .map_err(|_| Error::InputTooLarge)?;
+        for buf in bufs {
+            bytes.extend_from_slice(buf);
+        }
+        Ok(bytes)
+    }
+
+    /// Total length of scattered buffers, which must be block-aligned
+    /// and fit into the memory budget.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    fn gathered_len(&self, bufs: &[IoSlice]) -> Result<usize, Error> {
+        // The same memory can be passed multiple times,
+        // so the total length can overflow even though every buffer fits in memory.
+        let len = bufs
+            .iter()
+            .try_fold(0usize, |len, buf| len.checked_add(buf.len()))
+            .ok_or(Error::InputTooLarge)?;
+        if !len.is_multiple_of(Self::BLOCK_SIZE) {
+            return Err(Error::InvalidInputLength {
+                actual: len,
+                block_size: Self::BLOCK_SIZE,
+            });
+        }
+        self.memory_budget.check(len)?;
+        Ok(len)
+    }
 }
 
-fn bytes_to_words<W: Word>(bytes: &[u8]) -> Vec<W>
+impl<W, S> BlockCipher for RC5<W, S>
 where
+    W: Word,
+    S: KeyTableStorage<W>,
     <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
 {
-    bytes
-        .chunks(W::BYTES)
-        .map(|chunk| W::from_le_bytes(chunk.to_vec().try_into().unwrap()))
-        .collect()
-}
+    // Block is a pair of words.
+    const BLOCK_SIZE: usize = 2 * W::BYTES;
+
+    #[cfg(feature = "encrypt")]
+    fn encrypt_block(&self, block: &mut [u8]) {
+        if let Some(whitening) = &self.whitening {
+            whitening.pre(block);
+        }
+
+        // Blocks are read and written without intermediate buffers,
+        // so a single block is encrypted without allocating.
+        Block::read_ordered(block, self.byte_order, self.word_order)
+            .encode(self)
+            .write_ordered(block, self.byte_order, self.word_order);
+
+        if let Some(whitening) = &self.whitening {
+            whitening.post(block);
+        }
+    }
 
-fn blocks_to_words<W: Word>(blocks: &[Block<W>]) -> Vec<W> {
-    blocks.iter().flat_map(Block::to_words).collect()
+    #[cfg(feature = "decrypt")]
+    fn decrypt_block(&self, block: &mut [u8]) {
+        if let Some(whitening) = &self.whitening {
+            whitening.post(block);
+        }
+
+        Block::read_ordered(block, self.byte_order, self.word_order)
+            .decode(self)
+            .write_ordered(block, self.byte_order, self.word_order);
+
+        if let Some(whitening) = &self.whitening {
+            whitening.pre(block);
+        }
+    }
 }
 
-fn blocks_to_bytes<W: Word>(blocks: &[Block<W>]) -> Vec<u8>
-where
-    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
-{
-    blocks_to_words(blocks)
+/// Splits a buffer into parts of the same lengths as given messages.
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+fn split(bytes: &[u8], messages: &[&[u8]]) -> Vec<Vec<u8>> {
+    let mut offset = 0;
+    messages
         .iter()
-        .flat_map(|w| w.to_le_bytes().try_into().unwrap())
-        .collect::<Vec<u8>>()
+        .map(|message| {
+            let part = bytes[offset..offset + message.len()].to_vec();
+            offset += message.len();
+            part
+        })
+        .collect()
 }
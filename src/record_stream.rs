@@ -0,0 +1,292 @@
+//! Authenticated records over byte streams.
+//!
+//! Two endpoints sharing a key exchange messages over any `Read` and `Write`
+//! (a TCP socket, a pipe, a serial link) as records:
+//!
+//! ```text
+//! record = length (4 bytes, little-endian) || CCM(nonce, header, payload) || tag
+//! nonce = role (1 byte) || sequence number (8 bytes)
+//! ```
+//!
+//! The length covers the cipher text and the tag, and is authenticated as
+//! the associated data. The sequence number counts the records sent in one
+//! direction and isn't sent at all: the reader keeps its own count, so that
+//! a dropped, replayed or reordered record fails to authenticate.
+//! The role keeps the two directions of a connection from sharing nonces,
+//! as with `datagram::Role`.
+//!
+//! An attacker can still cut the stream short after any record, so protocols
+//! that care should end with an explicit closing message. Any error leaves
+//! a reader out of step with the stream, and it shouldn't be used afterwards.
+//!
+//! Only 64-bit words are supported, so that the nonces fit into CCM blocks.
+//! A key must never be reused for another connection in the same role.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+    io::{self, Read, Write},
+};
+
+use crate::{datagram::Role, modes::Ccm, Error, FromLeBytes, MemoryBudget, ToLeBytes, Word, RC5};
+
+/// Length of the record header in bytes.
+const HEADER_LEN: usize = 4;
+
+/// Length of the nonce of a record in bytes.
+const NONCE_LEN: usize = 9;
+
+fn nonce(role: Role, sequence: u64) -> [u8; NONCE_LEN] {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[0] = role as u8;
+    nonce[1..].copy_from_slice(&sequence.to_le_bytes());
+    nonce
+}
+
+fn check_word_size<W: Word>() -> Result<(), Error> {
+    if W::BITS != 64 {
+        return Err(Error::InvalidWordSize(W::BITS));
+    }
+    Ok(())
+}
+
+/// Sends records to a stream.
+pub struct RecordWriter<T: Write, W: Word> {
+    inner: T,
+    ccm: Ccm<W>,
+    role: Role,
+    /// Sequence number of the next record.
+    sequence: u64,
+}
+
+impl<T: Write, W: Word> RecordWriter<T, W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Creates a writer of records with tags of `tag_len` bytes
+    /// (an even number from 4 up to 16), sent by an endpoint in `role`.
+    ///
+    /// Fails for word sizes other than 64 bits.
+    pub fn new(inner: T, cipher: RC5<W>, tag_len: usize, role: Role) -> Result<Self, Error> {
+        check_word_size::<W>()?;
+        Ok(Self {
+            inner,
+            ccm: Ccm::new(cipher, tag_len)?,
+            role,
+            sequence: 0,
+        })
+    }
+
+    /// Encrypts a message into the next record and flushes it to the stream.
+    ///
+    /// Fails with `Error::InputTooLarge` if the record length doesn't fit
+    /// into the header, and with `Error::NonceExhausted` once every sequence
+    /// number has been used.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), Error> {
+        let next_sequence = self.sequence.checked_add(1).ok_or(Error::NonceExhausted)?;
+        let len = message
+            .len()
+            .checked_add(self.ccm.tag_len())
+            .and_then(|len| u32::try_from(len).ok())
+            .ok_or(Error::InputTooLarge)?;
+
+        let header = len.to_le_bytes();
+        let body = self
+            .ccm
+            .encrypt(&nonce(self.role, self.sequence), header, message)?;
+        self.sequence = next_sequence;
+
+        let mut record = Vec::with_capacity(HEADER_LEN + body.len());
+        record.extend_from_slice(&header);
+        record.extend_from_slice(&body);
+        self.inner.write_all(&record)?;
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+/// Receives records from a stream.
+pub struct RecordReader<T: Read, W: Word> {
+    inner: T,
+    ccm: Ccm<W>,
+    memory_budget: MemoryBudget,
+    /// Role of the endpoint that sends the records.
+    peer: Role,
+    /// Sequence number of the next record.
+    sequence: u64,
+}
+
+impl<T: Read, W: Word> RecordReader<T, W>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    /// Creates a reader of records with tags of `tag_len` bytes,
+    /// received by an endpoint in `role` from the other endpoint.
+    ///
+    /// Records longer than the memory budget of `cipher` are rejected
+    /// before they are read. Fails for word sizes other than 64 bits.
+    pub fn new(inner: T, cipher: RC5<W>, tag_len: usize, role: Role) -> Result<Self, Error> {
+        check_word_size::<W>()?;
+        Ok(Self {
+            inner,
+            memory_budget: cipher.memory_budget,
+            ccm: Ccm::new(cipher, tag_len)?,
+            peer: role.peer(),
+            sequence: 0,
+        })
+    }
+
+    /// Reads, verifies and decrypts the next record.
+    ///
+    /// Returns `None` if the stream ends between records, and fails with
+    /// an `io::ErrorKind::UnexpectedEof` I/O error if it ends within one.
+    pub fn recv(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut header = [0; HEADER_LEN];
+        if !self.read_header(&mut header)? {
+            return Ok(None);
+        }
+        let len = usize::try_from(u32::from_le_bytes(header)).map_err(|_| Error::InputTooLarge)?;
+        if len < self.ccm.tag_len() {
+            return Err(Error::CiphertextTooShort {
+                actual: len,
+                min: self.ccm.tag_len(),
+            });
+        }
+        self.memory_budget.check(len)?;
+        let next_sequence = self.sequence.checked_add(1).ok_or(Error::NonceExhausted)?;
+
+        let mut body = vec![0; len];
+        self.inner.read_exact(&mut body)?;
+        let message = self
+            .ccm
+            .decrypt(&nonce(self.peer, self.sequence), header, body)?;
+        self.sequence = next_sequence;
+        Ok(Some(message))
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Fills the header, or returns `false` if the stream ends before it starts.
+    fn read_header(&mut self, header: &mut [u8; HEADER_LEN]) -> Result<bool, Error> {
+        let mut filled = 0;
+        while filled < HEADER_LEN {
+            match self.inner.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rc5() -> RC5<u64> {
+        RC5::new(vec![0x42; 16]).unwrap()
+    }
+
+    fn send(messages: &[&[u8]]) -> Vec<u8> {
+        let mut writer = RecordWriter::new(Vec::new(), rc5(), 16, Role::Initiator).unwrap();
+        for message in messages {
+            writer.send(message).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn reader(stream: &[u8]) -> RecordReader<&[u8], u64> {
+        RecordReader::new(stream, rc5(), 16, Role::Responder).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let messages: [&[u8]; 3] = [b"hello", b"", &[7; 5000]];
+        let stream = send(&messages);
+        assert_eq!(stream.len(), 3 * (4 + 16) + 5 + 5000);
+        assert_eq!(stream[..4], 21u32.to_le_bytes());
+
+        let mut reader = reader(&stream);
+        for message in messages {
+            assert_eq!(reader.recv().unwrap().unwrap(), message);
+        }
+        assert_eq!(reader.recv(), Ok(None));
+
+        // Both endpoints in the same role don't understand each other.
+        let mut reader = RecordReader::new(&stream[..], rc5(), 16, Role::Initiator).unwrap();
+        assert_eq!(reader.recv(), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn reordered_and_tampered() {
+        let stream = send(&[b"first", b"second"]);
+        let (first, second) = stream.split_at(4 + 5 + 16);
+
+        // Records only authenticate in the order they were sent.
+        assert_eq!(reader(second).recv(), Err(Error::AuthenticationFailed));
+        let replayed = [first, first].concat();
+        let mut replay = reader(&replayed);
+        assert_eq!(replay.recv().unwrap().unwrap(), b"first");
+        assert_eq!(replay.recv(), Err(Error::AuthenticationFailed));
+
+        let mut tampered = stream.clone();
+        tampered[6] ^= 1;
+        assert_eq!(reader(&tampered).recv(), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn truncated() {
+        let stream = send(&[b"message"]);
+        for len in [2, 4, stream.len() - 1] {
+            let e = reader(&stream[..len]).recv().unwrap_err();
+            assert_eq!(io::Error::from(e).kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        assert!(matches!(
+            reader(&[3, 0, 0, 0, 1, 2, 3]).recv(),
+            Err(Error::CiphertextTooShort { actual: 3, min: 16 })
+        ));
+        let mut reader = RecordReader::new(
+            &stream[..],
+            rc5().with_memory_budget(MemoryBudget::new(16)),
+            16,
+            Role::Responder,
+        )
+        .unwrap();
+        assert!(matches!(
+            reader.recv(),
+            Err(Error::MemoryBudgetExceeded { requested: 23, .. })
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        let rc5_32 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        assert!(matches!(
+            RecordWriter::new(Vec::new(), rc5_32.clone(), 8, Role::Initiator),
+            Err(Error::InvalidWordSize(32))
+        ));
+        assert!(matches!(
+            RecordReader::new(&[][..], rc5_32, 8, Role::Responder),
+            Err(Error::InvalidWordSize(32))
+        ));
+
+        let mut writer = RecordWriter::new(Vec::new(), rc5(), 16, Role::Initiator).unwrap();
+        writer.sequence = u64::MAX;
+        assert_eq!(writer.send(b"message"), Err(Error::NonceExhausted));
+        assert!(writer.into_inner().is_empty());
+    }
+}
@@ -0,0 +1,419 @@
+//! Allocation-free streaming encryption from a ring buffer.
+//!
+//! Meant for firmware encrypting a stream of telemetry (e.g. over UART or RF):
+//! a producer (e.g. an interrupt handler) pushes bytes into a fixed-size ring buffer,
+//! and a consumer pulls encrypted chunks into a buffer handed over to DMA.
+//!
+//! The stream is encrypted in counter (CTR) mode: the `i`-th block of the stream
+//! is XORed with the encryption of the counter block `(nonce, i)`.
+//! The ciphertext has the same length as the plaintext, so chunks don't need padding,
+//! and the ciphertext of a stream doesn't depend on how it was chunked:
+//! it can be decrypted with a `Keystream` of the same nonce.
+//! A nonce must never be reused with the same key.
+//!
+//! Nothing is allocated after creation and every call does a bounded amount of work:
+//! at most one block encryption per block of the output buffer.
+//!
+//! The position in the stream can be exported as a `StreamState` and restored later,
+//! so that a long-running job can checkpoint and resume after an interruption.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+};
+
+use zeroize::Zeroize;
+
+use crate::{
+    block_cipher::{BlockCipher, MAX_BLOCK_SIZE},
+    compat_profile::ByteOrder,
+    Block, Error, FromLeBytes, ToLeBytes, Word, RC5,
+};
+
+/// A counter (CTR) mode encryptor consuming data from a ring buffer of `N` bytes.
+pub struct RingEncryptor<'a, W: Word, const N: usize> {
+    cipher: &'a RC5<W>,
+    /// The first word of every counter block.
+    nonce: W,
+    /// Index of the next counter block.
+    counter: W,
+    /// Set once the counter has wrapped around, the stream can't go on.
+    exhausted: bool,
+    /// The last keystream block.
+    block: [u8; MAX_BLOCK_SIZE],
+    /// Index of the next unused byte of the last keystream block.
+    pos: usize,
+    /// Number of bytes encrypted so far.
+    bytes_processed: u64,
+    /// Ring buffer of pending plaintext.
+    ring: [u8; N],
+    /// Index of the first pending byte.
+    head: usize,
+    /// Number of pending bytes.
+    len: usize,
+}
+
+impl<'a, W: Word, const N: usize> RingEncryptor<'a, W, N>
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const BLOCK_SIZE: usize = <RC5<W> as BlockCipher>::BLOCK_SIZE;
+
+    /// Creates an encryptor with a given nonce, which must be unique for the key.
+    pub fn new(cipher: &'a RC5<W>, nonce: W) -> Self {
+        Self {
+            cipher,
+            nonce,
+            counter: W::zero(),
+            exhausted: false,
+            block: [0; MAX_BLOCK_SIZE],
+            pos: Self::BLOCK_SIZE,
+            bytes_processed: 0,
+            ring: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Resumes encryption of a stream from a checkpoint.
+    ///
+    /// The stream continues with the plaintext byte at `state.bytes_processed`.
+    /// Fails if the state doesn't fit the block size of `cipher`.
+    pub fn resume(cipher: &'a RC5<W>, state: &StreamState<W>) -> Result<Self, Error> {
+        let mut encryptor = Self::new(cipher, state.nonce);
+        encryptor.bytes_processed = state.bytes_processed;
+        if state.unused > 0 {
+            // The unused tail of the last block is regenerated.
+            if state.unused >= Self::BLOCK_SIZE || (state.counter.is_zero() && !state.exhausted) {
+                return Err(Error::InvalidStreamState);
+            }
+            encryptor.counter = state.counter.wrapping_sub(&W::one());
+            encryptor.next_keystream_block();
+            encryptor.pos = Self::BLOCK_SIZE - state.unused;
+        }
+        encryptor.counter = state.counter;
+        encryptor.exhausted = state.exhausted;
+        Ok(encryptor)
+    }
+
+    /// Exports the position in the stream.
+    ///
+    /// Pending plaintext isn't part of the state: after resuming, plaintext has to
+    /// be pushed again starting from the byte at `StreamState::bytes_processed`.
+    pub fn state(&self) -> StreamState<W> {
+        StreamState {
+            nonce: self.nonce,
+            counter: self.counter,
+            exhausted: self.exhausted,
+            unused: Self::BLOCK_SIZE - self.pos,
+            bytes_processed: self.bytes_processed,
+        }
+    }
+
+    /// Number of pending plaintext bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no pending plaintext bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes that can be pushed before the ring buffer is full.
+    pub fn remaining(&self) -> usize {
+        N - self.len
+    }
+
+    /// Pushes plaintext into the ring buffer, returns the number of bytes that fit.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(self.remaining());
+        let tail = (self.head + self.len) % N.max(1);
+        let first = n.min(N - tail);
+        self.ring[tail..tail + first].copy_from_slice(&data[..first]);
+        self.ring[..n - first].copy_from_slice(&data[first..n]);
+        self.len += n;
+        n
+    }
+
+    /// Encrypts as many whole blocks of pending plaintext as fit into `out`,
+    /// returns the number of bytes written.
+    pub fn pull(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        let n = self.len.min(out.len()) / Self::BLOCK_SIZE * Self::BLOCK_SIZE;
+        self.encrypt_into(&mut out[..n])?;
+        Ok(n)
+    }
+
+    /// Encrypts all pending plaintext (up to the size of `out`) including a trailing
+    /// partial block, returns the number of bytes written.
+    ///
+    /// The rest of the keystream block of a partial block is kept for the next bytes,
+    /// so more data can still be pushed and pulled afterwards.
+    pub fn flush(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        let n = self.len.min(out.len());
+        self.encrypt_into(&mut out[..n])?;
+        Ok(n)
+    }
+
+    /// Moves `out.len()` pending bytes into `out` and encrypts them.
+    ///
+    /// Fails before touching `out` if the counter would run out midway,
+    /// so no plaintext is ever left in the output buffer.
+    fn encrypt_into(&mut self, out: &mut [u8]) -> Result<(), Error> {
+        let n = out.len();
+        let unused = (Self::BLOCK_SIZE - self.pos) as u128;
+        if n as u128 > unused + self.remaining_blocks() * Self::BLOCK_SIZE as u128 {
+            return Err(Error::CounterExhausted);
+        }
+
+        let first = n.min(N - self.head);
+        out[..first].copy_from_slice(&self.ring[self.head..self.head + first]);
+        out[first..].copy_from_slice(&self.ring[..n - first]);
+
+        for byte in out.iter_mut() {
+            if self.pos == Self::BLOCK_SIZE {
+                self.next_keystream_block();
+            }
+            *byte ^= self.block[self.pos];
+            self.pos += 1;
+        }
+
+        // Plaintext is wiped from the ring buffer once it's consumed.
+        for i in 0..n {
+            self.ring[(self.head + i) % N] = 0;
+        }
+        self.head = (self.head + n) % N.max(1);
+        self.len -= n;
+        self.bytes_processed += n as u64;
+        Ok(())
+    }
+
+    /// Number of counter blocks left before the counter wraps around.
+    fn remaining_blocks(&self) -> u128 {
+        if self.exhausted {
+            return 0;
+        }
+        (1 << W::BITS) - u128::from(self.counter.to_u64().unwrap())
+    }
+
+    /// Encrypts the next counter block, the counter must not be exhausted.
+    fn next_keystream_block(&mut self) {
+        let block = &mut self.block[..Self::BLOCK_SIZE];
+        Block::new(self.nonce, self.counter).write_ordered(
+            block,
+            self.cipher.byte_order,
+            self.cipher.word_order,
+        );
+        self.cipher.encrypt_block(block);
+
+        self.counter = self.counter.wrapping_add(&W::one());
+        self.exhausted = self.counter.is_zero();
+        self.pos = 0;
+    }
+}
+
+/// Pending plaintext and the buffered keystream block are wiped.
+impl<W: Word, const N: usize> Drop for RingEncryptor<'_, W, N> {
+    fn drop(&mut self) {
+        self.ring.zeroize();
+        self.block.zeroize();
+    }
+}
+
+/// A position in an encrypted stream, see `RingEncryptor::state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamState<W: Word> {
+    /// The nonce of the stream.
+    pub nonce: W,
+    /// Index of the next counter block.
+    pub counter: W,
+    /// Whether the counter has wrapped around.
+    pub exhausted: bool,
+    /// Number of unused keystream bytes left of the last counter block.
+    pub unused: usize,
+    /// Number of bytes encrypted so far.
+    pub bytes_processed: u64,
+}
+
+impl<W: Word> StreamState<W> {
+    /// Serializes the state as the word size (1 byte), the nonce and the counter
+    /// (`W::BYTES` each, little-endian), the exhausted flag (1 byte),
+    /// the number of unused keystream bytes (1 byte)
+    /// and the number of processed bytes (8 bytes, little-endian).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 2 * W::BYTES + 11];
+        bytes[0] = W::BITS as u8;
+        Block::new(self.nonce, self.counter)
+            .write(&mut bytes[1..=2 * W::BYTES], ByteOrder::LittleEndian);
+        bytes[2 * W::BYTES + 1] = self.exhausted as u8;
+        bytes[2 * W::BYTES + 2] = self.unused as u8;
+        bytes[2 * W::BYTES + 3..].copy_from_slice(&self.bytes_processed.to_le_bytes());
+        bytes
+    }
+
+    /// Parses a state serialized with `StreamState::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let words = 2 * W::BYTES;
+        if bytes.len() != words + 11
+            || bytes[0] as usize != W::BITS
+            || bytes[words + 1] > 1
+            || bytes[words + 2] as usize >= words
+        {
+            return Err(Error::InvalidStreamState);
+        }
+
+        let block = Block::<W>::read(&bytes[1..=words], ByteOrder::LittleEndian);
+        let (nonce, counter) = block.words();
+        let mut processed = [0; 8];
+        processed.copy_from_slice(&bytes[words + 3..]);
+
+        Ok(Self {
+            nonce,
+            counter,
+            exhausted: bytes[words + 1] == 1,
+            unused: bytes[words + 2].into(),
+            bytes_processed: u64::from_le_bytes(processed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Keystream;
+
+    fn keystream(rc5: &RC5<u32>, nonce: u32, blocks: u32) -> Vec<u8> {
+        let counters = (0..blocks)
+            .flat_map(|i| [nonce.to_le_bytes(), i.to_le_bytes()].concat())
+            .collect::<Vec<_>>();
+        rc5.encode(counters)
+    }
+
+    #[test]
+    fn push_pull() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let pt = (0..100).map(|i| i as u8).collect::<Vec<_>>();
+        let expected = pt
+            .iter()
+            .zip(keystream(&rc5, 7, 13))
+            .map(|(p, k)| p ^ k)
+            .collect::<Vec<_>>();
+
+        let mut encryptor = RingEncryptor::<u32, 32>::new(&rc5, 7);
+        let (mut ct, mut pushed, mut out) = (Vec::new(), 0, [0u8; 20]);
+        while pushed < pt.len() {
+            pushed += encryptor.push(&pt[pushed..]);
+            let n = encryptor.pull(&mut out).unwrap();
+            assert_eq!(n % 8, 0);
+            ct.extend_from_slice(&out[..n]);
+        }
+        while !encryptor.is_empty() {
+            let n = encryptor.flush(&mut out).unwrap();
+            ct.extend_from_slice(&out[..n]);
+        }
+
+        assert_eq!(ct, expected);
+    }
+
+    #[test]
+    fn flush_mid_stream() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let pt = (0..60).map(|i| i as u8).collect::<Vec<_>>();
+        let expected = pt
+            .iter()
+            .zip(keystream(&rc5, 7, 8))
+            .map(|(p, k)| p ^ k)
+            .collect::<Vec<_>>();
+
+        let mut encryptor = RingEncryptor::<u32, 32>::new(&rc5, 7);
+        let (mut ct, mut out) = (Vec::new(), [0u8; 32]);
+        for chunk in pt.chunks(11) {
+            encryptor.push(chunk);
+            let n = encryptor.pull(&mut out).unwrap();
+            ct.extend_from_slice(&out[..n]);
+            let n = encryptor.flush(&mut out).unwrap();
+            ct.extend_from_slice(&out[..n]);
+        }
+        assert_eq!(ct, expected);
+
+        let mut data = pt.clone();
+        Keystream::new(&rc5, 7).apply(&mut data).unwrap();
+        assert_eq!(data, ct);
+    }
+
+    #[test]
+    fn full_ring() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let mut encryptor = RingEncryptor::<u32, 16>::new(&rc5, 0);
+        assert_eq!(encryptor.push(&[1; 20]), 16);
+        assert_eq!(encryptor.remaining(), 0);
+        assert_eq!(encryptor.pull(&mut [0; 4]).unwrap(), 0);
+        assert_eq!(encryptor.pull(&mut [0; 12]).unwrap(), 8);
+        assert_eq!(encryptor.len(), 8);
+    }
+
+    #[test]
+    fn counter_exhausted() {
+        let rc5 = RC5::<u8>::new(vec![0x42; 16]).unwrap();
+        let mut encryptor = RingEncryptor::<u8, 64>::new(&rc5, 0);
+        let mut out = [0; 64];
+        for _ in 0..8 {
+            encryptor.push(&[0; 64]);
+            encryptor.pull(&mut out).unwrap();
+        }
+        encryptor.push(&[0; 2]);
+        assert!(matches!(
+            encryptor.pull(&mut out),
+            Err(Error::CounterExhausted)
+        ));
+    }
+
+    #[test]
+    fn resume() {
+        let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+        let pt = (0..50).map(|i| i as u8).collect::<Vec<_>>();
+        let mut out = [0u8; 64];
+
+        let mut encryptor = RingEncryptor::<u32, 64>::new(&rc5, 7);
+        encryptor.push(&pt);
+        let n = encryptor.flush(&mut out).unwrap();
+        let expected = out[..n].to_vec();
+
+        // Checkpoint with a partial block pending, which is not part of the state.
+        let mut encryptor = RingEncryptor::<u32, 64>::new(&rc5, 7);
+        encryptor.push(&pt[..21]);
+        let n = encryptor.pull(&mut out).unwrap();
+        let mut ct = out[..n].to_vec();
+        let state = StreamState::<u32>::from_bytes(&encryptor.state().to_bytes()).unwrap();
+        assert_eq!(state, encryptor.state());
+        assert_eq!(state.bytes_processed, 16);
+
+        let mut encryptor = RingEncryptor::<u32, 64>::resume(&rc5, &state).unwrap();
+        encryptor.push(&pt[state.bytes_processed as usize..]);
+        let n = encryptor.flush(&mut out).unwrap();
+        ct.extend_from_slice(&out[..n]);
+        assert_eq!(ct, expected);
+
+        // Checkpoint in the middle of a keystream block.
+        let mut encryptor = RingEncryptor::<u32, 64>::new(&rc5, 7);
+        encryptor.push(&pt[..21]);
+        let n = encryptor.flush(&mut out).unwrap();
+        let mut ct = out[..n].to_vec();
+        let state = StreamState::<u32>::from_bytes(&encryptor.state().to_bytes()).unwrap();
+        assert_eq!(state, encryptor.state());
+        assert_eq!(state.unused, 3);
+
+        let mut encryptor = RingEncryptor::<u32, 64>::resume(&rc5, &state).unwrap();
+        encryptor.push(&pt[21..]);
+        let n = encryptor.flush(&mut out).unwrap();
+        ct.extend_from_slice(&out[..n]);
+        assert_eq!(ct, expected);
+
+        let state = StreamState { unused: 8, ..state };
+        assert!(RingEncryptor::<u32, 64>::resume(&rc5, &state).is_err());
+        assert!(StreamState::<u32>::from_bytes(&state.to_bytes()).is_err());
+        assert!(StreamState::<u32>::from_bytes(&[32; 5]).is_err());
+        assert!(StreamState::<u16>::from_bytes(&state.to_bytes()).is_err());
+    }
+}
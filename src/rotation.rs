@@ -0,0 +1,61 @@
+//! Rotation schedules.
+//!
+//! Standard RC5 rotates by data-dependent amounts, which is the core of its design,
+//! but on some microcontrollers the rotation amount leaks through simple power
+//! analysis (SPA), since the duration or the power trace of a rotation depends on it.
+//!
+//! The constant schedule replaces data-dependent rotation amounts with fixed
+//! per-half-round amounts. **It is not RC5**: ciphertexts don't interoperate with
+//! any other implementation, and the known analysis of RC5 (which relies on
+//! data-dependent rotations to resist differential and linear cryptanalysis)
+//! doesn't apply to it. It is meant for research and for closed systems
+//! that prioritize the side-channel shape over interoperability.
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+use crate::Word;
+
+/// Fixed rotation amounts of the constant schedule, repeating every 4 rounds.
+///
+/// Amounts are odd, so that no rotation is zero or a multiple of a byte
+/// for any word size (amounts are taken modulo `w`).
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+const CONSTANT_ROTATIONS: [u32; 8] = [3, 13, 7, 9, 5, 15, 1, 11];
+
+/// How rotation amounts are chosen by the round function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RotationSchedule {
+    /// Standard RC5: rotations by the other register, `A = ((A ^ B) <<< B) + S[2i]`.
+    #[default]
+    DataDependent,
+    /// Non-standard: rotations by fixed per-half-round amounts, see the module docs.
+    Constant,
+}
+
+impl RotationSchedule {
+    /// Rotation amount of a given half-round (`2i` for `A`, `2i + 1` for `B`),
+    /// where `data` is the data-dependent amount of the standard schedule.
+    #[inline(always)]
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn amount<W: Word>(self, half_round: usize, data: W) -> W {
+        match self {
+            Self::DataDependent => data,
+            Self::Constant => W::from(CONSTANT_ROTATIONS[half_round % CONSTANT_ROTATIONS.len()])
+                .expect("rotation amount fits in a word"),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "encrypt", feature = "decrypt")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts() {
+        let schedule = RotationSchedule::Constant;
+        assert_eq!(schedule.amount(2, 0xFFu8), 7);
+        assert_eq!(schedule.amount(11, 0u16), 9);
+        assert_eq!(RotationSchedule::default().amount(2, 0xABu8), 0xAB);
+
+        assert!(CONSTANT_ROTATIONS.iter().all(|a| a % 2 == 1));
+    }
+}
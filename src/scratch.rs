@@ -0,0 +1,68 @@
+//! Caller-supplied scratch buffer.
+//!
+//! Every bulk operation allocates its output, and some also copy their input
+//! into an internal buffer first (e.g. `RC5::encode_vectored`). Tight loops,
+//! like encrypting a token per request, can pass a reusable `ScratchSpace`
+//! to the `*_with_scratch` variants instead: the result is written into
+//! the scratch space and borrowed from it, so after the buffer has grown
+//! to the largest message (the warm-up) nothing is allocated anymore.
+
+use zeroize::Zeroizing;
+
+/// A reusable buffer for the temporaries and the results of bulk operations.
+///
+/// It holds plain text between operations, so it is securely zeroed when dropped.
+/// It grows by moving into a larger allocation and wiping the old one,
+/// so no copies of its contents are left behind in freed memory.
+#[derive(Default)]
+pub struct ScratchSpace(Zeroizing<Vec<u8>>);
+
+impl ScratchSpace {
+    /// Creates an empty scratch space, which allocates on first use.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a scratch space for operations on up to `capacity` bytes,
+    /// so that even the first of them doesn't allocate.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(Zeroizing::new(Vec::with_capacity(capacity)))
+    }
+
+    /// Number of bytes the scratch space holds without growing.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
+    /// Empties the buffer, making room for `len` bytes.
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn reset(&mut self, len: usize) -> &mut Vec<u8> {
+        if self.0.capacity() < len {
+            // The old buffer is wiped when dropped.
+            self.0 = Zeroizing::new(Vec::with_capacity(len));
+        }
+        self.0.clear();
+        &mut self.0
+    }
+}
+
+#[cfg(all(test, any(feature = "encrypt", feature = "decrypt")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset() {
+        let mut scratch = ScratchSpace::new();
+        assert_eq!(scratch.capacity(), 0);
+
+        scratch.reset(16).extend_from_slice(&[1; 16]);
+        assert!(scratch.capacity() >= 16);
+        let ptr = scratch.0.as_ptr();
+        assert!(scratch.reset(8).is_empty());
+        assert_eq!(scratch.0.as_ptr(), ptr);
+
+        scratch.reset(1024);
+        assert!(scratch.capacity() >= 1024);
+        assert!(ScratchSpace::with_capacity(64).capacity() >= 64);
+    }
+}
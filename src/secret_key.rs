@@ -1,7 +1,7 @@
 //! A variable-length cryptographic key.
 
-use crate::{Error, Word};
-use rand::{thread_rng, RngCore};
+use crate::{Error, KeyLength, Word};
+use rand::{thread_rng, CryptoRng, RngCore};
 use secrecy::{ExposeSecret, SecretVec};
 
 /// A variable-legnth cryptographic key.
@@ -16,23 +16,56 @@ use secrecy::{ExposeSecret, SecretVec};
 pub struct SecretKey(SecretVec<u8>);
 
 impl SecretKey {
-    /// Maximum key length is 256
-    /// according to the RC5 original paper.
-    const MAX_KEY_LENGTH: usize = 256;
+    /// Minimum estimated entropy in bits of the keys accepted by `SecretKey::new_checked`.
+    pub const MIN_ENTROPY: usize = 64;
 
     /// Creates a new secret key from the given vector of bytes.
+    ///
+    /// The key must be at most 255 bytes long (see `KeyLength`).
     pub fn new(bytes: Vec<u8>) -> Result<Self, Error> {
-        if bytes.len() > Self::MAX_KEY_LENGTH {
-            Err(Error::InvalidSecretKeyLength(bytes.len()))
-        } else {
-            Ok(Self(bytes.into()))
+        // Rejected keys are wiped as well, when `key` is dropped.
+        let key = Self(bytes.into());
+        KeyLength::new(key.len())?;
+        Ok(key)
+    }
+
+    /// Creates a new secret key, rejecting keys that look weak.
+    ///
+    /// Users routinely pass passwords directly as keys. Keys with
+    /// an estimated entropy (see `SecretKey::estimated_entropy`) lower than
+    /// `SecretKey::MIN_ENTROPY` bits are rejected, and wiped as well.
+    pub fn new_checked(bytes: Vec<u8>) -> Result<Self, Error> {
+        let key = Self::new(bytes)?;
+        let estimated = key.estimated_entropy();
+        if estimated < Self::MIN_ENTROPY {
+            return Err(Error::WeakSecretKey {
+                estimated,
+                min: Self::MIN_ENTROPY,
+            });
         }
+        Ok(key)
     }
 
     /// Generates a random secret key of the given length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length is greater than 255 bytes (see `KeyLength`).
     pub fn random(len: usize) -> Self {
+        Self::random_with_rng(len, &mut thread_rng())
+    }
+
+    /// Generates a random secret key of the given length using a given
+    /// cryptographically secure generator, e.g. an adapter for the hardware
+    /// random number generator of a board.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length is greater than 255 bytes (see `KeyLength`).
+    pub fn random_with_rng(len: usize, rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        KeyLength::new(len).expect("secret key length should be at most 255 bytes");
         let mut data = vec![0u8; len];
-        thread_rng().fill_bytes(&mut data);
+        rng.fill_bytes(&mut data);
         Self(data.into())
     }
 
@@ -41,11 +74,96 @@ impl SecretKey {
         self.secret().len()
     }
 
+    /// Validated length of the secret key.
+    pub fn key_length(&self) -> KeyLength {
+        KeyLength::from(self.len() as u8)
+    }
+
+    /// Returns `true` if the secret key is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gives a closure temporary access to the raw bytes of the secret key.
+    ///
+    /// This is the only sanctioned way to get the key material out of the crate,
+    /// e.g. to hand it over to an HSM. The bytes can't outlive the call,
+    /// but the closure must take care not to copy them somewhere else.
+    pub fn expose_with<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        f(self.secret())
+    }
+
+    /// Estimates the entropy of the secret key in bits, as a rough upper bound.
+    ///
+    /// Only catches obviously weak keys, it can't tell a random-looking
+    /// password from a random key:
+    /// - Repeated patterns (including all-zero keys) count only once.
+    /// - Printable ASCII keys count `log2` of the size of the character classes they use
+    ///   (digits, lowercase, uppercase and other symbols) per character, instead of 8 bits.
+    pub fn estimated_entropy(&self) -> usize {
+        let bytes = self.secret();
+
+        // The shortest prefix the key is a repetition of.
+        let period = (1..bytes.len())
+            .find(|&p| bytes.iter().zip(&bytes[p..]).all(|(a, b)| a == b))
+            .unwrap_or(bytes.len());
+
+        let bits_per_byte = if bytes.iter().all(|b| (0x20..0x7f).contains(b)) {
+            let uses = |class: fn(&u8) -> bool| bytes.iter().any(class);
+            let charset = [
+                (uses(u8::is_ascii_digit), 10),
+                (uses(u8::is_ascii_lowercase), 26),
+                (uses(u8::is_ascii_uppercase), 26),
+                (uses(|b| !b.is_ascii_alphanumeric()), 33),
+            ]
+            .iter()
+            .filter(|(used, _)| *used)
+            .map(|(_, size)| size)
+            .sum::<usize>();
+            (charset as f64).log2()
+        } else {
+            u8::BITS as f64
+        };
+
+        (period as f64 * bits_per_byte) as usize
+    }
+
     /// Returns a reference to a vector of the secret key's bytes.
-    fn secret(&self) -> &Vec<u8> {
+    pub(crate) fn secret(&self) -> &Vec<u8> {
         self.0.expose_secret()
     }
 
+    /// Converts the secret key into an vector of words.
+    pub fn to_words<W: Word>(&self) -> Vec<W> {
+        SecretKeyRef::from(self).to_words()
+    }
+}
+
+/// A secret key borrowed from the caller.
+///
+/// Lets callers holding key bytes in memory of their own (e.g. locked or
+/// hardware-backed) expand a key without the crate copying it into a `SecretKey`.
+/// The caller remains responsible for wiping the bytes.
+#[derive(Clone, Copy)]
+pub struct SecretKeyRef<'a>(&'a [u8]);
+
+impl<'a> SecretKeyRef<'a> {
+    /// Borrows a secret key, which must be at most 255 bytes long (see `KeyLength`).
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        KeyLength::new(bytes.len())?;
+        Ok(Self(bytes))
+    }
+
+    /// Length of the secret key in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the secret key is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     /// Converts the secret key into an vector of words.
     pub fn to_words<W: Word>(&self) -> Vec<W> {
         // According to 4.3 of the original RC5 paper:
@@ -54,12 +172,13 @@ impl SecretKey {
         //
         // where
         // L                 - Vector of words
-        // c = max(b, 1) / u - Number of words in the vector L
-        // u = w / 8         - Number of bytes in word, we use `W::BYTES` for this
+        // c = ceil(max(b, 1) / u) - Number of words in the vector L
+        // u = w / 8               - Number of bytes in word, we use `W::BYTES` for this
 
         // In case of an empty secret key (when `self.len() == 0`),
-        // we return a vector of words of length one with a single `W::zero()` element
-        let len = self.len().max(1) / W::BYTES;
+        // we return a vector of words of length one with a single `W::zero()` element.
+        // Keys that aren't a multiple of the word size are padded with zero bytes.
+        let len = self.len().max(1).div_ceil(W::BYTES);
         let mut words = vec![W::zero(); len];
 
         // To convert secret key's bytes into vector of words
@@ -78,12 +197,13 @@ impl SecretKey {
 
             // Convert key byte into a word.
             // Note, that we will never have `Word` implementations for
-            // types smaller than `u16`, so it is ok to use `expect` here.
-            let w = W::from(self.secret()[i]).expect("word should be larger than u8");
+            // types smaller than `u8`, so it is ok to use `expect` here.
+            let w = W::from(self.0[i]).expect("word should not be smaller than u8");
 
             // Here we use a regular `rotate_left` function instead of our
-            // custom `rotate_left_by` becase we 8 is a constant number less than
-            // the size of the smallest word: 8 < 16. Hence this is safe.
+            // custom `rotate_left_by` becase we 8 is a constant number not larger than
+            // the size of the smallest word: 8 <= 8. Hence this is safe
+            // (for `u8` the rotation is a no-op, since every word holds a single key byte).
             let v = words[j].rotate_left(8).wrapping_add(&w);
 
             words[j] = v;
@@ -93,10 +213,67 @@ impl SecretKey {
     }
 }
 
+impl<'a> From<&'a SecretKey> for SecretKeyRef<'a> {
+    fn from(key: &'a SecretKey) -> Self {
+        Self(key.secret())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// An adapter for a memory-mapped hardware random number generator,
+    /// emulated by a xorshift register.
+    struct HardwareRng {
+        data_register: u32,
+    }
+
+    impl RngCore for HardwareRng {
+        fn next_u32(&mut self) -> u32 {
+            // Reading the data register yields the next random word.
+            let x = &mut self.data_register;
+            *x ^= *x << 13;
+            *x ^= *x >> 17;
+            *x ^= *x << 5;
+            *x
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            (self.next_u32() as u64) << 32 | self.next_u32() as u64
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            for chunk in dest.chunks_mut(4) {
+                let word = self.next_u32().to_le_bytes();
+                chunk.copy_from_slice(&word[..chunk.len()]);
+            }
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for HardwareRng {}
+
+    #[test]
+    fn random_with_rng() {
+        let key = |seed| {
+            SecretKey::random_with_rng(
+                6,
+                &mut HardwareRng {
+                    data_register: seed,
+                },
+            )
+        };
+        assert_eq!(key(1).len(), 6);
+        assert_eq!(key(1).secret(), key(1).secret());
+        assert_ne!(key(1).secret(), key(2).secret());
+        assert_eq!(SecretKey::random(16).len(), 16);
+    }
+
     #[test]
     fn to_words() {
         let key1_bytes = vec![
@@ -111,4 +288,57 @@ mod tests {
             vec![256, 770, 1284, 1798, 2312, 2826, 3340, 3854]
         )
     }
+
+    #[test]
+    fn secret_key_ref() {
+        let bytes = [0x01, 0x02, 0x03];
+        let key = SecretKeyRef::new(&bytes).unwrap();
+        assert_eq!(key.len(), 3);
+        assert_eq!(key.to_words::<u16>(), vec![0x0201, 0x0003]);
+        assert!(matches!(
+            SecretKeyRef::new(&[0; 256]),
+            Err(Error::InvalidSecretKeyLength { actual: 256, .. })
+        ));
+    }
+
+    #[test]
+    fn to_words_unaligned() {
+        let key = SecretKey::new(vec![0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(key.to_words::<u16>(), vec![0x0201, 0x0003]);
+
+        let key = SecretKey::new(vec![]).unwrap();
+        assert_eq!(key.to_words::<u32>(), vec![0]);
+    }
+
+    #[test]
+    fn estimated_entropy() {
+        let entropy = |bytes: &[u8]| SecretKey::new(bytes.to_vec()).unwrap().estimated_entropy();
+        assert_eq!(entropy(&[0; 16]), 8);
+        assert_eq!(entropy(&[1, 2, 3, 1, 2, 3, 1, 2]), 24);
+        assert_eq!(entropy(b"12345678"), 26);
+        assert_eq!(entropy(b"password"), 37);
+        assert_eq!(entropy(b"Tr0ub4dor&3"), 72);
+        assert_eq!(entropy(&(0..16).collect::<Vec<u8>>()), 128);
+        assert_eq!(entropy(&[]), 0);
+    }
+
+    #[test]
+    fn new_checked() {
+        assert!(SecretKey::new_checked((0..16).collect()).is_ok());
+        assert!(matches!(
+            SecretKey::new_checked(vec![0; 16]),
+            Err(Error::WeakSecretKey {
+                estimated: 8,
+                min: 64
+            })
+        ));
+        assert!(SecretKey::new_checked(b"hunter2".to_vec()).is_err());
+    }
+
+    #[test]
+    fn expose_with() {
+        let key = SecretKey::new(vec![0x01, 0x02, 0x03]).unwrap();
+        let sum = key.expose_with(|bytes| bytes.iter().map(|b| *b as u32).sum::<u32>());
+        assert_eq!(sum, 6);
+    }
 }
@@ -0,0 +1,223 @@
+//! [Shamir's secret sharing](https://en.wikipedia.org/wiki/Shamir%27s_secret_sharing)
+//! of the secret key.
+//!
+//! A secret key is split into `n` shares, any `k` of which are enough to
+//! recover the key, while `k - 1` shares reveal nothing about it.
+//! This lets a master key be escrowed across multiple custodians
+//! without any single point of compromise.
+//!
+//! Every byte of the key is shared independently using a random polynomial
+//! of degree `k - 1` over `GF(2^8)` (the same field as in AES, reduced by
+//! `x^8 + x^4 + x^3 + x + 1`). Share `i` holds the values of these
+//! polynomials at `x = i`, the key is recovered by Lagrange interpolation at `x = 0`.
+
+use rand::{thread_rng, RngCore};
+use zeroize::Zeroizing;
+
+use crate::{Error, SecretKey};
+
+/// A single share of a secret key.
+///
+/// Shares are as sensitive as the key itself (given enough of them),
+/// so their contents are securely zeroed when dropped.
+pub struct Share {
+    /// The `x` coordinate of the share (never zero).
+    index: u8,
+    /// Values of the per-byte polynomials at `x = index`.
+    data: Zeroizing<Vec<u8>>,
+}
+
+impl Share {
+    /// Creates a share from its index and data.
+    pub fn new(index: u8, data: Vec<u8>) -> Result<Self, Error> {
+        if index == 0 {
+            return Err(Error::InvalidShare);
+        }
+
+        Ok(Self {
+            index,
+            data: Zeroizing::new(data),
+        })
+    }
+
+    /// Parses a share previously serialized with `Share::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        match bytes.split_first() {
+            Some((&index, data)) => Self::new(index, data.to_vec()),
+            None => Err(Error::InvalidShare),
+        }
+    }
+
+    /// Serializes the share as its index followed by its data.
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut bytes = Zeroizing::new(Vec::with_capacity(self.data.len() + 1));
+        bytes.push(self.index);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// The index (`x` coordinate) of the share.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+}
+
+impl SecretKey {
+    /// Splits the secret key into `n` shares, any `k` of which
+    /// can be combined to recover the key.
+    pub fn split(&self, n: u8, k: u8) -> Result<Vec<Share>, Error> {
+        if k == 0 || k > n {
+            return Err(Error::InvalidShareThreshold { n, k });
+        }
+
+        let secret = self.secret();
+        let mut shares = (1..=n)
+            .map(|index| Share {
+                index,
+                data: Zeroizing::new(vec![0u8; secret.len()]),
+            })
+            .collect::<Vec<_>>();
+
+        // Coefficients of the current polynomial:
+        // the constant term is the secret byte, the rest are random.
+        let mut coefficients = Zeroizing::new(vec![0u8; k as usize]);
+
+        for (i, byte) in secret.iter().enumerate() {
+            coefficients[0] = *byte;
+            thread_rng().fill_bytes(&mut coefficients[1..]);
+
+            for share in shares.iter_mut() {
+                share.data[i] = eval(&coefficients, share.index);
+            }
+        }
+
+        Ok(shares)
+    }
+
+    /// Recovers the secret key from a set of shares.
+    ///
+    /// Note that combining fewer shares than the threshold used when
+    /// splitting silently produces a wrong key, since that is
+    /// indistinguishable from a valid one.
+    pub fn combine(shares: &[Share]) -> Result<Self, Error> {
+        let len = match shares.first() {
+            Some(share) => share.data.len(),
+            None => return Err(Error::InvalidShare),
+        };
+
+        for (i, share) in shares.iter().enumerate() {
+            let duplicate = shares[..i].iter().any(|s| s.index == share.index);
+            if share.data.len() != len || duplicate {
+                return Err(Error::InvalidShare);
+            }
+        }
+
+        // Lagrange basis polynomials evaluated at `x = 0`:
+        //
+        // l[j] = prod(x[m] / (x[m] - x[j])) for m != j
+        //
+        // Subtraction in GF(2^8) is the same as addition (XOR).
+        let basis = shares
+            .iter()
+            .map(|share| {
+                shares
+                    .iter()
+                    .filter(|other| other.index != share.index)
+                    .fold(1, |acc, other| {
+                        mul(acc, div(other.index, other.index ^ share.index))
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        let mut secret = Zeroizing::new(vec![0u8; len]);
+        for (i, byte) in secret.iter_mut().enumerate() {
+            *byte = shares
+                .iter()
+                .zip(basis.iter())
+                .fold(0, |acc, (share, l)| acc ^ mul(share.data[i], *l));
+        }
+
+        SecretKey::new(secret.to_vec())
+    }
+}
+
+/// Evaluates a polynomial at `x` using Horner's method.
+fn eval(coefficients: &[u8], x: u8) -> u8 {
+    coefficients.iter().rev().fold(0, |acc, c| mul(acc, x) ^ c)
+}
+
+/// Multiplies two elements of `GF(2^8)`.
+///
+/// Doesn't branch on its arguments, since those are bytes of the secret key.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// Divides two elements of `GF(2^8)`, `b` must not be zero.
+fn div(a: u8, b: u8) -> u8 {
+    // The multiplicative group has order 255, hence b^-1 = b^254.
+    let mut inverse = 1;
+    for _ in 0..254 {
+        inverse = mul(inverse, b);
+    }
+    mul(a, inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div() {
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        assert_eq!(mul(0x57, 0x13), 0xfe);
+        for a in 1..=255 {
+            assert_eq!(div(mul(a, 0x35), 0x35), a);
+        }
+    }
+
+    #[test]
+    fn split_combine() {
+        let key_bytes = vec![
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+            0x0E, 0x0F,
+        ];
+        let key = SecretKey::new(key_bytes.clone()).unwrap();
+        let shares = key.split(5, 3).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        let recovered = SecretKey::combine(&shares[1..4]).unwrap();
+        assert_eq!(recovered.secret(), &key_bytes);
+
+        let parsed = [&shares[4], &shares[0], &shares[2]]
+            .iter()
+            .map(|s| Share::from_bytes(&s.to_bytes()).unwrap())
+            .collect::<Vec<_>>();
+        let recovered = SecretKey::combine(&parsed).unwrap();
+        assert_eq!(recovered.secret(), &key_bytes);
+    }
+
+    #[test]
+    fn invalid_shares() {
+        let key = SecretKey::random(16);
+        assert!(key.split(2, 3).is_err());
+        assert!(key.split(2, 0).is_err());
+
+        let shares = key.split(3, 2).unwrap();
+        let duplicate = Share::from_bytes(&shares[0].to_bytes()).unwrap();
+        assert!(SecretKey::combine(&[
+            duplicate,
+            Share::from_bytes(&shares[0].to_bytes()).unwrap()
+        ])
+        .is_err());
+        assert!(SecretKey::combine(&[]).is_err());
+        assert!(Share::new(0, vec![1, 2, 3]).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Cipher text and plain text size calculators.
+//!
+//! Lets callers pre-allocate buffers, size database columns and reject
+//! cipher text of an impossible length before attempting to decrypt it.
+//! Lengths don't include the initialization vector or nonce,
+//! which are transmitted separately (e.g. in a `ControlBlockV2`),
+//! nor the tag that `Ccm` and `Ocb` append to the cipher text.
+
+use crate::{control_block::Mode, Error, Word};
+
+/// Padding of the final partial block of the `Ecb`, `Cbc` and `Pcbc` modes.
+///
+/// The other modes don't take a padding: `CbcPad` always pads as specified
+/// by RFC 2040, the others never pad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Padding {
+    /// No padding, the plain text must be a multiple of the block size.
+    None,
+    /// Zeros up to the block size, as done by `RC5::encode_zero_padded`.
+    Zero,
+    /// 1 to block size bytes of the padding length (RFC 2040, PKCS #7).
+    Pkcs7,
+}
+
+/// Length of the cipher text of `plaintext_len` bytes of plain text,
+/// encrypted with `W` words in a given mode.
+///
+/// Fails if the plain text can't be encrypted in the mode: a multiple of the
+/// block size is required without padding, and `Cts` and `Xts` require at least one block.
+/// Fails with `Error::InputTooLarge` if the cipher text length overflows `usize`.
+pub fn encrypted_len<W: Word>(
+    plaintext_len: usize,
+    mode: Mode,
+    padding: Padding,
+) -> Result<usize, Error> {
+    let block_size = 2 * W::BYTES;
+    let invalid = Error::InvalidInputLength {
+        actual: plaintext_len,
+        block_size,
+    };
+
+    match (mode, padding) {
+        (Mode::Ecb | Mode::Cbc | Mode::Pcbc, Padding::None)
+            if !plaintext_len.is_multiple_of(block_size) =>
+        {
+            Err(invalid)
+        }
+        (Mode::Ecb | Mode::Cbc | Mode::Pcbc, Padding::None) => Ok(plaintext_len),
+        (Mode::Ecb | Mode::Cbc | Mode::Pcbc, Padding::Zero) => plaintext_len
+            .div_ceil(block_size)
+            .checked_mul(block_size)
+            .ok_or(Error::InputTooLarge),
+        (Mode::Ecb | Mode::Cbc | Mode::Pcbc, Padding::Pkcs7) | (Mode::CbcPad, _) => (plaintext_len
+            / block_size)
+            .checked_add(1)
+            .and_then(|blocks| blocks.checked_mul(block_size))
+            .ok_or(Error::InputTooLarge),
+        (Mode::Cts | Mode::Xts, _) if plaintext_len < block_size => Err(invalid),
+        (Mode::Cts | Mode::Xts | Mode::Ctr | Mode::Cfb | Mode::Cfb8 | Mode::Ccm | Mode::Ocb, _) => {
+            Ok(plaintext_len)
+        }
+    }
+}
+
+/// Maximum length of the plain text of `ciphertext_len` bytes of cipher text,
+/// encrypted with `W` words in a given mode.
+///
+/// The plain text is exactly this long for the modes without padding
+/// and may be shorter otherwise. Fails if no plain text encrypts
+/// to cipher text of this length.
+pub fn max_plaintext_len<W: Word>(
+    ciphertext_len: usize,
+    mode: Mode,
+    padding: Padding,
+) -> Result<usize, Error> {
+    let block_size = 2 * W::BYTES;
+    let invalid = Error::InvalidInputLength {
+        actual: ciphertext_len,
+        block_size,
+    };
+
+    match (mode, padding) {
+        (Mode::Ecb | Mode::Cbc | Mode::Pcbc | Mode::CbcPad, _)
+            if !ciphertext_len.is_multiple_of(block_size) =>
+        {
+            Err(invalid)
+        }
+        (Mode::Ecb | Mode::Cbc | Mode::Pcbc, Padding::None | Padding::Zero) => Ok(ciphertext_len),
+        // At least one byte of padding.
+        (Mode::Ecb | Mode::Cbc | Mode::Pcbc, Padding::Pkcs7) | (Mode::CbcPad, _) => {
+            ciphertext_len.checked_sub(1).ok_or(invalid)
+        }
+        (Mode::Cts | Mode::Xts, _) if ciphertext_len < block_size => Err(invalid),
+        (Mode::Cts | Mode::Xts | Mode::Ctr | Mode::Cfb | Mode::Cfb8 | Mode::Ccm | Mode::Ocb, _) => {
+            Ok(ciphertext_len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_len() {
+        let len = super::encrypted_len::<u32>;
+        assert_eq!(len(16, Mode::Ecb, Padding::None).unwrap(), 16);
+        assert!(matches!(
+            len(15, Mode::Cbc, Padding::None),
+            Err(Error::InvalidInputLength {
+                actual: 15,
+                block_size: 8
+            })
+        ));
+        assert_eq!(len(0, Mode::Ecb, Padding::Zero).unwrap(), 0);
+        assert_eq!(len(9, Mode::Ecb, Padding::Zero).unwrap(), 16);
+        assert_eq!(len(16, Mode::Cbc, Padding::Pkcs7).unwrap(), 24);
+        assert_eq!(len(0, Mode::CbcPad, Padding::None).unwrap(), 8);
+        assert_eq!(len(9, Mode::Cts, Padding::None).unwrap(), 9);
+        assert!(len(7, Mode::Cts, Padding::None).is_err());
+        assert_eq!(len(3, Mode::Ctr, Padding::None).unwrap(), 3);
+        assert_eq!(len(3, Mode::Cfb8, Padding::None).unwrap(), 3);
+        assert!(len(7, Mode::Xts, Padding::None).is_err());
+        assert_eq!(len(9, Mode::Pcbc, Padding::Zero).unwrap(), 16);
+        assert_eq!(len(5, Mode::Ocb, Padding::None).unwrap(), 5);
+        assert_eq!(
+            super::encrypted_len::<u64>(9, Mode::Ecb, Padding::Zero).unwrap(),
+            16
+        );
+
+        assert!(matches!(
+            len(usize::MAX, Mode::Ecb, Padding::Zero),
+            Err(Error::InputTooLarge)
+        ));
+        assert!(matches!(
+            len(usize::MAX, Mode::CbcPad, Padding::None),
+            Err(Error::InputTooLarge)
+        ));
+        assert!(matches!(
+            len(usize::MAX - 7, Mode::Cbc, Padding::Pkcs7),
+            Err(Error::InputTooLarge)
+        ));
+        assert_eq!(
+            len(usize::MAX, Mode::Ctr, Padding::None).unwrap(),
+            usize::MAX
+        );
+    }
+
+    #[test]
+    fn max_plaintext_len() {
+        let len = super::max_plaintext_len::<u32>;
+        assert_eq!(len(16, Mode::Ecb, Padding::Zero).unwrap(), 16);
+        assert!(len(15, Mode::Ecb, Padding::None).is_err());
+        assert_eq!(len(16, Mode::CbcPad, Padding::None).unwrap(), 15);
+        assert!(len(0, Mode::Cbc, Padding::Pkcs7).is_err());
+        assert!(len(7, Mode::Cts, Padding::None).is_err());
+        assert_eq!(len(7, Mode::Ctr, Padding::None).unwrap(), 7);
+
+        for plaintext_len in 0..40 {
+            for (mode, padding) in [
+                (Mode::Ecb, Padding::Zero),
+                (Mode::Cbc, Padding::Pkcs7),
+                (Mode::Ctr, Padding::None),
+            ] {
+                let ct_len = super::encrypted_len::<u32>(plaintext_len, mode, padding).unwrap();
+                assert!(plaintext_len <= len(ct_len, mode, padding).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "encrypt")]
+    fn matches_zero_padded() {
+        let rc5 = crate::RC5::<u32>::new(vec![0; 16]).unwrap();
+        for plaintext_len in 0..20 {
+            assert_eq!(
+                super::encrypted_len::<u32>(plaintext_len, Mode::Ecb, Padding::Zero).unwrap(),
+                rc5.encode_zero_padded(vec![1; plaintext_len]).len()
+            );
+        }
+    }
+}
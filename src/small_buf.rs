@@ -0,0 +1,104 @@
+//! Small output buffer.
+//!
+//! Messages of a few blocks (e.g. tokens of 16 to 48 bytes) are dominated
+//! by the cost of allocating the output. `SmallBuf` keeps up to `N` bytes
+//! inline, so they can live on the stack, and only spills to the heap
+//! for longer messages.
+
+use std::{
+    mem,
+    ops::{Deref, DerefMut},
+};
+
+use zeroize::{Zeroize, Zeroizing};
+
+enum Repr<const N: usize> {
+    /// Bytes and the length of the buffer.
+    Inline([u8; N], usize),
+    Heap(Zeroizing<Vec<u8>>),
+}
+
+/// A byte buffer holding up to `N` bytes inline, without allocating.
+///
+/// It may hold plain text, so it is securely zeroed when dropped.
+pub struct SmallBuf<const N: usize = 64>(Repr<N>);
+
+impl<const N: usize> SmallBuf<N> {
+    /// Copies bytes into a new buffer, inline if they fit.
+    pub fn from_slice(bytes: &[u8]) -> Self {
+        if bytes.len() > N {
+            return Self(Repr::Heap(Zeroizing::new(bytes.to_vec())));
+        }
+
+        let mut inline = [0; N];
+        inline[..bytes.len()].copy_from_slice(bytes);
+        Self(Repr::Inline(inline, bytes.len()))
+    }
+
+    /// Returns `true` if the bytes are held inline.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline(..))
+    }
+
+    /// Moves the bytes into a vector, which allocates if they are held inline.
+    pub fn into_vec(mut self) -> Vec<u8> {
+        match &mut self.0 {
+            Repr::Inline(..) => self.to_vec(),
+            Repr::Heap(bytes) => mem::take(&mut **bytes),
+        }
+    }
+}
+
+impl<const N: usize> Deref for SmallBuf<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Inline(bytes, len) => &bytes[..*len],
+            Repr::Heap(bytes) => bytes,
+        }
+    }
+}
+
+impl<const N: usize> DerefMut for SmallBuf<N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match &mut self.0 {
+            Repr::Inline(bytes, len) => &mut bytes[..*len],
+            Repr::Heap(bytes) => bytes,
+        }
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for SmallBuf<N> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<const N: usize> Drop for SmallBuf<N> {
+    fn drop(&mut self) {
+        if let Repr::Inline(bytes, _) = &mut self.0 {
+            bytes.zeroize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_and_heap() {
+        let small = SmallBuf::<16>::from_slice(&[1, 2, 3]);
+        assert!(small.is_inline());
+        assert_eq!(&*small, &[1, 2, 3]);
+        assert_eq!(small.into_vec(), vec![1, 2, 3]);
+
+        let mut large = SmallBuf::<2>::from_slice(&[1, 2, 3]);
+        assert!(!large.is_inline());
+        large[0] = 7;
+        assert_eq!(large.into_vec(), vec![7, 2, 3]);
+
+        assert!(SmallBuf::<0>::from_slice(&[]).is_empty());
+    }
+}
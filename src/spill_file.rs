@@ -0,0 +1,209 @@
+//! Encrypted temporary file.
+//!
+//! Pipelines that buffer sensitive intermediate data larger than RAM can spill
+//! it to a `SpillFile` instead of a plain temporary file. Everything written
+//! is encrypted in counter mode (see `Keystream`) under a random ephemeral key,
+//! and decrypted when read back, so the disk only ever holds cipher text.
+//!
+//! The key only lives in memory: it is wiped and the file is deleted on drop,
+//! after which the spilled data can't be recovered, even from a copy of the file.
+//!
+//! The file is append-only: it can be read anywhere, but only written at or
+//! after the end of the data written so far. Overwriting would encrypt the new
+//! bytes with the keystream of the old ones, and XORing the two cipher texts
+//! would reveal the XOR of the two plain texts.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use rand::{thread_rng, RngCore};
+use zeroize::Zeroizing;
+
+use crate::{Error, Keystream, SecretKey, Word, RC5};
+
+/// Length of the ephemeral key in bytes.
+const KEY_LEN: usize = 32;
+
+/// Number of bytes encrypted at a time by `write`.
+const CHUNK_SIZE: usize = 4096;
+
+/// A temporary file encrypted with an ephemeral key.
+///
+/// RC5-64 is used, so the keystream of a file is practically unlimited.
+pub struct SpillFile {
+    cipher: RC5<u64>,
+    file: File,
+    path: PathBuf,
+    /// Current position in the file.
+    pos: u64,
+    /// End of the written data, the keystream before it is used up.
+    end: u64,
+}
+
+impl SpillFile {
+    /// Creates an empty spill file in the temporary directory of the system.
+    pub fn new() -> io::Result<Self> {
+        Self::new_in(std::env::temp_dir())
+    }
+
+    /// Creates an empty spill file in a given directory.
+    pub fn new_in(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let cipher = RC5::from_secret_key(&SecretKey::random(KEY_LEN), u64::ROUNDS)?;
+        loop {
+            let name = format!("rc5-spill-{:016x}", thread_rng().next_u64());
+            let path = dir.as_ref().join(name);
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path);
+            match file {
+                Ok(file) => {
+                    return Ok(Self {
+                        cipher,
+                        file,
+                        path,
+                        pos: 0,
+                        end: 0,
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Path of the file, which holds cipher text only.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// XORs the keystream at the current position into `data`.
+    fn apply_keystream(&self, data: &mut [u8]) -> io::Result<()> {
+        let mut keystream = Keystream::new(&self.cipher, 0);
+        keystream.discard(self.pos)?;
+        keystream.apply(data)?;
+        Ok(())
+    }
+}
+
+/// Reads and decrypts bytes at the current position.
+impl Read for SpillFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read(buf)?;
+        self.apply_keystream(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Encrypts and writes bytes at the current position.
+///
+/// Fails with `Error::InvalidWritePosition` behind the end of the written data.
+impl Write for SpillFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.pos < self.end {
+            return Err(Error::InvalidWritePosition {
+                pos: self.pos,
+                end: self.end,
+            }
+            .into());
+        }
+
+        let mut chunk = Zeroizing::new([0; CHUNK_SIZE]);
+        let chunk = &mut chunk[..buf.len().min(CHUNK_SIZE)];
+        chunk.copy_from_slice(&buf[..chunk.len()]);
+        self.apply_keystream(chunk)?;
+        // A partial write would leave the file out of step with the keystream.
+        if let Err(e) = self.file.write_all(chunk) {
+            // Some of the chunk may have been written, using up its keystream.
+            self.pos = self.file.stream_position()?;
+            self.end = self.end.max(self.pos);
+            return Err(e);
+        }
+        self.pos += chunk.len() as u64;
+        self.end = self.pos;
+        Ok(chunk.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for SpillFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.file.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+/// The file is deleted, the key is wiped along with the cipher.
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        // Nothing to do about a failure, the cipher text is useless without the key.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_back() {
+        let data = (0..10_000).map(|i| i as u8).collect::<Vec<_>>();
+        let mut spill = SpillFile::new().unwrap();
+        for chunk in data.chunks(777) {
+            spill.write_all(chunk).unwrap();
+        }
+        spill.flush().unwrap();
+
+        let on_disk = fs::read(spill.path()).unwrap();
+        assert_eq!(on_disk.len(), data.len());
+        assert_ne!(on_disk, data);
+
+        spill.rewind().unwrap();
+        let mut read = Vec::new();
+        spill.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+
+        spill.seek(SeekFrom::Start(1234)).unwrap();
+        let mut read = [0; 20];
+        spill.read_exact(&mut read).unwrap();
+        assert_eq!(read, data[1234..1254]);
+    }
+
+    #[test]
+    fn append_only() {
+        let mut spill = SpillFile::new().unwrap();
+        spill.write_all(&[1; 100]).unwrap();
+
+        spill.seek(SeekFrom::Start(40)).unwrap();
+        let e = spill.write(&[0xFF; 10]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            e.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+            Some(&Error::InvalidWritePosition { pos: 40, end: 100 })
+        );
+
+        spill.seek(SeekFrom::End(0)).unwrap();
+        spill.write_all(&[2; 10]).unwrap();
+        spill.rewind().unwrap();
+        let mut read = Vec::new();
+        spill.read_to_end(&mut read).unwrap();
+        assert_eq!(read, [&[1; 100][..], &[2; 10]].concat());
+    }
+
+    #[test]
+    fn deleted_on_drop() {
+        let spill = SpillFile::new().unwrap();
+        let path = spill.path().to_owned();
+        assert!(path.exists());
+        drop(spill);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,52 @@
+//! Measured encryption throughput.
+
+use std::fmt;
+#[cfg(feature = "encrypt")]
+use std::time::Duration;
+
+/// Encryption throughput of an RC5 instance on the current machine,
+/// see `RC5::benchmark`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Throughput {
+    /// Number of blocks encrypted per second.
+    pub blocks_per_sec: f64,
+    /// Number of bytes encrypted per second.
+    pub bytes_per_sec: f64,
+}
+
+impl Throughput {
+    /// Computes the throughput of encrypting a number of bytes in a given time.
+    #[cfg(feature = "encrypt")]
+    pub(crate) fn new(bytes: u64, block_size: usize, elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+        Self {
+            blocks_per_sec: (bytes / block_size as u64) as f64 / secs,
+            bytes_per_sec: bytes as f64 / secs,
+        }
+    }
+}
+
+/// Formats the throughput in MiB/s, e.g. for startup logs.
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} MiB/s ({:.0} blocks/s)",
+            self.bytes_per_sec / (1024.0 * 1024.0),
+            self.blocks_per_sec
+        )
+    }
+}
+
+#[cfg(all(test, feature = "encrypt"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new() {
+        let throughput = Throughput::new(2 * 1024 * 1024, 8, Duration::from_secs(2));
+        assert_eq!(throughput.bytes_per_sec, 1024.0 * 1024.0);
+        assert_eq!(throughput.blocks_per_sec, 131072.0);
+        assert_eq!(throughput.to_string(), "1.0 MiB/s (131072 blocks/s)");
+    }
+}
@@ -0,0 +1,67 @@
+//! XEX-style key whitening.
+//!
+//! Whitening XORs an extra key into the block before the first round and
+//! another one after the last round (as in DESX). It makes exhaustive search
+//! and some attacks on the outer rounds harder, which may help users stuck
+//! on low round counts for interoperability reasons.
+//!
+//! Note that whitened ciphertexts are not compatible with plain RC5.
+
+use zeroize::Zeroizing;
+
+use crate::{Error, SecretKey};
+
+/// Pre- and post-whitening keys.
+///
+/// They are as sensitive as the secret key itself,
+/// so they are securely zeroed when dropped.
+#[derive(Clone)]
+#[cfg_attr(not(any(feature = "encrypt", feature = "decrypt")), allow(dead_code))]
+pub(crate) struct Whitening {
+    /// Key XORed into the block before encryption.
+    pre: Zeroizing<Vec<u8>>,
+    /// Key XORed into the block after encryption.
+    post: Zeroizing<Vec<u8>>,
+}
+
+impl Whitening {
+    /// Splits additional key material of twice the block size
+    /// into pre- and post-whitening keys.
+    pub(crate) fn new(key: &SecretKey, block_size: usize) -> Result<Self, Error> {
+        key.expose_with(|bytes| {
+            if bytes.len() != 2 * block_size {
+                return Err(Error::InvalidWhiteningKeyLength {
+                    actual: bytes.len(),
+                    expected: 2 * block_size,
+                });
+            }
+
+            let (pre, post) = bytes.split_at(block_size);
+            Ok(Self {
+                pre: Zeroizing::new(pre.to_vec()),
+                post: Zeroizing::new(post.to_vec()),
+            })
+        })
+    }
+
+    /// Applies the pre-whitening key when encrypting
+    /// (or the post-whitening key when decrypting).
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn pre(&self, block: &mut [u8]) {
+        xor(block, &self.pre);
+    }
+
+    /// Applies the post-whitening key when encrypting
+    /// (or the pre-whitening key when decrypting).
+    #[cfg(any(feature = "encrypt", feature = "decrypt"))]
+    pub(crate) fn post(&self, block: &mut [u8]) {
+        xor(block, &self.post);
+    }
+}
+
+#[cfg(any(feature = "encrypt", feature = "decrypt"))]
+fn xor(block: &mut [u8], key: &[u8]) {
+    for (b, k) in block.iter_mut().zip(key) {
+        *b ^= k;
+    }
+}
@@ -1,11 +1,15 @@
-//! Provides the `Word` trait and its implementations for `u16`, `u32` and `u64`.
+//! Provides the `Word` trait and its implementations for `u8`, `u16`, `u32` and `u64`.
 //!
 //! The RC5 is adaptable for processors of different word-lengths.
 //! Hence all of the basic computational operations have `w`-bit words as inputs and outputs.
 //! It is well-defined for any `w > 0`, but for simplicity only allowable
-//! sizes are 16, 32 and 64. The nominal choice for `w` is 32 bits.
+//! sizes are 8, 16, 32 and 64. The nominal choice for `w` is 32 bits.
+//!
+//! The 8-bit word is not meant for real use, its 16-bit block space
+//! is small enough to be tested exhaustively.
 
 use num_traits::{PrimInt, WrappingAdd, WrappingSub, Zero};
+use zeroize::Zeroize;
 
 use crate::{FromLeBytes, HasPQ, ToLeBytes};
 
@@ -13,7 +17,8 @@ use crate::{FromLeBytes, HasPQ, ToLeBytes};
 ///
 /// Our word type is a primitive integer (`PrimInt`),
 /// has wrapping (modulo 2) addition and subtraction operations (`WrappingAdd` and `WrappingSub`),
-/// magic constants (`HasPQ`) and is convertible to/from little-endian byte array (`FromLeBytes` and `ToLeBytes`).
+/// magic constants (`HasPQ`), is convertible to/from little-endian byte array (`FromLeBytes` and `ToLeBytes`)
+/// and can be securely zeroed (`Zeroize`), since words hold plaintext and key material.
 ///
 /// According to section *3. Notation and RC5 Primitive Operations* of the [original paper](https://www.grc.com/r&d/rc5.pdf):
 ///
@@ -21,7 +26,7 @@ use crate::{FromLeBytes, HasPQ, ToLeBytes};
 /// interpreted modulo `w` (size of the word in bits), so that when `w` is a power of two,
 /// only the `lg(w)` low-order bits are used to determine the rotation amount.
 pub trait Word:
-    PrimInt + Zero + WrappingAdd + WrappingSub + HasPQ + FromLeBytes + ToLeBytes
+    PrimInt + Zero + WrappingAdd + WrappingSub + HasPQ + FromLeBytes + ToLeBytes + Zeroize
 {
     /// The size of this word type in bits
     const BITS: usize;
@@ -37,7 +42,7 @@ pub trait Word:
     ///
     /// Corresponds to `<<<` operator from the RC5 paper.
     fn rotate_left_by(&self, n: Self) -> Self {
-        self.rotate_left(n.to_u32().unwrap() % Self::BITS as u32)
+        self.rotate_left((n.to_u64().unwrap() % Self::BITS as u64) as u32)
     }
 
     /// Shifts the bits to the right by a specified `word`, wrapping
@@ -45,7 +50,7 @@ pub trait Word:
     ///
     /// Corresponds to `>>>` operator from the RC5 paper.
     fn rotate_right_by(&self, n: Self) -> Self {
-        self.rotate_right(n.to_u32().unwrap() % Self::BITS as u32)
+        self.rotate_right((n.to_u64().unwrap() % Self::BITS as u64) as u32)
     }
 }
 
@@ -55,6 +60,20 @@ macro_rules! word_impl {
         impl Word for $t {
             const BITS: usize = Self::BITS as usize;
             const ROUNDS: usize = $r;
+
+            // Native rotations: the amount is reduced modulo `w` in the word's
+            // own width, without widening to `u64` and unwrapping through `num-traits`.
+            // That matters on 8/16-bit microcontrollers, which only use RC5-16.
+
+            #[inline(always)]
+            fn rotate_left_by(&self, n: Self) -> Self {
+                <$t>::rotate_left(*self, (n & (Self::BITS - 1) as $t) as u32)
+            }
+
+            #[inline(always)]
+            fn rotate_right_by(&self, n: Self) -> Self {
+                <$t>::rotate_right(*self, (n & (Self::BITS - 1) as $t) as u32)
+            }
         }
     };
 }
@@ -76,6 +95,7 @@ macro_rules! word_impl {
 // the authors changed the recommendation when `w=32` to 16 rounds (see [RC5sec](https://datatracker.ietf.org/doc/html/draft-krovetz-rc6-rc5-vectors-00#ref-RC5sec)).
 // So the recommended/nominal choice of parameters is RC5-32/16/16.
 
+word_impl!(u8, 8); // RC5-8/8/b
 word_impl!(u16, 12); // RC5-16/12/b
 word_impl!(u32, 16); // RC5-32/16/b
 word_impl!(u64, 20); // RC5-64/20/b
@@ -86,6 +106,12 @@ mod tests {
 
     // Sanity check tests
 
+    #[test]
+    fn u8_sizes() {
+        assert_eq!(<u8 as Word>::BITS, 8);
+        assert_eq!(<u8 as Word>::BYTES, 1);
+    }
+
     #[test]
     fn u16_sizes() {
         assert_eq!(<u16 as Word>::BITS, 16);
@@ -121,4 +147,13 @@ mod tests {
         let r = Word::rotate_right_by(&n, bits);
         assert_eq!(r, m);
     }
+
+    #[test]
+    fn rotate_by_large_word() {
+        let n = 0x0123456789ABCDEFu64;
+        let m = 0x3456789ABCDEF012u64;
+        let bits = 12 + (1u64 << 40);
+        assert_eq!(Word::rotate_left_by(&n, bits), m);
+        assert_eq!(Word::rotate_right_by(&m, bits), n);
+    }
 }
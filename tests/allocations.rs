@@ -0,0 +1,122 @@
+//! Counts heap allocations of the hot paths, so that they can't silently regress.
+//!
+//! Lives in its own test binary, as it replaces the global allocator.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    hint::black_box,
+    io::IoSlice,
+};
+
+use rc5_test::*;
+
+/// The system allocator, counting allocations of every thread.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // Thread-local storage may already be gone while a thread exits.
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Number of allocations made by the current thread while running `f`.
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn encode_decode() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    let rc5_64 = RC5::<u64>::new(vec![0x42; 16]).unwrap();
+    for len in [16, 64, 1024] {
+        let pt = vec![7; len];
+        // Enough for the vector kernels, if the machine supports them.
+        assert!(allocations(|| drop(black_box(rc5.encode(&pt)))) <= 1);
+        assert!(allocations(|| drop(black_box(rc5.decode(&pt)))) <= 1);
+        assert!(allocations(|| drop(black_box(rc5_64.encode(&pt)))) <= 1);
+        assert!(allocations(|| drop(black_box(rc5_64.decode(&pt)))) <= 1);
+    }
+}
+
+#[test]
+fn in_place() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    let mut packet = vec![7; 4 + 1024];
+    assert_eq!(
+        allocations(|| rc5.encode_in_place(&mut packet, 4).unwrap()),
+        0
+    );
+    assert_eq!(
+        allocations(|| rc5.decode_in_place(&mut packet, 4).unwrap()),
+        0
+    );
+
+    let mut data = vec![7; 100];
+    let mut keystream = Keystream::new(&rc5, 1);
+    assert_eq!(allocations(|| keystream.apply(&mut data).unwrap()), 0);
+}
+
+#[test]
+fn small() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    let pt = [7; 48];
+    assert_eq!(
+        allocations(|| drop(black_box(rc5.encode_small::<64>(pt)))),
+        0
+    );
+    assert_eq!(
+        allocations(|| drop(black_box(rc5.decode_small::<64>(pt)))),
+        0
+    );
+}
+
+#[test]
+fn scratch() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    let mut scratch = ScratchSpace::new();
+    // Warm-up.
+    rc5.encode_with_scratch([7; 1024], &mut scratch);
+
+    for len in [16, 64, 1024] {
+        let pt = vec![7; len];
+        assert_eq!(
+            allocations(|| {
+                black_box(rc5.encode_with_scratch(&pt, &mut scratch));
+            }),
+            0
+        );
+        assert_eq!(
+            allocations(|| {
+                black_box(rc5.decode_with_scratch(&pt, &mut scratch));
+            }),
+            0
+        );
+    }
+
+    let bufs = [IoSlice::new(&[7; 5]), IoSlice::new(&[8; 11])];
+    assert_eq!(
+        allocations(|| {
+            black_box(
+                rc5.encode_vectored_with_scratch(&bufs, &mut scratch)
+                    .unwrap(),
+            );
+        }),
+        0
+    );
+}
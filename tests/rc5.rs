@@ -1,4 +1,55 @@
-use rc5_test::*;
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt::Debug,
+    io::IoSlice,
+};
+
+use rc5_test::{
+    block::Block,
+    le_bytes::{FromLeBytes, ToLeBytes},
+    modes::BlockMode,
+    *,
+};
+use zeroize::Zeroizing;
+
+/// Exhaustively checks that encryption is a bijection over the whole block space
+/// and that decryption inverts it, which catches round function and packing
+/// regressions that random tests miss.
+///
+/// Only feasible for blocks of up to 4 bytes (`w <= 16`).
+fn assert_bijective<W: Word>(key: Vec<u8>, rounds: usize)
+where
+    <<W as FromLeBytes>::T as TryFrom<Vec<u8>>>::Error: Debug,
+    <<W as ToLeBytes>::T as TryInto<Vec<u8>>>::Error: Debug,
+{
+    const CHUNK: u64 = 1 << 16;
+
+    let rc5 = RC5::<W>::new_with_rounds(key, rounds).unwrap();
+    let block_size = 2 * W::BYTES;
+    assert!(block_size <= 4, "block space is too large");
+
+    let blocks = 1u64 << (8 * block_size);
+    let mut seen = vec![0u64; (blocks / 64) as usize];
+    let (mut pt, mut value) = (Vec::new(), [0; 8]);
+
+    for start in (0..blocks).step_by(CHUNK as usize) {
+        pt.clear();
+        for block in start..(start + CHUNK).min(blocks) {
+            pt.extend_from_slice(&block.to_le_bytes()[..block_size]);
+        }
+
+        let ct = rc5.encode(&pt);
+        for block in ct.chunks(block_size) {
+            value[..block_size].copy_from_slice(block);
+            let v = u64::from_le_bytes(value);
+            let (word, bit) = ((v / 64) as usize, 1 << (v % 64));
+            assert_eq!(seen[word] & bit, 0, "collision at {:#x}", v);
+            seen[word] |= bit;
+        }
+
+        assert_eq!(rc5.decode(&ct), pt);
+    }
+}
 
 #[test]
 fn encode_a() {
@@ -47,3 +98,532 @@ fn decode_b() {
     let ct = vec![0xEA, 0x02, 0x47, 0x14, 0xAD, 0x5C, 0x4D, 0x84];
     assert_eq!(ct, rc5.encode(&pt));
 }
+
+#[test]
+fn reference_profile() {
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let rc5 = RC5::<u32>::new_with_profile(key, &CompatProfile::REFERENCE).unwrap();
+    let pt = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+    let ct = vec![0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E];
+    assert_eq!(ct, rc5.encode(&pt));
+}
+
+#[test]
+fn crypto_pp_profile() {
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let rc5 = RC5::<u32>::new_with_profile(key.clone(), &CompatProfile::CRYPTO_PP).unwrap();
+    let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+    let rounds_16 = RC5::<u32>::new_with_rounds(key, 16).unwrap();
+    assert_eq!(rc5.encode(pt), rounds_16.encode(pt));
+
+    // Crypto++ validates RC5 against the RC5-32/12 vectors of the paper (`rc5val.dat`).
+    let profile = CompatProfile {
+        rounds: Some(12),
+        ..CompatProfile::CRYPTO_PP
+    };
+    let vectors = [
+        (
+            [0; 16],
+            [0x00; 8],
+            [0x21, 0xA5, 0xDB, 0xEE, 0x15, 0x4B, 0x8F, 0x6D],
+        ),
+        (
+            [
+                0x91, 0x5F, 0x46, 0x19, 0xBE, 0x41, 0xB2, 0x51, 0x63, 0x55, 0xA5, 0x01, 0x10, 0xA9,
+                0xCE, 0x91,
+            ],
+            [0x21, 0xA5, 0xDB, 0xEE, 0x15, 0x4B, 0x8F, 0x6D],
+            [0xF7, 0xC0, 0x13, 0xAC, 0x5B, 0x2B, 0x89, 0x52],
+        ),
+    ];
+    for (key, pt, ct) in vectors {
+        let rc5 = RC5::<u32>::new_with_profile(key.to_vec(), &profile).unwrap();
+        assert_eq!(rc5.encode(pt), ct);
+        assert_eq!(rc5.decode(ct), pt);
+    }
+
+    // `CBC_Mode<RC5>` with PKCS padding, the RC5-CBC-Pad vectors of RFC 2040 (8 rounds).
+    let profile = CompatProfile {
+        rounds: Some(8),
+        ..CompatProfile::CRYPTO_PP
+    };
+    let rc5 = RC5::<u32>::new_with_profile(vec![0x01, 0x02, 0x03, 0x04, 0x05], &profile).unwrap();
+    let cbc = modes::Rc5CbcPad::new(rc5, &[0; 8]).unwrap();
+    let pt = [0xff; 8];
+    let ct = [
+        0x78, 0x75, 0xdb, 0xf6, 0x73, 0x8c, 0x64, 0x78, 0x8f, 0x34, 0xc3, 0xc6, 0x81, 0xc9, 0x96,
+        0x95,
+    ];
+    assert_eq!(cbc.encrypt(pt), ct);
+    assert_eq!(cbc.decrypt(ct).unwrap(), pt);
+}
+
+#[test]
+fn big_endian_profile() {
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let profile = CompatProfile {
+        name: "big-endian",
+        byte_order: compat_profile::ByteOrder::BigEndian,
+        word_order: compat_profile::WordOrder::AB,
+        rounds: Some(12),
+    };
+    let rc5 = RC5::<u32>::new_with_profile(key, &profile).unwrap();
+    let pt = vec![0x33, 0x22, 0x11, 0x00, 0x77, 0x66, 0x55, 0x44];
+    let ct = vec![0x9B, 0x14, 0xDC, 0x2D, 0x9E, 0x8B, 0x08, 0xCF];
+    assert_eq!(ct, rc5.encode(&pt));
+    assert_eq!(pt, rc5.decode(&ct));
+}
+
+#[test]
+fn swapped_word_order_profile() {
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let profile = CompatProfile {
+        name: "swapped",
+        word_order: compat_profile::WordOrder::BA,
+        ..CompatProfile::REFERENCE
+    };
+    let rc5 = RC5::<u32>::new_with_profile(key, &profile).unwrap();
+    // The reference vector with the words of every block swapped.
+    let pt = vec![0x44, 0x55, 0x66, 0x77, 0x00, 0x11, 0x22, 0x33];
+    let ct = vec![0xCF, 0x08, 0x8B, 0x9E, 0x2D, 0xDC, 0x14, 0x9B];
+    assert_eq!(ct, rc5.encode(&pt));
+    assert_eq!(pt, rc5.decode(&ct));
+
+    // Longer inputs bypass the vector kernels.
+    let pt = (0..128).collect::<Vec<u8>>();
+    let blockwise = pt.chunks(8).flat_map(|b| rc5.encode(b)).collect::<Vec<_>>();
+    assert_eq!(rc5.encode(&pt), blockwise);
+    assert_eq!(pt, rc5.decode(&blockwise));
+}
+
+#[test]
+fn encode_decode_vectored() {
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let rc5 = RC5::<u32>::new_with_rounds(key, 12).unwrap();
+    let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+    let ct = vec![0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E];
+    let bufs = [
+        IoSlice::new(&pt[..3]),
+        IoSlice::new(&[]),
+        IoSlice::new(&pt[3..]),
+    ];
+    assert_eq!(ct, rc5.encode_vectored(&bufs).unwrap());
+    let bufs = [IoSlice::new(&ct[..5]), IoSlice::new(&ct[5..])];
+    assert_eq!(pt.to_vec(), rc5.decode_vectored(&bufs).unwrap());
+    assert!(matches!(
+        rc5.encode_vectored(&[IoSlice::new(&pt[1..])]),
+        Err(Error::InvalidInputLength {
+            actual: 7,
+            block_size: 8
+        })
+    ));
+}
+
+#[test]
+fn encode_decode_in_place() {
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let rc5 = RC5::<u32>::new_with_rounds(key, 12).unwrap();
+    let header = [0xFF, 0xEE, 0xDD];
+    let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+    let ct = [0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E];
+
+    let mut packet = [&header[..], &pt[..]].concat();
+    rc5.encode_in_place(&mut packet, header.len()).unwrap();
+    assert_eq!(packet, [&header[..], &ct[..]].concat());
+    rc5.decode_in_place(&mut packet, header.len()).unwrap();
+    assert_eq!(packet, [&header[..], &pt[..]].concat());
+
+    assert!(matches!(
+        rc5.encode_in_place(&mut packet, 12),
+        Err(Error::InvalidOffset {
+            offset: 12,
+            len: 11
+        })
+    ));
+    assert!(matches!(
+        rc5.encode_in_place(&mut packet, 1),
+        Err(Error::InvalidInputLength { actual: 10, .. })
+    ));
+}
+
+#[test]
+fn whitening() {
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let pre = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+    let post = [0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80];
+    let whitening_key = SecretKey::new([pre, post].concat()).unwrap();
+    let rc5 = RC5::<u32>::new_with_rounds(key.clone(), 12).unwrap();
+    let whitened = RC5::<u32>::new_with_rounds(key, 12)
+        .unwrap()
+        .with_whitening(&whitening_key)
+        .unwrap();
+
+    let pt = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+    let xored = |a: &[u8], b: &[u8]| a.iter().zip(b).map(|(x, y)| x ^ y).collect::<Vec<_>>();
+    let ct = whitened.encode(&pt);
+    assert_eq!(ct, xored(&rc5.encode(xored(&pt, &pre)), &post));
+    assert_eq!(pt, whitened.decode(&ct));
+
+    // Long enough for the batch kernels as well.
+    let long_pt = pt.repeat(19);
+    let long_ct = whitened.encode(&long_pt);
+    assert_eq!(long_ct, ct.repeat(19));
+    assert_eq!(long_pt, whitened.decode(&long_ct));
+
+    assert!(matches!(
+        rc5.with_whitening(&SecretKey::new(vec![0; 8]).unwrap()),
+        Err(Error::InvalidWhiteningKeyLength {
+            actual: 8,
+            expected: 16
+        })
+    ));
+}
+
+#[test]
+fn encode_decode_many() {
+    let rc5 = RC5::<u32>::new(vec![0x2A; 16]).unwrap();
+    let messages = (0..20u8)
+        .map(|i| vec![i; 8 * (i as usize % 4)])
+        .collect::<Vec<_>>();
+    let plaintexts = messages.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+    let ciphertexts = rc5.encode_many(&plaintexts).unwrap();
+    for (pt, ct) in plaintexts.iter().zip(&ciphertexts) {
+        assert_eq!(rc5.encode(pt), *ct);
+    }
+
+    let ciphertexts = ciphertexts.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    assert_eq!(rc5.decode_many(&ciphertexts).unwrap(), messages);
+
+    assert!(matches!(
+        rc5.encode_many(&[&[0; 8], &[0; 3]]),
+        Err(Error::InvalidInputLength { actual: 3, .. })
+    ));
+}
+
+#[test]
+fn bijective_w8() {
+    for rounds in [0, 1, 8] {
+        assert_bijective::<u8>(vec![0x00, 0x01, 0x02, 0x03], rounds);
+        assert_bijective::<u8>(vec![0xFF; 16], rounds);
+    }
+}
+
+#[test]
+#[ignore = "encrypts the whole 32-bit block space, takes minutes in release mode"]
+fn bijective_w16() {
+    assert_bijective::<u16>(vec![0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07], 12);
+}
+
+#[test]
+fn convertible_inputs() {
+    let key = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+    let ct = vec![0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E];
+
+    let from_array = RC5::<u32>::new_with_rounds(key, 12).unwrap();
+    let from_slice = RC5::<u32>::new_with_rounds(&key[..], 12).unwrap();
+    assert_eq!(from_array.encode(pt), ct);
+    assert_eq!(from_slice.encode(&pt[..]), ct);
+    assert_eq!(from_slice.decode(&ct), pt);
+
+    let from_str = RC5::<u32>::new("correct horse battery").unwrap();
+    assert_eq!(from_str.decode(from_str.encode("8 bytes!")), b"8 bytes!");
+}
+
+#[test]
+fn encode_decode_parallel() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    let pt = (0..4096 + 24).map(|i| i as u8).collect::<Vec<_>>();
+    let ct = rc5.encode(&pt);
+
+    for threads in [0, 1, 3, 8, 1000] {
+        assert_eq!(rc5.encode_parallel(&pt, threads), ct);
+        assert_eq!(rc5.decode_parallel(&ct, threads), pt);
+    }
+}
+
+#[test]
+fn security_level() {
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let pt = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+    let legacy = RC5::<u32>::new_with_security_level(key.clone(), SecurityLevel::Legacy).unwrap();
+    let reference = RC5::<u32>::new_with_rounds(key.clone(), 12).unwrap();
+    assert_eq!(legacy.encode(&pt), reference.encode(&pt));
+
+    let paranoid = RC5::<u32>::new_with_security_level(key, SecurityLevel::Paranoid).unwrap();
+    assert_eq!(paranoid.decode(paranoid.encode(&pt)), pt);
+}
+
+#[test]
+fn benchmark() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    let throughput = rc5.benchmark(std::time::Duration::from_millis(10));
+    assert!(throughput.bytes_per_sec > 0.0);
+    assert!((throughput.bytes_per_sec / throughput.blocks_per_sec - 8.0).abs() < 1e-9);
+}
+
+#[test]
+fn rc5_params() {
+    let params = Rc5Params::new(32, 12, 16).unwrap();
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let pt = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+    let ct = vec![0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E];
+    let rc5 = RC5::<u32>::new_with_rc5_params(key.clone(), params).unwrap();
+    assert_eq!(rc5.encode(&pt), ct);
+
+    assert!(matches!(
+        RC5::<u64>::new_with_rc5_params(key.clone(), params),
+        Err(Error::InvalidWordSize(32))
+    ));
+    assert!(matches!(
+        RC5::<u32>::new_with_rc5_params(&key[..8], params),
+        Err(Error::InvalidSecretKeyLength {
+            actual: 8,
+            min: 16,
+            max: 16
+        })
+    ));
+}
+
+#[test]
+fn constant_rotations() {
+    let key = vec![0x42; 16];
+    let pt = (0..200).map(|i| i as u8).collect::<Vec<_>>();
+    let standard = RC5::<u32>::new(key.clone()).unwrap();
+    let constant = RC5::<u32>::new(key)
+        .unwrap()
+        .with_rotation_schedule(RotationSchedule::Constant);
+
+    assert_eq!(constant.kernel(), kernel::Kernel::Scalar);
+    let ct = constant.encode(&pt);
+    assert_ne!(ct, standard.encode(&pt));
+    assert_eq!(constant.decode(&ct), pt);
+}
+
+#[test]
+fn encode_decode_small() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    for len in [0, 16, 48, 72] {
+        let pt = (0..len).collect::<Vec<u8>>();
+        let ct: SmallBuf = rc5.encode_small(&pt);
+        assert_eq!(ct.is_inline(), len <= 64);
+        assert_eq!(&*ct, &rc5.encode(&pt)[..]);
+        assert_eq!(&*rc5.decode_small::<64>(&ct), &pt[..]);
+    }
+}
+
+#[test]
+fn memory_budget() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16])
+        .unwrap()
+        .with_memory_budget(MemoryBudget::new(16));
+    let pt = [0u8; 24];
+    assert_eq!(rc5.encode_many(&[&pt[..8], &pt[..8]]).unwrap().len(), 2);
+    assert!(matches!(
+        rc5.encode_many(&[&pt[..16], &pt[..8]]),
+        Err(Error::MemoryBudgetExceeded {
+            requested: 24,
+            budget: 16
+        })
+    ));
+    assert!(rc5
+        .decode_vectored(&[IoSlice::new(&pt[..17]), IoSlice::new(&pt[..7])])
+        .is_err());
+    // The output of a single message isn't an internal buffer.
+    assert_eq!(rc5.encode(pt).len(), 24);
+}
+
+#[test]
+fn from_secret_key_ref() {
+    let key = [0x42; 16];
+    let pt = [1, 2, 3, 4, 5, 6, 7, 8];
+    let borrowed = RC5::<u32>::from_secret_key(SecretKeyRef::new(&key).unwrap(), 12).unwrap();
+    let owned = RC5::<u32>::new_with_rounds(key.to_vec(), 12).unwrap();
+    assert_eq!(borrowed.encode(pt), owned.encode(pt));
+}
+
+#[test]
+fn clone_shares_key_table() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    let pt = (0..64).collect::<Vec<u8>>();
+    let ct = rc5.encode(&pt);
+
+    let workers = (0..4).map(|_| rc5.clone()).collect::<Vec<_>>();
+    drop(rc5);
+    std::thread::scope(|scope| {
+        for worker in workers {
+            let (pt, ct) = (&pt, &ct);
+            scope.spawn(move || assert_eq!(worker.decode(ct), *pt));
+        }
+    });
+}
+
+#[test]
+fn encode_decode_zero_padded() {
+    let key = vec![
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let rc5 = RC5::<u32>::new_with_rounds(key, 12).unwrap();
+    let pt = [0x00, 0x11, 0x22];
+    let ct = rc5.encode_zero_padded(pt);
+    assert_eq!(ct, rc5.encode([0x00, 0x11, 0x22, 0, 0, 0, 0, 0]));
+    assert_eq!(rc5.decode_zero_padded(&ct, pt.len()).unwrap(), pt);
+    assert_eq!(rc5.decode_zero_padded(&ct, 8).unwrap().len(), 8);
+
+    assert!(rc5.encode_zero_padded([]).is_empty());
+    assert_eq!(rc5.encode_zero_padded([0; 8]).len(), 8);
+
+    assert!(matches!(
+        rc5.decode_zero_padded(&ct, 9),
+        Err(Error::InvalidPlaintextLength {
+            actual: 9,
+            ciphertext_len: 8
+        })
+    ));
+    assert!(matches!(
+        rc5.decode_zero_padded(&ct[..5], 3),
+        Err(Error::InvalidInputLength { actual: 5, .. })
+    ));
+}
+
+#[test]
+fn incremental_key_expansion() {
+    let key = SecretKey::new(vec![0x42; 16]).unwrap();
+    let mut expansion = KeyExpansion::<u32>::new(&key, 16).unwrap();
+    while expansion.step(8) > 0 {}
+    let rc5 = RC5::from_key_table(expansion.finish());
+
+    let expected = RC5::<u32>::from_secret_key(&key, 16).unwrap();
+    let pt = (0..32).collect::<Vec<u8>>();
+    assert_eq!(rc5.encode(&pt), expected.encode(&pt));
+    assert_eq!(rc5.decode(rc5.encode(&pt)), pt);
+
+    // Into a static buffer of firmware.
+    let mut buffer = [0u32; 34];
+    let mut expansion = KeyExpansion::new_in(&key, 16, BorrowedStorage(&mut buffer)).unwrap();
+    while expansion.step(8) > 0 {}
+    let rc5 = RC5::from_key_table(expansion.finish());
+    assert_eq!(rc5.encode(&pt), expected.encode(&pt));
+}
+
+#[test]
+fn key_table_storage() {
+    let key = SecretKey::new(vec![0x42; 16]).unwrap();
+    let expected = RC5::<u32>::from_secret_key(&key, 12).unwrap();
+    let pt = (0..32).collect::<Vec<u8>>();
+    let ct = expected.encode(&pt);
+
+    let array = ExpandedKeyTable::new_in(&key, 12, Zeroizing::new([0u32; 26])).unwrap();
+    let rc5 = RC5::from_key_table(array);
+    assert_eq!(rc5.encode(&pt), ct);
+    assert_eq!(rc5.clone().decode(&ct), pt);
+
+    // E.g. a static buffer of firmware.
+    let mut buffer = [0u32; 26];
+    {
+        let table = ExpandedKeyTable::new_in(&key, 12, BorrowedStorage(&mut buffer)).unwrap();
+        let rc5 = RC5::from_key_table(table);
+        assert_eq!(rc5.encode(&pt), ct);
+        assert_eq!(rc5.decode(&ct), pt);
+
+        let block = Block::new(1u32, 2);
+        assert_eq!(block.encode(&rc5).decode(&rc5).to_words(), block.to_words());
+    }
+    assert!(buffer.iter().all(|w| *w == 0));
+}
+
+#[test]
+fn scratch_space() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    let mut scratch = ScratchSpace::with_capacity(16);
+    let pt = (0..32).collect::<Vec<u8>>();
+
+    let ct = rc5.encode_with_scratch(&pt, &mut scratch).to_vec();
+    assert_eq!(ct, rc5.encode(&pt));
+    assert_eq!(rc5.decode_with_scratch(&ct, &mut scratch), &pt[..]);
+
+    let bufs = [IoSlice::new(&pt[..3]), IoSlice::new(&pt[3..])];
+    let ct = rc5
+        .encode_vectored_with_scratch(&bufs, &mut scratch)
+        .unwrap()
+        .to_vec();
+    assert_eq!(ct, rc5.encode(&pt));
+    let bufs = [IoSlice::new(&ct[..20]), IoSlice::new(&ct[20..])];
+    assert_eq!(
+        rc5.decode_vectored_with_scratch(&bufs, &mut scratch)
+            .unwrap(),
+        &pt[..]
+    );
+
+    assert!(matches!(
+        rc5.encode_vectored_with_scratch(&[IoSlice::new(&pt[..3])], &mut scratch),
+        Err(Error::InvalidInputLength { actual: 3, .. })
+    ));
+}
+
+/// Encrypts a message through `BlockMode` in two pieces and decrypts it again.
+fn assert_block_mode(mut encryptor: impl BlockMode, mut decryptor: impl BlockMode, split: usize) {
+    let pt = (0..48).collect::<Vec<u8>>();
+    let mut data = pt.clone();
+    let (first, second) = data.split_at_mut(split);
+    encryptor.encrypt(first).unwrap();
+    encryptor.encrypt(second).unwrap();
+    assert_ne!(data, pt);
+
+    decryptor.decrypt(&mut data).unwrap();
+    assert_eq!(data, pt);
+}
+
+#[test]
+fn block_modes() {
+    let rc5 = RC5::<u32>::new(vec![0x42; 16]).unwrap();
+    let iv = [7; 8];
+    let ecb = || modes::Ecb::new(rc5.clone());
+    let ctr = || modes::Ctr::new(rc5.clone(), 1);
+    let cfb = || modes::Cfb::new(rc5.clone(), &iv).unwrap();
+    let pcbc = || modes::Pcbc::new(rc5.clone(), &iv).unwrap();
+
+    assert_block_mode(ecb(), ecb(), 16);
+    assert_block_mode(ctr(), ctr(), 5);
+    assert_block_mode(cfb(), cfb(), 5);
+    assert_block_mode(pcbc(), pcbc(), 16);
+
+    // Each call of RC5-CTS is a separate message.
+    let mut cts = modes::Cts::new(rc5.clone(), &iv).unwrap();
+    let mut data = [7; 13];
+    BlockMode::encrypt(&mut cts, &mut data).unwrap();
+    BlockMode::decrypt(&mut cts, &mut data).unwrap();
+    assert_eq!(data, [7; 13]);
+}